@@ -406,6 +406,606 @@ fn console_write_stdout(message: &str, use_new_line: bool) {
   }
 }
 
+/// Utility function to write a [CLogLevel] tagged message to STDOUT.
+fn console_write_level(level: CLogLevel, message: &str) {
+  console_write_stdout(
+    &format!("[{}]: {}", level.as_string(), message),
+    true
+  );
+}
+
+/// The command names reserved by the [CRepl] for its built-in commands.
+const REPL_BUILT_INS: [&str; 4] = ["exit", "help", "history", "quit"];
+
+/// The task that runs when a [CReplCommand] is entered into the [CRepl].
+/// The Ok string is written to STDOUT (if not empty) and the Err string is
+/// written as a [CLogLevel::Error] message.
+pub type CReplCommandCB = fn(&CReplArgs) -> Result<String, String>;
+
+/// Identifies the different parameter types a [CReplCommand] can be
+/// configured to parse.
+enum CReplParam {
+  /// A positional argument that may or may not be required.
+  Arg { name: String, description: String, required: bool },
+  /// A --name flag that does not take a value.
+  Flag { name: String, description: String },
+  /// A --name value (or --name=value) option.
+  Option { name: String, description: String },
+}
+impl CReplParam {
+  /// Retrieves the name of the parameter.
+  fn name(&self) -> &str {
+    match self {
+      CReplParam::Arg { name, .. } => name,
+      CReplParam::Flag { name, .. } => name,
+      CReplParam::Option { name, .. } => name,
+    }
+  }
+
+  /// Retrieves the usage representation of the parameter.
+  fn usage(&self) -> String {
+    match self {
+      CReplParam::Arg { name, required, .. } => {
+        if *required {
+          format!("<{}>", name)
+        } else {
+          format!("[{}]", name)
+        }
+      },
+      CReplParam::Flag { name, .. } => format!("[--{}]", name),
+      CReplParam::Option { name, .. } => format!("[--{} <value>]", name),
+    }
+  }
+
+  /// Retrieves the description of the parameter.
+  fn description(&self) -> &str {
+    match self {
+      CReplParam::Arg { description, .. } => description,
+      CReplParam::Flag { description, .. } => description,
+      CReplParam::Option { description, .. } => description,
+    }
+  }
+}
+
+/// The parsed arguments of a [CReplCommand] handed to its [CReplCommandCB]
+/// when the command is entered into the [CRepl].
+#[derive(Debug)]
+pub struct CReplArgs {
+  command: String,
+  values: std::collections::HashMap<String, String>,
+  flags: Vec<String>,
+}
+impl CReplArgs {
+  /// The name of the command that was entered.
+  pub fn command(&self) -> &str {
+    &self.command
+  }
+
+  /// Retrieves the value of a positional argument or option. None is
+  /// returned if it was not entered.
+  pub fn get(&self, name: &str) -> Option<String> {
+    self.values.get(name).map(|v| v.to_string())
+  }
+
+  /// Determines if the specified flag was entered.
+  pub fn has_flag(&self, name: &str) -> bool {
+    self.flags.iter().any(|v| v == name)
+  }
+}
+
+/// Defines a command registered with the [CRepl] via the
+/// [CRepl::command] function. The positional arguments, options, and flags
+/// added to the command are parsed from the entered line and are the basis
+/// of the generated help.
+pub struct CReplCommand {
+  name: String,
+  description: String,
+  params: Vec<CReplParam>,
+  handler: CReplCommandCB,
+}
+impl CReplCommand {
+  /// Creates a new [CReplCommand] with the name typed to execute it, a
+  /// description for the generated help, and the [CReplCommandCB] to run.
+  pub fn new(
+    name: &str,
+    description: &str,
+    handler: CReplCommandCB
+  ) -> CReplCommand {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+      panic!("SyntaxError: CReplCommand name '{}' is not valid.", name);
+    }
+    CReplCommand {
+      name: name.to_string(),
+      description: description.to_string(),
+      params: Vec::new(),
+      handler,
+    }
+  }
+
+  /// Adds a positional argument to the command. Positional arguments are
+  /// parsed in the order they are added. A required argument can't follow
+  /// an optional one.
+  pub fn arg(
+    self,
+    name: &str,
+    description: &str,
+    required: bool
+  ) -> CReplCommand {
+    let follows_optional = self.params.iter().any(|p| matches!(
+      p,
+      CReplParam::Arg { required: false, .. }
+    ));
+    if required && follows_optional {
+      panic!(
+        "SyntaxError: CReplCommand '{}' required arg '{}' follows an \
+        optional arg.",
+        self.name,
+        name
+      );
+    }
+    self.add_param(CReplParam::Arg {
+      name: name.to_string(),
+      description: description.to_string(),
+      required,
+    })
+  }
+
+  /// Adds a --name flag to the command that does not take a value.
+  pub fn flag(self, name: &str, description: &str) -> CReplCommand {
+    self.add_param(CReplParam::Flag {
+      name: name.to_string(),
+      description: description.to_string(),
+    })
+  }
+
+  /// Adds a --name option to the command that takes a value via either
+  /// --name value or --name=value.
+  pub fn option(self, name: &str, description: &str) -> CReplCommand {
+    self.add_param(CReplParam::Option {
+      name: name.to_string(),
+      description: description.to_string(),
+    })
+  }
+
+  /// Helper function to add a parameter ensuring names are unique.
+  fn add_param(mut self, param: CReplParam) -> CReplCommand {
+    if self.params.iter().any(|p| p.name() == param.name()) {
+      panic!(
+        "SyntaxError: CReplCommand '{}' already has a '{}' parameter.",
+        self.name,
+        param.name()
+      );
+    }
+    self.params.push(param);
+    self
+  }
+
+  /// The names of the options and flags for tab completion.
+  fn option_names(&self) -> Vec<String> {
+    self.params.iter().filter_map(|p| match p {
+      CReplParam::Arg { .. } => None,
+      _ => Some(format!("--{}", p.name())),
+    }).collect()
+  }
+
+  /// Builds the usage line of the command.
+  fn usage(&self) -> String {
+    let mut usage = self.name.to_string();
+    for param in &self.params {
+      usage.push(' ');
+      usage.push_str(&param.usage());
+    }
+    usage
+  }
+
+  /// Builds the detailed help of the command.
+  fn help(&self) -> String {
+    let mut help = format!("{}\n  {}\n", self.usage(), self.description);
+    for param in &self.params {
+      help.push_str(&format!(
+        "    {:<20} {}\n",
+        param.usage(),
+        param.description()
+      ));
+    }
+    help
+  }
+
+  /// Parses the tokens following the command name into [CReplArgs].
+  fn parse(&self, tokens: &[String]) -> Result<CReplArgs, String> {
+    let mut args = CReplArgs {
+      command: self.name.to_string(),
+      values: std::collections::HashMap::new(),
+      flags: Vec::new(),
+    };
+    let positional: Vec<&CReplParam> = self.params.iter()
+      .filter(|p| matches!(p, CReplParam::Arg { .. }))
+      .collect();
+    let mut position = 0;
+    let mut x = 0;
+    while x < tokens.len() {
+      let token = &tokens[x];
+      if let Some(option) = token.strip_prefix("--") {
+        let (name, inline_value) = match option.split_once('=') {
+          Some((n, v)) => (n, Some(v.to_string())),
+          None => (option, None),
+        };
+        match self.params.iter().find(|p| p.name() == name) {
+          Some(CReplParam::Flag { .. }) => {
+            if inline_value.is_some() {
+              return Err(format!("--{} does not take a value", name));
+            }
+            args.flags.push(name.to_string());
+          },
+          Some(CReplParam::Option { .. }) => {
+            let value = match inline_value {
+              Some(v) => v,
+              None => {
+                x += 1;
+                match tokens.get(x) {
+                  Some(v) => v.to_string(),
+                  None => return Err(format!("--{} requires a value", name)),
+                }
+              },
+            };
+            args.values.insert(name.to_string(), value);
+          },
+          _ => return Err(format!("unknown option --{}", name)),
+        }
+      } else {
+        match positional.get(position) {
+          Some(param) => {
+            args.values.insert(param.name().to_string(), token.to_string());
+            position += 1;
+          },
+          None => return Err(format!("unexpected argument '{}'", token)),
+        }
+      }
+      x += 1;
+    }
+
+    // Make sure all the required arguments were entered.
+    for param in positional.iter().skip(position) {
+      if let CReplParam::Arg { name, required: true, .. } = param {
+        return Err(format!("missing required argument <{}>", name));
+      }
+    }
+    Ok(args)
+  }
+}
+
+/// Supports the [CRepl::run] with tab completion of the registered command
+/// names and their options.
+struct CReplHelper {
+  commands: Vec<(String, Vec<String>)>,
+}
+impl rustyline::completion::Completer for CReplHelper {
+  type Candidate = String;
+
+  fn complete(
+    &self,
+    line: &str,
+    pos: usize,
+    _ctx: &rustyline::Context<'_>
+  ) -> rustyline::Result<(usize, Vec<String>)> {
+    let before = &line[..pos];
+    let start = match before.rfind(char::is_whitespace) {
+      Some(v) => v + 1,
+      None => 0,
+    };
+    let word = &before[start..];
+    let command = before.split_whitespace().next().unwrap_or("");
+    let candidates: Vec<String> = if before[..start].trim().is_empty() {
+      // Still on the first word, complete the command name.
+      self.commands.iter()
+        .map(|(name, _)| name.to_string())
+        .filter(|name| name.starts_with(word))
+        .collect()
+    } else if command == "help" {
+      self.commands.iter()
+        .map(|(name, _)| name.to_string())
+        .filter(|name| name.starts_with(word))
+        .collect()
+    } else {
+      match self.commands.iter().find(|(name, _)| name == command) {
+        Some((_, options)) => options.iter()
+          .filter(|option| option.starts_with(word))
+          .map(|option| option.to_string())
+          .collect(),
+        None => Vec::new(),
+      }
+    };
+    Ok((start, candidates))
+  }
+}
+impl rustyline::hint::Hinter for CReplHelper {
+  type Hint = String;
+}
+impl rustyline::highlight::Highlighter for CReplHelper {}
+impl rustyline::validate::Validator for CReplHelper {}
+impl rustyline::Helper for CReplHelper {}
+
+/// The result of a [console_repl] call. This provides an interactive
+/// Read-Eval-Print-Loop (REPL) of registered [CReplCommand] items with line
+/// editing, tab completion, generated help, and command history that can
+/// be persisted via the storage use case. The built-in commands are
+/// help [command], history, and exit / quit.
+pub struct CRepl {
+  prompt: String,
+  commands: Vec<CReplCommand>,
+  history: Vec<String>,
+  history_key: Option<String>,
+  history_size: usize,
+}
+impl CRepl {
+  /// Private constructor to support the [console_repl] function.
+  fn new(prompt: &str) -> CRepl {
+    CRepl {
+      prompt: prompt.to_string(),
+      commands: Vec::new(),
+      history: Vec::new(),
+      history_key: None,
+      history_size: 100,
+    }
+  }
+
+  /// Registers a [CReplCommand] with the REPL. Will panic if the command
+  /// name is already registered or is one of the built-in commands.
+  pub fn command(mut self, command: CReplCommand) -> CRepl {
+    let exists = self.commands.iter().any(|c| c.name == command.name);
+    if exists || REPL_BUILT_INS.contains(&command.name.as_str()) {
+      panic!(
+        "SyntaxError: CRepl command '{}' is already registered.",
+        command.name
+      );
+    }
+    self.commands.push(command);
+    self.commands.sort_by(|a, b| a.name.cmp(&b.name));
+    self
+  }
+
+  /// Persists the entered command history via [storage_set] under the
+  /// specified storage key keeping the last max_size entries.
+  pub fn history(mut self, storage_key: &str, max_size: usize) -> CRepl {
+    self.history_key = Some(storage_key.to_string());
+    self.history_size = max_size;
+    self
+  }
+
+  /// Generates the help listing all the commands or the detailed help of
+  /// the specified command. None is returned if the command is not found.
+  pub fn help(&self, command: Option<&str>) -> Option<String> {
+    match command {
+      Some(name) => self.commands.iter()
+        .find(|c| c.name == name)
+        .map(|c| c.help()),
+      None => {
+        let mut help = String::from("COMMANDS:\n");
+        for c in &self.commands {
+          help.push_str(&format!("  {:<20} {}\n", c.name, c.description));
+        }
+        help.push_str(&format!(
+          "  {:<20} {}\n",
+          "help [command]",
+          "Displays this help or the help of a command."
+        ));
+        help.push_str(&format!(
+          "  {:<20} {}\n",
+          "history",
+          "Displays the entered command history."
+        ));
+        help.push_str(&format!(
+          "  {:<20} {}\n",
+          "exit / quit",
+          "Exits the REPL."
+        ));
+        Some(help)
+      },
+    }
+  }
+
+  /// Parses and executes a single line as if it were entered into the
+  /// REPL writing any output to STDOUT. Returns false if the line was an
+  /// exit / quit command.
+  pub fn execute(&mut self, line: &str) -> bool {
+    let tokens = match CRepl::tokenize(line) {
+      Ok(v) => v,
+      Err(why) => {
+        console_write_level(CLogLevel::Warning, &why);
+        return true;
+      },
+    };
+    if tokens.is_empty() {
+      return true;
+    }
+
+    let name = tokens[0].as_str();
+    match name {
+      "exit" | "quit" => return false,
+      "help" => {
+        match self.help(tokens.get(1).map(|v| v.as_str())) {
+          Some(v) => console_write(&v),
+          None => console_write_level(
+            CLogLevel::Warning,
+            &format!("unknown command '{}'", tokens[1])
+          ),
+        }
+      },
+      "history" => {
+        for (x, entry) in self.history.iter().enumerate() {
+          console_writeln(&format!("{:>4}  {}", x + 1, entry));
+        }
+      },
+      _ => {
+        match self.commands.iter().find(|c| c.name == name) {
+          Some(command) => {
+            match command.parse(&tokens[1..]) {
+              Ok(args) => match (command.handler)(&args) {
+                Ok(v) => {
+                  if !v.is_empty() {
+                    console_writeln(&v);
+                  }
+                },
+                Err(why) => console_write_level(CLogLevel::Error, &why),
+              },
+              Err(why) => {
+                console_write_level(CLogLevel::Warning, &why);
+                console_writeln(&format!("usage: {}", command.usage()));
+              },
+            }
+          },
+          None => console_write_level(
+            CLogLevel::Warning,
+            &format!("unknown command '{}', type help for commands", name)
+          ),
+        }
+      },
+    }
+    true
+  }
+
+  /// Runs the REPL reading lines from STDIN until an exit / quit command,
+  /// CTRL+D, or the end of STDIN is reached. CTRL+C clears the line being
+  /// edited. Line editing is not available when STDIN is not a terminal.
+  pub fn run(&mut self) {
+    self.load_history();
+    let config = rustyline::Config::builder()
+      .auto_add_history(false)
+      .max_history_size(self.history_size)
+      .unwrap_or_else(|_| rustyline::Config::builder())
+      .build();
+    let editor = rustyline::Editor::<
+      CReplHelper,
+      rustyline::history::DefaultHistory
+    >::with_config(config);
+    match editor {
+      Ok(mut editor) => {
+        let mut commands: Vec<(String, Vec<String>)> = self.commands.iter()
+          .map(|c| (c.name.to_string(), c.option_names()))
+          .collect();
+        for name in REPL_BUILT_INS {
+          commands.push((name.to_string(), Vec::new()));
+        }
+        editor.set_helper(Some(CReplHelper { commands }));
+        for entry in &self.history {
+          let _ = editor.add_history_entry(entry.as_str());
+        }
+        loop {
+          match editor.readline(&self.prompt) {
+            Ok(line) => {
+              if !line.trim().is_empty() {
+                let _ = editor.add_history_entry(line.as_str());
+                self.save_history(&line);
+              }
+              if !self.execute(&line) {
+                break;
+              }
+            },
+            Err(rustyline::error::ReadlineError::Interrupted) => continue,
+            Err(rustyline::error::ReadlineError::Eof) => break,
+            Err(why) => {
+              console_write_level(CLogLevel::Error, &why.to_string());
+              break;
+            },
+          }
+        }
+      },
+      // No terminal to edit lines with, fall back to reading STDIN.
+      Err(_) => loop {
+        let mut line = String::new();
+        console_write(&self.prompt);
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+        match std::io::stdin().read_line(&mut line) {
+          Ok(0) | Err(_) => break,
+          Ok(_) => {
+            let line = line.trim().to_string();
+            if !line.is_empty() {
+              self.save_history(&line);
+            }
+            if !self.execute(&line) {
+              break;
+            }
+          },
+        }
+      },
+    }
+  }
+
+  /// Loads the persisted history from the module storage if configured.
+  fn load_history(&mut self) {
+    if let Some(key) = &self.history_key {
+      storage_init();
+      let entries = storage_get(key).and_then(|v| json_parse(&v));
+      if let Some(entries) = entries {
+        self.history = entries.members()
+          .filter_map(|v| v.as_str().map(|v| v.to_string()))
+          .collect();
+      }
+    }
+  }
+
+  /// Adds an entry to the history and persists it to the module storage
+  /// if configured.
+  fn save_history(&mut self, line: &str) {
+    self.history.push(line.trim().to_string());
+    if self.history.len() > self.history_size {
+      let overflow = self.history.len() - self.history_size;
+      self.history.drain(0..overflow);
+    }
+    if let Some(key) = &self.history_key {
+      let mut entries = json_create_array();
+      for entry in &self.history {
+        let _ = entries.push(entry.as_str());
+      }
+      storage_set(key, &json_stringify(entries));
+    }
+  }
+
+  /// Splits a line into tokens honoring single / double quotes and
+  /// backslash escapes.
+  fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_token = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+      match (quote, c) {
+        (_, '\\') if quote != Some('\'') => {
+          match chars.next() {
+            Some(v) => token.push(v),
+            None => return Err(String::from("line ends with an escape")),
+          }
+          in_token = true;
+        },
+        (Some(q), c) if c == q => quote = None,
+        (Some(_), c) => token.push(c),
+        (None, '"') | (None, '\'') => {
+          quote = Some(c);
+          in_token = true;
+        },
+        (None, c) if c.is_whitespace() => {
+          if in_token {
+            tokens.push(std::mem::take(&mut token));
+            in_token = false;
+          }
+        },
+        (None, c) => {
+          token.push(c);
+          in_token = true;
+        },
+      }
+    }
+    if quote.is_some() {
+      return Err(String::from("line has an unterminated quote"));
+    }
+    if in_token {
+      tokens.push(token);
+    }
+    Ok(tokens)
+  }
+}
+
 /// Puts out an alert to STDOUT awaiting for the user to press the ENTER
 /// key.
 ///
@@ -529,6 +1129,48 @@ pub fn console_prompt(message: &str) -> String {
   String::from(answer)
 }
 
+/// Creates a [CRepl] with the specified prompt to build an interactive
+/// command shell from registered [CReplCommand] items. Call [CRepl::run]
+/// to start reading commands from STDIN or [CRepl::execute] to process a
+/// single line.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CReplArgs, CReplCommand};
+///
+/// fn add_cb(args: &CReplArgs) -> Result<String, String> {
+///   let a = args.get("a").unwrap().parse::<i32>().map_err(|e| e.to_string())?;
+///   let b = args.get("b").unwrap().parse::<i32>().map_err(|e| e.to_string())?;
+///   match args.has_flag("negate") {
+///     true => Ok(format!("{}", -(a + b))),
+///     false => Ok(format!("{}", a + b)),
+///   }
+/// }
+///
+/// let mut repl = codemelted::console_repl("device> ")
+///   .command(
+///     CReplCommand::new("add", "Adds two numbers.", add_cb)
+///       .arg("a", "The first number.", true)
+///       .arg("b", "The second number.", true)
+///       .flag("negate", "Negates the answer.")
+///   );
+/// assert!(repl.help(None).unwrap().contains("add"));
+/// assert!(repl.help(Some("add")).unwrap().contains("add <a> <b>"));
+/// assert!(repl.execute("add 1 2 --negate"));
+/// assert!(!repl.execute("exit"));
+/// ```
+///
+/// **Example (Interactive With Persisted History):**
+/// ```no_run
+/// let mut repl = codemelted::console_repl("device> ")
+///   .history("device_console_history", 500);
+/// repl.run();
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_console.mmd")]
+pub fn console_repl(prompt: &str) -> CRepl {
+  CRepl::new(prompt)
+}
+
 /// Will put a string to STDOUT without the new line character.
 ///
 /// **Example:**
@@ -555,532 +1197,9292 @@ pub fn console_writeln(message: &str) {
 // [DB UC IMPLEMENTATION] =====================================================
 // ============================================================================
 
-/// Ensures a database exists as any of the [db_query] and [db_update]
-/// functions will panic if the specified database does not exist. So this
-/// will aid in ensuring expected configurations.
-///
-/// **Example:**
-/// ```no_run
-/// let db_file = format!("{}/test.db", codemelted::runtime_temp_path());
-/// assert!(!codemelted::db_exists(&db_file, false));
-/// ```
-#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
-pub fn db_exists(db_path: &str, should_panic: bool) -> bool {
-  match disk_exists(db_path, CDiskType::File) {
-    true => true,
-    false => {
-      if should_panic {
-        panic!("SyntaxError: {} database does not exist!", db_path);
-      }
-      false
-    },
+/// Mutex to hold the encryption keys registered via the [db_key] function
+/// by database path.
+static DB_KEYS: std::sync::Mutex<Vec<(String, String)>> =
+  std::sync::Mutex::new(Vec::new());
+
+/// Identifies a PRAGMA applied to every connection opened by a [CDatabase]
+/// via the [db_open] function.
+#[derive(Clone, Debug)]
+pub enum CDbPragma {
+  /// Milliseconds a connection waits on a locked database before failing
+  /// with a busy error.
+  BusyTimeout(u64),
+  /// Enables (true) / disables (false) foreign key constraint enforcement.
+  ForeignKeys(bool),
+  /// Sets the journal_mode to WAL (true) allowing readers and a writer to
+  /// run concurrently or back to DELETE (false).
+  WalMode(bool),
+  /// Any other PRAGMA name / value pair. The value is not escaped.
+  Custom(String, String),
+}
+impl CDbPragma {
+  /// Applies the PRAGMA to the specified connection.
+  fn apply(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    match self {
+      CDbPragma::BusyTimeout(v) => {
+        conn.busy_timeout(std::time::Duration::from_millis(*v))
+      },
+      CDbPragma::ForeignKeys(v) => {
+        conn.pragma_update(None, "foreign_keys", v)
+      },
+      CDbPragma::WalMode(v) => {
+        let mode = if *v { "WAL" } else { "DELETE" };
+        conn.execute_batch(&format!("PRAGMA journal_mode = {};", mode))
+      },
+      CDbPragma::Custom(name, value) => {
+        conn.execute_batch(&format!("PRAGMA {} = {};", name, value))
+      },
+    }
   }
 }
 
-/// Manages the sqlite3 database on disk by creating the initial database
-/// if it does not already exist along with execute Data Definition Language
-/// SQL statements to manage the database.
-///
-/// **Example:**
-/// ```no_run
-/// let db_file = format!("{}/test.db", codemelted::runtime_temp_path());
-/// assert!(!codemelted::db_exists(&db_file, false));
-/// codemelted::db_manage(&db_file, true, None);
-/// assert!(codemelted::db_exists(&db_file, false));
-/// let _ = codemelted::disk_rm(&db_file);
-/// ```
-#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
-pub fn db_manage(db_path: &str, create_db: bool, sql: Option<&str>) {
-  // If not creating the database on connection, check to make sure
-  // it is where we expect.
-  if !create_db {
-    db_exists(db_path, true);
+/// Identifies the kind of [CDbChangeEvent] delivered to the subscribers of
+/// a [CDatabase].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDbChangeOperation {
+  /// A row was inserted.
+  Insert,
+  /// A row was updated.
+  Update,
+  /// A row was deleted.
+  Delete,
+  /// The transaction holding the preceding row changes was committed.
+  Commit,
+  /// A transaction was rolled back discarding its row changes.
+  Rollback,
+}
+
+/// A change made to a [CDatabase] delivered to its subscribers. The
+/// Insert / Update / Delete row events of a transaction are delivered
+/// once it commits followed by a Commit event. Commit and Rollback events
+/// carry an empty table and a rowid of 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CDbChangeEvent {
+  operation: CDbChangeOperation,
+  table: String,
+  rowid: i64,
+}
+impl CDbChangeEvent {
+  /// Private constructor supporting the connection hooks.
+  fn new(
+    operation: CDbChangeOperation,
+    table: &str,
+    rowid: i64
+  ) -> CDbChangeEvent {
+    CDbChangeEvent { operation, table: table.to_string(), rowid }
+  }
+
+  /// The kind of change.
+  pub fn operation(&self) -> CDbChangeOperation {
+    self.operation
+  }
+
+  /// The rowid of the changed row.
+  pub fn rowid(&self) -> i64 {
+    self.rowid
+  }
+
+  /// The name of the changed table.
+  pub fn table(&self) -> String {
+    self.table.to_string()
+  }
+}
+
+/// Callback receiving the [CDbChangeEvent] items of a [CDatabase] via the
+/// [CDatabase::subscribe] function. It runs on the thread that committed the
+/// change once the connection is returned to the pool.
+pub type CDbChangeCB = fn(CDbChangeEvent);
+
+/// The row changes captured by a connection's hooks. The pending changes
+/// of the open transaction move to the held changes when it commits and
+/// are published once the connection is out of its transaction, confirming
+/// the commit succeeded. The held changes from the committing index on are
+/// dropped when the commit fails and the transaction rolls back.
+#[derive(Default)]
+struct CDbChangeState {
+  pending: Vec<CDbChangeEvent>,
+  held: Vec<CDbChangeEvent>,
+  committing: Option<usize>,
+}
+
+/// The [CDbChangeState] shared by a connection with its hooks.
+type CDbChanges = std::sync::Arc<std::sync::Mutex<CDbChangeState>>;
+
+/// A consumer of the [CDbChangeEvent] items of a [CDatabase].
+#[derive(Clone)]
+enum CDbSubscriber {
+  Callback(CDbChangeCB),
+  Channel(std::sync::mpsc::Sender<CDbChangeEvent>),
+}
+
+/// Holds the subscribers of a [CDatabase] along with the next subscription
+/// id. Shared with the hooks of every pooled connection.
+struct CDbChangeHub {
+  subscribers: std::sync::Mutex<(usize, Vec<(usize, CDbSubscriber)>)>,
+}
+impl CDbChangeHub {
+  /// Adds the subscriber returning its subscription id.
+  fn add(&self, subscriber: CDbSubscriber) -> usize {
+    let mut subscribers = self.subscribers.lock().unwrap();
+    subscribers.0 += 1;
+    let id = subscribers.0;
+    subscribers.1.push((id, subscriber));
+    id
+  }
+
+  /// Delivers the events to each subscriber dropping the channels whose
+  /// receiver is gone. Delivery happens outside of the lock so a callback
+  /// can subscribe / unsubscribe.
+  fn publish(&self, events: Vec<CDbChangeEvent>) {
+    let subscribers = self.subscribers.lock().unwrap().1.clone();
+    for (id, subscriber) in subscribers {
+      for event in &events {
+        match &subscriber {
+          CDbSubscriber::Callback(handler) => handler(event.clone()),
+          CDbSubscriber::Channel(tx) => {
+            if tx.send(event.clone()).is_err() {
+              self.remove(id);
+              break;
+            }
+          },
+        }
+      }
+    }
   }
 
-  // Alrighty, time to go carry out the DDL statement and panic if
-  // something about it fails.
-  match rusqlite::Connection::open(db_path) {
-    Ok(conn) => {
-      match sql {
-        // Welp, we were asked to perform the DDL statement. Go do it
-        // and see what happens.
-        Some(stmt) => {
-          match conn.execute(stmt, ()) {
-            Ok(_) => {},
-            Err(why) => panic!("SyntaxError: db_manage {}", why),
-          }
-        },
-        // The sql was None so it may have been to just create the
-        // database.
-        None => {},
-      }
-    },
-    Err(why) => panic!("SyntaxError: db_manage {}", why),
-  };
+  /// Removes the subscriber with the specified subscription id.
+  fn remove(&self, id: usize) {
+    self.subscribers.lock().unwrap().1.retain(|v| v.0 != id);
+  }
 }
 
-/// Will query a table specified by the db_path to retrieve the data
-/// as a vector of the given type. This represents the Data Query
-/// Language (DQL) SELECT statement.
-///
-/// **Example:**
-/// ```
-/// // Create the database file and table.
-/// let db_file = format!("{}/test.db", codemelted::runtime_temp_path());
-/// assert!(!codemelted::db_exists(&db_file, false));
-/// let sql = "CREATE TABLE person (
-///   id    INTEGER PRIMARY KEY,
-///   name  TEXT NOT NULL,
-///   data  BLOB
-/// )";
-/// codemelted::db_manage(&db_file, true, Some(sql));
-/// assert!(codemelted::db_exists(&db_file, false));
-///
-/// // Go add a person to the database.
-/// #[derive(Debug)]
-/// struct Person {
-///   id: i32,
-///   name: String,
-///   data: Option<Vec<u8>>,
-/// }
-///
-/// let sql = "INSERT INTO person (name, data) VALUES (?1, ?2)";
-/// let me = Person {
-///    id: 0,
-///    name: "Steven".to_string(),
-///    data: None,
-/// };
-/// let rows = codemelted::db_update(
+/// The result of a [CDatabase::subscribe_protocol] call queuing the
+/// [CDbChangeEvent] items of the database for retrieval via the
+/// [CProtocolHandler::get_message] function. Messages can't be posted to it
+/// and terminating it (or dropping it) ends the subscription.
+pub struct CDbChangeProtocol {
+  id: String,
+  subscription: usize,
+  hub: std::sync::Arc<CDbChangeHub>,
+  protocol_rx: std::sync::mpsc::Receiver<CDbChangeEvent>,
+  running: bool,
+}
+impl CProtocolHandler<Option<CDbChangeEvent>> for CDbChangeProtocol {
+  fn id(&mut self) -> String {
+    self.id.to_string()
+  }
+
+  fn get_message(
+    &mut self,
+    _request: Option<&str>
+  ) -> Result<Option<CDbChangeEvent>, std::io::Error> {
+    match self.protocol_rx.try_recv() {
+      Ok(v) => Ok(Some(v)),
+      Err(std::sync::mpsc::TryRecvError::Empty) => Ok(None),
+      Err(why) => Err(std::io::Error::new(
+        std::io::ErrorKind::BrokenPipe,
+        why.to_string()
+      )),
+    }
+  }
+
+  fn is_running(&self) -> bool {
+    self.running
+  }
+
+  fn post_message(
+    &mut self,
+    _data: Option<CDbChangeEvent>
+  ) -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(
+      std::io::ErrorKind::Unsupported,
+      "database change notifications are receive only"
+    ))
+  }
+
+  fn terminate(&mut self) {
+    self.hub.remove(self.subscription);
+    self.running = false;
+  }
+}
+impl Drop for CDbChangeProtocol {
+  fn drop(&mut self) {
+    self.terminate();
+  }
+}
+
+/// The shared state of a [CDatabase] holding the idle connections of the
+/// pool and the count of connections opened.
+struct CDatabasePool {
+  db_path: String,
+  flags: rusqlite::OpenFlags,
+  pragmas: Vec<CDbPragma>,
+  max_size: usize,
+  connections: std::sync::Mutex<
+    (Vec<(rusqlite::Connection, CDbChanges)>, usize)
+  >,
+  available: std::sync::Condvar,
+  hub: std::sync::Arc<CDbChangeHub>,
+}
+
+/// The result of a [db_open] call holding a pool of persistent connections
+/// to a sqlite database with the [CDbPragma] items applied to each
+/// connection. The handle is cheap to clone and can be shared across
+/// threads (like those of [async_worker]). Calls block waiting on an idle
+/// connection when all of the pool is in use.
+#[derive(Clone)]
+pub struct CDatabase {
+  pool: std::sync::Arc<CDatabasePool>,
+}
+impl CDatabase {
+  /// Private constructor to support the [db_open] function. Opens the first
+  /// connection to validate the database and PRAGMA settings.
+  fn new(
+    db_path: &str,
+    create_db: bool,
+    pool_size: usize,
+    pragmas: &[CDbPragma]
+  ) -> Result<CDatabase, rusqlite::Error> {
+    let mut flags = rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE
+      | rusqlite::OpenFlags::SQLITE_OPEN_URI
+      | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    if create_db {
+      flags |= rusqlite::OpenFlags::SQLITE_OPEN_CREATE;
+    }
+
+    // Each connection to a private in-memory database gets its own.
+    let private = db_is_memory(db_path) && !db_path.contains("cache=shared");
+    let pool = CDatabasePool {
+      db_path: db_path.to_string(),
+      flags,
+      pragmas: pragmas.to_vec(),
+      max_size: if private { 1 } else { pool_size.max(1) },
+      connections: std::sync::Mutex::new((Vec::new(), 0)),
+      available: std::sync::Condvar::new(),
+      hub: std::sync::Arc::new(CDbChangeHub {
+        subscribers: std::sync::Mutex::new((0, Vec::new())),
+      }),
+    };
+    let conn = CDatabase::open_connection(&pool)?;
+    {
+      let mut connections = pool.connections.lock().unwrap();
+      connections.0.push(conn);
+      connections.1 = 1;
+    }
+    Ok(CDatabase { pool: std::sync::Arc::new(pool) })
+  }
+
+  /// Opens a new connection applying the configured [CDbPragma] items and
+  /// installing the hooks that deliver its changes to the subscribers.
+  fn open_connection(
+    pool: &CDatabasePool
+  ) -> Result<(rusqlite::Connection, CDbChanges), rusqlite::Error> {
+    let conn = rusqlite::Connection::open_with_flags(
+      &pool.db_path,
+      pool.flags
+    )?;
+    db_apply_key(&conn, &pool.db_path)?;
+    for pragma in &pool.pragmas {
+      pragma.apply(&conn)?;
+    }
+
+    // Hold the row changes until we know the fate of the transaction.
+    let changes = CDbChanges::default();
+    let pending = changes.clone();
+    conn.update_hook(Some(
+      move |action, _db: &str, table: &str, rowid| {
+        let operation = match action {
+          rusqlite::hooks::Action::SQLITE_INSERT => CDbChangeOperation::Insert,
+          rusqlite::hooks::Action::SQLITE_UPDATE => CDbChangeOperation::Update,
+          rusqlite::hooks::Action::SQLITE_DELETE => CDbChangeOperation::Delete,
+          _ => return,
+        };
+        pending.lock().unwrap().pending.push(
+          CDbChangeEvent::new(operation, table, rowid)
+        );
+      }
+    ));
+    // The commit may still fail after this hook so only hold the changes.
+    // They are published once the connection is returned to the pool.
+    let pending = changes.clone();
+    conn.commit_hook(Some(move || {
+      let mut state = pending.lock().unwrap();
+      if !state.pending.is_empty() {
+        if state.committing.is_none() {
+          state.committing = Some(state.held.len());
+        }
+        let events = std::mem::take(&mut state.pending);
+        state.held.extend(events);
+        state.held.push(
+          CDbChangeEvent::new(CDbChangeOperation::Commit, "", 0)
+        );
+      }
+      false
+    }));
+    let pending = changes.clone();
+    conn.rollback_hook(Some(move || {
+      let mut state = pending.lock().unwrap();
+      let discarded = !state.pending.is_empty() || state.committing.is_some();
+      state.pending.clear();
+      if let Some(mark) = state.committing.take() {
+        state.held.truncate(mark);
+      }
+      if discarded {
+        state.held.push(
+          CDbChangeEvent::new(CDbChangeOperation::Rollback, "", 0)
+        );
+      }
+    }));
+    Ok((conn, changes))
+  }
+
+  /// Retrieves the path of the database the pool is connected to.
+  pub fn db_path(&self) -> String {
+    self.pool.db_path.to_string()
+  }
+
+  /// The maximum number of connections the pool will open.
+  pub fn pool_size(&self) -> usize {
+    self.pool.max_size
+  }
+
+  /// Checks out a [CDbConnection] from the pool opening a new connection
+  /// if the pool is not yet full. Blocks until a connection is returned to
+  /// the pool if all of them are in use.
+  pub fn connection(&self) -> Result<CDbConnection, rusqlite::Error> {
+    let mut connections = self.pool.connections.lock().unwrap();
+    loop {
+      if let Some(conn) = connections.0.pop() {
+        return Ok(CDbConnection::new(self, conn));
+      }
+      if connections.1 < self.pool.max_size {
+        // Reserve the slot and open the connection outside the lock.
+        connections.1 += 1;
+        drop(connections);
+        return match CDatabase::open_connection(&self.pool) {
+          Ok(conn) => Ok(CDbConnection::new(self, conn)),
+          Err(why) => {
+            self.pool.connections.lock().unwrap().1 -= 1;
+            self.pool.available.notify_one();
+            Err(why)
+          },
+        };
+      }
+      connections = self.pool.available.wait(connections).unwrap();
+    }
+  }
+
+  /// Executes a SQL script of one or more statements within a single
+  /// transaction. Either all of the statements are applied or none of them
+  /// are. The script must not manage its own transaction.
+  pub fn batch(&self, sql: &str) -> Result<(), rusqlite::Error> {
+    self.transaction(|tx| tx.manage(sql))
+  }
+
+  /// Executes one or more Data Definition Language SQL statements via a
+  /// pooled connection. See [db_manage] for details.
+  pub fn manage(&self, sql: &str) -> Result<(), rusqlite::Error> {
+    let conn = self.connection()?;
+    conn.execute_batch(sql)
+  }
+
+  /// Queries the database via a pooled connection mapping each row via the
+  /// specified function. See [db_query] for details.
+  pub fn query<
+    P: rusqlite::Params,
+    T,
+    F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>
+  >(
+    &self,
+    sql: &str,
+    params: P,
+    f: F
+  ) -> Result<Vec<T>, rusqlite::Error> {
+    let conn = self.connection()?;
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params, f)?;
+    rows.collect()
+  }
+
+  /// Queries the database via a pooled connection building a [CDbRowMapper]
+  /// struct from each row. See [db_query_as] for details.
+  pub fn query_as<P: rusqlite::Params, T: CDbRowMapper>(
+    &self,
+    sql: &str,
+    params: P
+  ) -> Result<Vec<T>, rusqlite::Error> {
+    self.query(sql, params, T::from_row)
+  }
+
+  /// Queries the database via a pooled connection returning the column
+  /// keyed rows. See [db_query_object] for details.
+  pub fn query_object<P: rusqlite::Params>(
+    &self,
+    sql: &str,
+    params: P
+  ) -> Result<CDbResultSet, rusqlite::Error> {
+    let conn = self.connection()?;
+    CDbResultSet::new(&conn, sql, params)
+  }
+
+  /// Subscribes the callback to the row changes committed via any of the
+  /// pooled connections returning the subscription id to later
+  /// [CDatabase::unsubscribe]. Changes made outside of this [CDatabase]
+  /// (i.e. via [db_update] or another process) are not seen.
+  pub fn subscribe(&self, handler: CDbChangeCB) -> usize {
+    self.pool.hub.add(CDbSubscriber::Callback(handler))
+  }
+
+  /// Subscribes a [CDbChangeProtocol] to the row changes committed via any
+  /// of the pooled connections. The events queue until retrieved via its
+  /// [CProtocolHandler::get_message] function.
+  pub fn subscribe_protocol(&self, id: &str) -> CDbChangeProtocol {
+    let (protocol_tx, protocol_rx) = std::sync::mpsc::channel();
+    let subscription = self.pool.hub.add(CDbSubscriber::Channel(protocol_tx));
+    CDbChangeProtocol {
+      id: id.to_string(),
+      subscription,
+      hub: self.pool.hub.clone(),
+      protocol_rx,
+      running: true,
+    }
+  }
+
+  /// Runs the specified function within a transaction on a pooled
+  /// connection. The transaction is committed when the function returns Ok
+  /// and rolled back when it returns an Err (or panics). Nest
+  /// [CDbTransaction::savepoint] calls to partially roll back work.
+  pub fn transaction<T, F>(&self, f: F) -> Result<T, rusqlite::Error>
+  where
+    F: FnOnce(&CDbTransaction) -> Result<T, rusqlite::Error>
+  {
+    let conn = self.connection()?;
+    conn.execute_batch("BEGIN IMMEDIATE;")?;
+    let tx = CDbTransaction {
+      conn: &conn,
+      changes: conn.changes(),
+      depth: 0
+    };
+    match f(&tx) {
+      Ok(v) => match conn.execute_batch("COMMIT;") {
+        Ok(_) => Ok(v),
+        Err(why) => {
+          let _ = conn.execute_batch("ROLLBACK;");
+          Err(why)
+        },
+      },
+      Err(why) => {
+        let _ = conn.execute_batch("ROLLBACK;");
+        Err(why)
+      },
+    }
+  }
+
+  /// Ends the [CDatabase::subscribe] subscription with the specified id.
+  pub fn unsubscribe(&self, subscription: usize) {
+    self.pool.hub.remove(subscription);
+  }
+
+  /// Executes a Data Manipulation Language statement via a pooled
+  /// connection returning the number of rows updated. See [db_update] for
+  /// details.
+  pub fn update<P: rusqlite::Params>(
+    &self,
+    sql: &str,
+    params: P
+  ) -> Result<usize, rusqlite::Error> {
+    let conn = self.connection()?;
+    let mut stmt = conn.prepare_cached(sql)?;
+    stmt.execute(params)
+  }
+
+  /// Executes the same Data Manipulation Language statement for each set of
+  /// params within a single transaction reusing one prepared statement.
+  /// This is the fast path for bulk inserts. Returns the total rows
+  /// updated.
+  pub fn update_many<P, I>(
+    &self,
+    sql: &str,
+    rows: I
+  ) -> Result<usize, rusqlite::Error>
+  where
+    P: rusqlite::Params,
+    I: IntoIterator<Item = P>
+  {
+    self.transaction(|tx| tx.update_many(sql, rows))
+  }
+}
+
+/// A connection checked out of a [CDatabase] pool via
+/// [CDatabase::connection]. It dereferences to the underlying
+/// rusqlite::Connection and is returned to the pool when dropped. The
+/// changes committed through it are delivered to the subscribers then.
+pub struct CDbConnection {
+  pool: std::sync::Arc<CDatabasePool>,
+  conn: Option<(rusqlite::Connection, CDbChanges)>,
+}
+impl CDbConnection {
+  /// Private constructor supporting the [CDatabase::connection] call.
+  fn new(
+    db: &CDatabase,
+    conn: (rusqlite::Connection, CDbChanges)
+  ) -> CDbConnection {
+    CDbConnection { pool: db.pool.clone(), conn: Some(conn) }
+  }
+
+  /// The row changes pending the commit of the connection's transaction.
+  fn changes(&self) -> &CDbChanges {
+    &self.conn.as_ref().unwrap().1
+  }
+}
+impl std::ops::Deref for CDbConnection {
+  type Target = rusqlite::Connection;
+
+  fn deref(&self) -> &rusqlite::Connection {
+    &self.conn.as_ref().unwrap().0
+  }
+}
+impl Drop for CDbConnection {
+  fn drop(&mut self) {
+    if let Some(conn) = self.conn.take() {
+      // Don't hand the next user a connection left mid transaction.
+      if !conn.0.is_autocommit() {
+        let _ = conn.0.execute_batch("ROLLBACK;");
+      }
+      // Out of the transaction so any held commit has succeeded.
+      let events = match conn.0.is_autocommit() {
+        true => {
+          let mut state = conn.1.lock().unwrap();
+          state.committing = None;
+          std::mem::take(&mut state.held)
+        },
+        false => Vec::new(),
+      };
+      self.pool.connections.lock().unwrap().0.push(conn);
+      self.pool.available.notify_one();
+      if !events.is_empty() {
+        self.pool.hub.publish(events);
+      }
+    }
+  }
+}
+
+/// The transaction scope handed to the [CDatabase::transaction] function.
+/// All of the statements executed through it are committed or rolled back
+/// together. It dereferences to the underlying rusqlite::Connection.
+pub struct CDbTransaction<'a> {
+  conn: &'a rusqlite::Connection,
+  changes: &'a CDbChanges,
+  depth: usize,
+}
+impl CDbTransaction<'_> {
+  /// Executes one or more SQL statements within the transaction.
+  pub fn manage(&self, sql: &str) -> Result<(), rusqlite::Error> {
+    self.conn.execute_batch(sql)
+  }
+
+  /// Queries the database within the transaction mapping each row via the
+  /// specified function.
+  pub fn query<
+    P: rusqlite::Params,
+    T,
+    F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>
+  >(
+    &self,
+    sql: &str,
+    params: P,
+    f: F
+  ) -> Result<Vec<T>, rusqlite::Error> {
+    let mut stmt = self.conn.prepare(sql)?;
+    let rows = stmt.query_map(params, f)?;
+    rows.collect()
+  }
+
+  /// Queries the database within the transaction building a [CDbRowMapper]
+  /// struct from each row.
+  pub fn query_as<P: rusqlite::Params, T: CDbRowMapper>(
+    &self,
+    sql: &str,
+    params: P
+  ) -> Result<Vec<T>, rusqlite::Error> {
+    self.query(sql, params, T::from_row)
+  }
+
+  /// Queries the database within the transaction returning the column
+  /// keyed rows.
+  pub fn query_object<P: rusqlite::Params>(
+    &self,
+    sql: &str,
+    params: P
+  ) -> Result<CDbResultSet, rusqlite::Error> {
+    CDbResultSet::new(self.conn, sql, params)
+  }
+
+  /// Runs the specified function within a named SAVEPOINT of the
+  /// transaction. When the function returns an Err, only the work done
+  /// since the savepoint is rolled back and the Err is returned for the
+  /// caller to handle or propagate.
+  pub fn savepoint<T, F>(&self, f: F) -> Result<T, rusqlite::Error>
+  where
+    F: FnOnce(&CDbTransaction) -> Result<T, rusqlite::Error>
+  {
+    let name = format!("codemelted_sp_{}", self.depth + 1);
+    self.conn.execute_batch(&format!("SAVEPOINT {};", name))?;
+    let sp = CDbTransaction {
+      conn: self.conn,
+      changes: self.changes,
+      depth: self.depth + 1
+    };
+    let mark = self.changes.lock().unwrap().pending.len();
+    match f(&sp) {
+      Ok(v) => {
+        self.conn.execute_batch(&format!("RELEASE {};", name))?;
+        Ok(v)
+      },
+      Err(why) => {
+        let _ = self.conn.execute_batch(
+          &format!("ROLLBACK TO {}; RELEASE {};", name, name)
+        );
+        // No hook fires for a savepoint so drop its row changes here.
+        self.changes.lock().unwrap().pending.truncate(mark);
+        Err(why)
+      },
+    }
+  }
+
+  /// Executes a Data Manipulation Language statement within the
+  /// transaction returning the number of rows updated.
+  pub fn update<P: rusqlite::Params>(
+    &self,
+    sql: &str,
+    params: P
+  ) -> Result<usize, rusqlite::Error> {
+    let mut stmt = self.conn.prepare_cached(sql)?;
+    stmt.execute(params)
+  }
+
+  /// Executes the same Data Manipulation Language statement for each set of
+  /// params reusing one prepared statement. Returns the total rows updated.
+  pub fn update_many<P, I>(
+    &self,
+    sql: &str,
+    rows: I
+  ) -> Result<usize, rusqlite::Error>
+  where
+    P: rusqlite::Params,
+    I: IntoIterator<Item = P>
+  {
+    let mut stmt = self.conn.prepare_cached(sql)?;
+    let mut count = 0;
+    for params in rows {
+      count += stmt.execute(params)?;
+    }
+    Ok(count)
+  }
+}
+impl std::ops::Deref for CDbTransaction<'_> {
+  type Target = rusqlite::Connection;
+
+  fn deref(&self) -> &rusqlite::Connection {
+    self.conn
+  }
+}
+
+/// The full-text search carried out by the [db_fts_search] function. It
+/// holds the FTS5 MATCH expression along with how to mark up the matched
+/// terms and page through the ranked results.
+#[derive(Clone, Debug)]
+pub struct CDbFtsQuery {
+  query: String,
+  open: String,
+  close: String,
+  ellipsis: String,
+  tokens: usize,
+  limit: usize,
+  offset: usize,
+}
+impl CDbFtsQuery {
+  /// Creates a query for the FTS5 MATCH expression (i.e. "error AND disk",
+  /// "dev*", or "message: timeout"). Matched terms are marked with <b></b>,
+  /// snippets are 16 tokens long, and the first 20 results are returned.
+  pub fn new(query: &str) -> CDbFtsQuery {
+    CDbFtsQuery {
+      query: query.to_string(),
+      open: String::from("<b>"),
+      close: String::from("</b>"),
+      ellipsis: String::from("..."),
+      tokens: 16,
+      limit: 20,
+      offset: 0,
+    }
+  }
+
+  /// Sets the text placed before and after each matched term.
+  pub fn highlight(mut self, open: &str, close: &str) -> CDbFtsQuery {
+    self.open = open.to_string();
+    self.close = close.to_string();
+    self
+  }
+
+  /// Sets the page of results to return.
+  pub fn page(mut self, limit: usize, offset: usize) -> CDbFtsQuery {
+    self.limit = limit;
+    self.offset = offset;
+    self
+  }
+
+  /// Sets the text marking trimmed content and the number of tokens (1 to
+  /// 64) in the snippet.
+  pub fn snippet(mut self, ellipsis: &str, tokens: usize) -> CDbFtsQuery {
+    if !(1..=64).contains(&tokens) {
+      panic!("SyntaxError: snippet tokens must be between 1 and 64.");
+    }
+    self.ellipsis = ellipsis.to_string();
+    self.tokens = tokens;
+    self
+  }
+}
+
+/// The statement a [CDbQuery] builds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CDbQueryKind {
+  Select,
+  Insert,
+  Update,
+  Delete,
+}
+
+/// Builds a SELECT / INSERT / UPDATE / DELETE statement with quoted
+/// identifiers and bound parameters. The [CDbQuery::sql] and
+/// [CDbQuery::params] feed directly into [db_query] / [db_update] (or the
+/// [CDatabase] equivalents). Filters are joined with AND and
+/// [CDbQuery::when] only applies clauses when a condition holds.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CDbQuery;
+///
+/// let db_file = format!(
+///   "{}/test_db_query_builder.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some(
+///   "CREATE TABLE device (id INTEGER PRIMARY KEY, name TEXT, port INTEGER)"
+/// ));
+/// for (name, port) in [("pump", 1), ("valve", 2), ("fan", 3)] {
+///   let insert = CDbQuery::insert("device")
+///     .set("name", name)
+///     .set("port", port);
+///   assert_eq!(
+///     insert.sql(),
+///     "INSERT INTO \"device\" (\"name\", \"port\") VALUES (?, ?)"
+///   );
+///   codemelted::db_update(&db_file, &insert.sql(), insert.params());
+/// }
+///
+/// // Build the WHERE clause based on what the user asked for.
+/// let name_filter: Option<&str> = None;
+/// let min_port = Some(2);
+/// let select = CDbQuery::select("device", &["name"])
+///   .when(name_filter.is_some(), |q| q.filter("name", "=", name_filter))
+///   .when(min_port.is_some(), |q| q.filter("port", ">=", min_port))
+///   .order_by("port", false)
+///   .page(10, 0);
+/// assert_eq!(
+///   select.sql(),
+///   "SELECT \"name\" FROM \"device\" WHERE \"port\" >= ? \
+///     ORDER BY \"port\" DESC LIMIT ? OFFSET ?"
+/// );
+/// let names = codemelted::db_query(
 ///   &db_file,
-///   sql,
-///   (&me.name, &me.data)
+///   &select.sql(),
+///   select.params(),
+///   |row| row.get::<usize, String>(0)
+/// );
+/// assert_eq!(names, ["fan", "valve"]);
+///
+/// let update = CDbQuery::update("device")
+///   .set("port", 4)
+///   .filter_in("name", &["pump", "fan"]);
+/// assert_eq!(codemelted::db_update(&db_file, &update.sql(), update.params()), 2);
+///
+/// let delete = CDbQuery::delete("device").filter_null("name", false);
+/// assert_eq!(codemelted::db_update(&db_file, &delete.sql(), delete.params()), 3);
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CDbQuery {
+  kind: CDbQueryKind,
+  table: String,
+  columns: Vec<String>,
+  assignments: Vec<(String, rusqlite::types::Value)>,
+  conditions: Vec<String>,
+  condition_values: Vec<rusqlite::types::Value>,
+  order: Vec<String>,
+  page: Option<(usize, usize)>,
+}
+impl CDbQuery {
+  /// Private constructor for each of the statement kinds.
+  fn new(kind: CDbQueryKind, table: &str, columns: &[&str]) -> CDbQuery {
+    CDbQuery {
+      kind,
+      table: table.to_string(),
+      columns: columns.iter().map(|v| v.to_string()).collect(),
+      assignments: Vec::new(),
+      conditions: Vec::new(),
+      condition_values: Vec::new(),
+      order: Vec::new(),
+      page: None,
+    }
+  }
+
+  /// Captures a bound parameter as an owned value.
+  fn to_value<V: rusqlite::ToSql>(value: V) -> rusqlite::types::Value {
+    match value.to_sql() {
+      Ok(rusqlite::types::ToSqlOutput::Borrowed(v)) => v.into(),
+      Ok(rusqlite::types::ToSqlOutput::Owned(v)) => v,
+      _ => panic!("SyntaxError: CDbQuery unsupported parameter value."),
+    }
+  }
+
+  /// Panics if the clause does not apply to the statement kind.
+  fn expect_kind(&self, clause: &str, kinds: &[CDbQueryKind]) {
+    if !kinds.contains(&self.kind) {
+      panic!(
+        "SyntaxError: CDbQuery {} is not valid for {:?}.",
+        clause,
+        self.kind
+      );
+    }
+  }
+
+  /// Starts a DELETE FROM the table. Add filters to limit the rows deleted.
+  pub fn delete(table: &str) -> CDbQuery {
+    CDbQuery::new(CDbQueryKind::Delete, table, &[])
+  }
+
+  /// Starts an INSERT INTO the table. Add the column values via
+  /// [CDbQuery::set].
+  pub fn insert(table: &str) -> CDbQuery {
+    CDbQuery::new(CDbQueryKind::Insert, table, &[])
+  }
+
+  /// Starts a SELECT of the columns (all of them when empty) from the
+  /// table.
+  pub fn select(table: &str, columns: &[&str]) -> CDbQuery {
+    CDbQuery::new(CDbQueryKind::Select, table, columns)
+  }
+
+  /// Starts an UPDATE of the table. Add the column values via
+  /// [CDbQuery::set] and filters to limit the rows updated.
+  pub fn update(table: &str) -> CDbQuery {
+    CDbQuery::new(CDbQueryKind::Update, table, &[])
+  }
+
+  /// Filters the rows where the column compares to the value via the
+  /// operator (=, !=, <>, <, <=, >, >=, LIKE, NOT LIKE, or GLOB).
+  pub fn filter<V: rusqlite::ToSql>(
+    mut self,
+    column: &str,
+    op: &str,
+    value: V
+  ) -> CDbQuery {
+    self.expect_kind(
+      "filter",
+      &[CDbQueryKind::Select, CDbQueryKind::Update, CDbQueryKind::Delete]
+    );
+    let op = op.trim().to_uppercase();
+    let ops = [
+      "=", "!=", "<>", "<", "<=", ">", ">=", "LIKE", "NOT LIKE", "GLOB"
+    ];
+    if !ops.contains(&op.as_str()) {
+      panic!("SyntaxError: CDbQuery unsupported operator {}.", op);
+    }
+    self.conditions.push(format!("{} {} ?", db_quote(column), op));
+    self.condition_values.push(CDbQuery::to_value(value));
+    self
+  }
+
+  /// Filters the rows where the column is one of the values. No rows match
+  /// an empty list of values.
+  pub fn filter_in<V: rusqlite::ToSql>(
+    mut self,
+    column: &str,
+    values: &[V]
+  ) -> CDbQuery {
+    self.expect_kind(
+      "filter_in",
+      &[CDbQueryKind::Select, CDbQueryKind::Update, CDbQueryKind::Delete]
+    );
+    if values.is_empty() {
+      self.conditions.push(String::from("0"));
+      return self;
+    }
+    let placeholders = vec!["?"; values.len()].join(", ");
+    self.conditions.push(format!("{} IN ({})", db_quote(column), placeholders));
+    for value in values {
+      self.condition_values.push(CDbQuery::to_value(value));
+    }
+    self
+  }
+
+  /// Filters the rows where the column is NULL (true) or NOT NULL (false).
+  pub fn filter_null(mut self, column: &str, is_null: bool) -> CDbQuery {
+    self.expect_kind(
+      "filter_null",
+      &[CDbQueryKind::Select, CDbQueryKind::Update, CDbQueryKind::Delete]
+    );
+    let test = if is_null { "IS NULL" } else { "IS NOT NULL" };
+    self.conditions.push(format!("{} {}", db_quote(column), test));
+    self
+  }
+
+  /// Filters the rows via a SQL expression using ? placeholders for the
+  /// values (i.e. "(a = ? OR b = ?)"). The expression is not quoted so it
+  /// must not be built from user input.
+  pub fn filter_sql<V: rusqlite::ToSql>(
+    mut self,
+    expression: &str,
+    values: &[V]
+  ) -> CDbQuery {
+    self.expect_kind(
+      "filter_sql",
+      &[CDbQueryKind::Select, CDbQueryKind::Update, CDbQueryKind::Delete]
+    );
+    self.conditions.push(format!("({})", expression));
+    for value in values {
+      self.condition_values.push(CDbQuery::to_value(value));
+    }
+    self
+  }
+
+  /// Orders the selected rows by the column. Call again to add further
+  /// ordering columns.
+  pub fn order_by(mut self, column: &str, ascending: bool) -> CDbQuery {
+    self.expect_kind("order_by", &[CDbQueryKind::Select]);
+    let direction = if ascending { "ASC" } else { "DESC" };
+    self.order.push(format!("{} {}", db_quote(column), direction));
+    self
+  }
+
+  /// Returns the limit rows of the selection after skipping offset rows.
+  pub fn page(mut self, limit: usize, offset: usize) -> CDbQuery {
+    self.expect_kind("page", &[CDbQueryKind::Select]);
+    self.page = Some((limit, offset));
+    self
+  }
+
+  /// Sets the value of a column to INSERT or UPDATE.
+  pub fn set<V: rusqlite::ToSql>(mut self, column: &str, value: V) -> CDbQuery {
+    self.expect_kind("set", &[CDbQueryKind::Insert, CDbQueryKind::Update]);
+    self.assignments.push((column.to_string(), CDbQuery::to_value(value)));
+    self
+  }
+
+  /// Applies the clauses added by the function only when the condition is
+  /// true. Supports building optional filters without breaking the chain.
+  pub fn when<F: FnOnce(CDbQuery) -> CDbQuery>(
+    self,
+    condition: bool,
+    f: F
+  ) -> CDbQuery {
+    if condition { f(self) } else { self }
+  }
+
+  /// The bound parameters of the statement in placeholder order to hand to
+  /// [db_query] or [db_update].
+  pub fn params(
+    &self
+  ) -> rusqlite::ParamsFromIter<Vec<rusqlite::types::Value>> {
+    rusqlite::params_from_iter(self.values())
+  }
+
+  /// Builds the SQL statement with ? placeholders for each value.
+  pub fn sql(&self) -> String {
+    if self.assignments.is_empty()
+        && matches!(self.kind, CDbQueryKind::Insert | CDbQueryKind::Update) {
+      panic!("SyntaxError: CDbQuery {:?} requires a set value.", self.kind);
+    }
+    let table = db_quote(&self.table);
+    let mut sql = match self.kind {
+      CDbQueryKind::Select => {
+        let columns = match self.columns.is_empty() {
+          true => String::from("*"),
+          false => self.columns.iter()
+            .map(|v| db_quote(v))
+            .collect::<Vec<String>>()
+            .join(", "),
+        };
+        format!("SELECT {} FROM {}", columns, table)
+      },
+      CDbQueryKind::Insert => format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table,
+        self.assignments.iter()
+          .map(|v| db_quote(&v.0))
+          .collect::<Vec<String>>()
+          .join(", "),
+        vec!["?"; self.assignments.len()].join(", ")
+      ),
+      CDbQueryKind::Update => format!(
+        "UPDATE {} SET {}",
+        table,
+        self.assignments.iter()
+          .map(|v| format!("{} = ?", db_quote(&v.0)))
+          .collect::<Vec<String>>()
+          .join(", ")
+      ),
+      CDbQueryKind::Delete => format!("DELETE FROM {}", table),
+    };
+    if !self.conditions.is_empty() {
+      sql.push_str(" WHERE ");
+      sql.push_str(&self.conditions.join(" AND "));
+    }
+    if !self.order.is_empty() {
+      sql.push_str(" ORDER BY ");
+      sql.push_str(&self.order.join(", "));
+    }
+    if self.page.is_some() {
+      sql.push_str(" LIMIT ? OFFSET ?");
+    }
+    sql
+  }
+
+  /// The bound parameter values in placeholder order.
+  pub fn values(&self) -> Vec<rusqlite::types::Value> {
+    let mut values: Vec<rusqlite::types::Value> = self.assignments.iter()
+      .map(|v| v.1.clone())
+      .collect();
+    values.extend(self.condition_values.iter().cloned());
+    if let Some((limit, offset)) = self.page {
+      values.push(rusqlite::types::Value::Integer(limit as i64));
+      values.push(rusqlite::types::Value::Integer(offset as i64));
+    }
+    values
+  }
+}
+
+/// Identifies the kind of schema object listed by the [db_list] function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDbObjectType {
+  /// An index on a table (not the ones SQLite creates automatically).
+  Index,
+  /// A table (not the internal sqlite_ tables).
+  Table,
+  /// A trigger on a table or view.
+  Trigger,
+  /// A view.
+  View,
+}
+impl CDbObjectType {
+  /// The type name as stored in the sqlite_schema table.
+  fn as_str(&self) -> &'static str {
+    match self {
+      CDbObjectType::Index => "index",
+      CDbObjectType::Table => "table",
+      CDbObjectType::Trigger => "trigger",
+      CDbObjectType::View => "view",
+    }
+  }
+}
+
+/// A versioned step of a schema migration carried out by the [db_migrate]
+/// function. The up script moves the schema to this version and the
+/// optional down script reverts it to the previous version.
+#[derive(Clone, Debug)]
+pub struct CDbMigration {
+  version: u32,
+  name: String,
+  up: String,
+  down: Option<String>,
+}
+impl CDbMigration {
+  /// Creates a migration from embedded SQL scripts. The version must be
+  /// greater than 0.
+  pub fn new(
+    version: u32,
+    name: &str,
+    up: &str,
+    down: Option<&str>
+  ) -> CDbMigration {
+    if version == 0 {
+      panic!("SyntaxError: CDbMigration '{}' version must be > 0.", name);
+    }
+    CDbMigration {
+      version,
+      name: name.to_string(),
+      up: up.to_string(),
+      down: down.map(|v| v.to_string()),
+    }
+  }
+
+  /// Loads the migrations from a directory of .sql files named
+  /// VERSION_NAME.sql (i.e. 001_create_tables.sql) with an optional
+  /// VERSION_NAME.down.sql rollback script. Other files are ignored. The
+  /// directory is always read from the host operating system.
+  pub fn from_dir(path: &str) -> Result<Vec<CDbMigration>, std::io::Error> {
+    let read = |filename: &str| CDiskOsFileSystem.read(filename).and_then(
+      |v| String::from_utf8(v).map_err(|why| std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        why,
+      ))
+    );
+    let mut migrations = Vec::new();
+    for entry in disk_ls(path)? {
+      let file_name = entry?.file_name().to_string_lossy().to_string();
+      if file_name.ends_with(".down.sql") {
+        continue;
+      }
+      let stem = match file_name.strip_suffix(".sql") {
+        Some(v) => v,
+        None => continue,
+      };
+      let (version, name) = match stem.split_once('_') {
+        Some((v, n)) => match v.parse::<u32>() {
+          Ok(v) if v > 0 => (v, n),
+          _ => continue,
+        },
+        None => continue,
+      };
+      let up = read(&format!("{}/{}", path, file_name))?;
+      let down_file = format!("{}/{}.down.sql", path, stem);
+      let down = match CDiskOsFileSystem.exists(&down_file, CDiskType::File) {
+        true => Some(read(&down_file)?),
+        false => None,
+      };
+      migrations.push(CDbMigration {
+        version,
+        name: name.to_string(),
+        up,
+        down,
+      });
+    }
+    migrations.sort_by_key(|m| m.version);
+    Ok(migrations)
+  }
+
+  /// The name of the migration.
+  pub fn name(&self) -> String {
+    self.name.to_string()
+  }
+
+  /// The schema version the migration moves the database to.
+  pub fn version(&self) -> u32 {
+    self.version
+  }
+}
+
+/// Identifies where the [db_migrate] function tracks the schema version
+/// applied to a database.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDbMigrationTracker {
+  /// Tracks the version via the sqlite PRAGMA user_version.
+  UserVersion,
+  /// Tracks each applied migration (version, name, applied_at) in a
+  /// codemelted_migrations table.
+  Table,
+}
+impl CDbMigrationTracker {
+  /// Creates the tracking table if needed.
+  fn prepare(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    match self {
+      CDbMigrationTracker::UserVersion => Ok(()),
+      CDbMigrationTracker::Table => conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS codemelted_migrations (
+          version INTEGER PRIMARY KEY,
+          name TEXT NOT NULL,
+          applied_at TEXT NOT NULL
+        );"
+      ),
+    }
+  }
+
+  /// Reads the current version without changing the database. A missing
+  /// tracking table is version 0.
+  fn version(&self, conn: &rusqlite::Connection) -> rusqlite::Result<u32> {
+    match self {
+      CDbMigrationTracker::UserVersion => {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+      },
+      CDbMigrationTracker::Table => {
+        let tracked = conn.prepare(
+          "SELECT 1 FROM sqlite_master
+            WHERE type = 'table' AND name = 'codemelted_migrations'"
+        )?.exists([])?;
+        if !tracked {
+          return Ok(0);
+        }
+        conn.query_row(
+          "SELECT IFNULL(MAX(version), 0) FROM codemelted_migrations",
+          [],
+          |row| row.get(0)
+        )
+      },
+    }
+  }
+
+  /// Records the migration as applied (true) or reverted (false) moving the
+  /// tracked version to the specified version.
+  fn record(
+    &self,
+    conn: &rusqlite::Connection,
+    migration: &CDbMigration,
+    applied: bool,
+    version: u32,
+  ) -> rusqlite::Result<()> {
+    match self {
+      CDbMigrationTracker::UserVersion => {
+        conn.pragma_update(None, "user_version", version)
+      },
+      CDbMigrationTracker::Table => {
+        if applied {
+          conn.execute(
+            "INSERT INTO codemelted_migrations (version, name, applied_at)
+              VALUES (?1, ?2, datetime('now'))",
+            (migration.version, &migration.name)
+          )?;
+        } else {
+          conn.execute(
+            "DELETE FROM codemelted_migrations WHERE version = ?1",
+            [migration.version]
+          )?;
+        }
+        Ok(())
+      },
+    }
+  }
+}
+
+/// Receives the pages remaining / page count after each step of a
+/// [db_backup] or [db_restore] call.
+pub type CDbBackupCB = fn(rusqlite::backup::Progress);
+
+/// Identifies how the [db_import_csv] and [db_import_json] functions handle
+/// a row that conflicts with a UNIQUE or PRIMARY KEY constraint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDbConflict {
+  /// Fail the import rolling back the current batch.
+  Fail,
+  /// Skip the conflicting row keeping the existing one.
+  Ignore,
+  /// Replace the existing row with the conflicting row.
+  Replace,
+}
+impl CDbConflict {
+  /// The INSERT statement carrying out the conflict handling.
+  fn as_insert(&self) -> &'static str {
+    match self {
+      CDbConflict::Fail => "INSERT OR ABORT",
+      CDbConflict::Ignore => "INSERT OR IGNORE",
+      CDbConflict::Replace => "INSERT OR REPLACE",
+    }
+  }
+}
+
+/// The structured error returned by the [db_try_exists], [db_try_query], and
+/// [db_try_update] functions. It exposes the SQLite result codes, the name
+/// of any failed constraint, and the SQL statement being run so callers can
+/// handle unique key conflicts and busy / locked databases themselves.
+#[derive(Debug)]
+pub struct CDbError {
+  error: rusqlite::Error,
+  sql: Option<String>,
+}
+impl CDbError {
+  /// Wraps the rusqlite::Error with the SQL statement that caused it.
+  pub fn new(error: rusqlite::Error, sql: Option<&str>) -> CDbError {
+    CDbError { error, sql: sql.map(|v| v.to_string()) }
+  }
+
+  /// Private constructor for failures that happen outside of SQLite (i.e.
+  /// reading an import file) reported with the specified result code.
+  fn failure(code: std::ffi::c_int, message: &str) -> CDbError {
+    CDbError::from(rusqlite::Error::SqliteFailure(
+      rusqlite::ffi::Error::new(code),
+      Some(message.to_string())
+    ))
+  }
+
+  /// The primary SQLite result code (i.e. ConstraintViolation,
+  /// DatabaseBusy) or None if the error did not come from SQLite.
+  pub fn code(&self) -> Option<rusqlite::ErrorCode> {
+    self.error.sqlite_error_code()
+  }
+
+  /// The name of the failed constraint as reported by SQLite. For UNIQUE
+  /// and NOT NULL constraints this is the table.column list, for CHECK
+  /// constraints the constraint name. None if not a constraint violation
+  /// or SQLite did not name it (i.e. FOREIGN KEY).
+  pub fn constraint(&self) -> Option<String> {
+    if !self.is_constraint() {
+      return None;
+    }
+    let message = self.message();
+    let (_, name) = message.split_once("constraint failed: ")?;
+    Some(name.trim().to_string())
+  }
+
+  /// The underlying rusqlite::Error.
+  pub fn error(&self) -> &rusqlite::Error {
+    &self.error
+  }
+
+  /// The SQLite extended result code (i.e. SQLITE_CONSTRAINT_UNIQUE) or
+  /// None if the error did not come from SQLite.
+  pub fn extended_code(&self) -> Option<i32> {
+    self.error.sqlite_error().map(|v| v.extended_code)
+  }
+
+  /// True if the database was busy or locked by another connection and the
+  /// statement can be retried.
+  pub fn is_busy(&self) -> bool {
+    matches!(
+      self.code(),
+      Some(rusqlite::ErrorCode::DatabaseBusy)
+        | Some(rusqlite::ErrorCode::DatabaseLocked)
+    )
+  }
+
+  /// True if the statement violated a constraint.
+  pub fn is_constraint(&self) -> bool {
+    self.code() == Some(rusqlite::ErrorCode::ConstraintViolation)
+  }
+
+  /// True if the statement violated a UNIQUE or PRIMARY KEY constraint.
+  pub fn is_unique_violation(&self) -> bool {
+    matches!(
+      self.extended_code(),
+      Some(rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE)
+        | Some(rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY)
+    )
+  }
+
+  /// The error message reported for the failure.
+  pub fn message(&self) -> String {
+    self.error.to_string()
+  }
+
+  /// The SQL statement that failed or None if the failure was not tied to a
+  /// statement (i.e. opening the database).
+  pub fn sql(&self) -> Option<&str> {
+    self.sql.as_deref()
+  }
+}
+impl std::fmt::Display for CDbError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.sql {
+      Some(sql) => write!(f, "{} [{}]", self.error, sql),
+      None => write!(f, "{}", self.error),
+    }
+  }
+}
+impl std::error::Error for CDbError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    Some(&self.error)
+  }
+}
+impl From<rusqlite::Error> for CDbError {
+  fn from(error: rusqlite::Error) -> CDbError {
+    CDbError::new(error, None)
+  }
+}
+
+/// The column keyed rows returned by the [db_query_object] function. Each
+/// row is a [CObject] keyed by column name with the SQLite storage classes
+/// mapped as INTEGER / REAL to a number, TEXT to a string, NULL to null, and
+/// BLOB to an array of byte values. Implements [CCsvFormat] and provides
+/// [CDbResultSet::as_json] to export the result.
+#[derive(Clone, Debug)]
+pub struct CDbResultSet {
+  columns: Vec<String>,
+  rows: CObject,
+}
+impl CDbResultSet {
+  /// Private constructor carrying out the query on the specified connection.
+  fn new<P: rusqlite::Params>(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    params: P
+  ) -> Result<CDbResultSet, rusqlite::Error> {
+    let mut stmt = conn.prepare(sql)?;
+    let columns: Vec<String> = stmt.column_names()
+      .iter()
+      .map(|v| v.to_string())
+      .collect();
+    let mut rows = CObject::new_array();
+    let mut results = stmt.query(params)?;
+    while let Some(row) = results.next()? {
+      let mut obj = CObject::new_object();
+      for (x, column) in columns.iter().enumerate() {
+        obj[column.as_str()] = CDbResultSet::to_object(row.get_ref(x)?);
+      }
+      let _ = rows.push(obj);
+    }
+    Ok(CDbResultSet { columns, rows })
+  }
+
+  /// Maps a SQLite value to its [CObject] equivalent.
+  fn to_object(value: rusqlite::types::ValueRef<'_>) -> CObject {
+    match value {
+      rusqlite::types::ValueRef::Null => CObject::Null,
+      rusqlite::types::ValueRef::Integer(v) => CObject::from(v),
+      rusqlite::types::ValueRef::Real(v) => CObject::from(v),
+      rusqlite::types::ValueRef::Text(v) => {
+        CObject::from(String::from_utf8_lossy(v).to_string())
+      },
+      rusqlite::types::ValueRef::Blob(v) => CObject::from(v.to_vec()),
+    }
+  }
+
+  /// Formats a value as a CSV field quoting it when necessary. NULL is an
+  /// empty field and a BLOB is its JSON array of byte values.
+  fn to_csv_field(value: &CObject) -> String {
+    let field = match value {
+      CObject::Null => return String::new(),
+      CObject::Short(_) | CObject::String(_) => value.to_string(),
+      _ => value.dump(),
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+      format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+      field
+    }
+  }
+
+  /// Serializes the rows as a JSON array of objects.
+  pub fn as_json(&self) -> String {
+    self.rows.dump()
+  }
+
+  /// The column names of the query in SELECT order.
+  pub fn columns(&self) -> &[String] {
+    &self.columns
+  }
+
+  /// True if the query returned no rows.
+  pub fn is_empty(&self) -> bool {
+    self.rows.is_empty()
+  }
+
+  /// The number of rows returned by the query.
+  pub fn len(&self) -> usize {
+    self.rows.len()
+  }
+
+  /// The [CObject] array of column keyed row objects.
+  pub fn rows(&self) -> &CObject {
+    &self.rows
+  }
+}
+impl CCsvFormat for CDbResultSet {
+  fn csv_header(&self) -> String {
+    self.columns.iter()
+      .map(|v| CDbResultSet::to_csv_field(&CObject::from(v.as_str())))
+      .collect::<Vec<String>>()
+      .join(",")
+  }
+
+  fn as_csv(&self) -> String {
+    let mut csv_data = String::new();
+    for row in self.rows.members() {
+      let data = self.columns.iter()
+        .map(|v| CDbResultSet::to_csv_field(&row[v.as_str()]))
+        .collect::<Vec<String>>()
+        .join(",");
+      csv_data.push_str(&data);
+      csv_data.push('\n');
+    }
+    csv_data
+  }
+}
+
+/// Implemented by a struct to be built from a query row by the
+/// [db_query_as] function. Use row.get("column") to pick the fields by
+/// column name so the mapping does not depend on the SELECT order.
+pub trait CDbRowMapper: Sized {
+  /// Builds the struct from the specified row.
+  fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self>;
+}
+
+/// Copies the database page by page from one connection to another. A
+/// step copies 100 pages then pauses so other connections can keep writing
+/// to the source database.
+fn db_copy(
+  src: &rusqlite::Connection,
+  dest: &mut rusqlite::Connection,
+  progress: Option<CDbBackupCB>
+) -> Result<(), rusqlite::Error> {
+  let backup = rusqlite::backup::Backup::new(src, dest)?;
+  backup.run_to_completion(
+    100,
+    std::time::Duration::from_millis(10),
+    progress
+  )
+}
+
+/// Quotes a table, column, or other identifier for use in a SQL statement.
+fn db_quote(name: &str) -> String {
+  format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Applies the key registered via [db_key] for the database to a newly
+/// opened connection. The key must be the first statement run on the
+/// connection.
+fn db_apply_key(
+  conn: &rusqlite::Connection,
+  db_path: &str
+) -> Result<(), rusqlite::Error> {
+  let key = DB_KEYS.lock().unwrap()
+    .iter()
+    .find(|v| v.0 == db_path)
+    .map(|v| v.1.clone());
+  if let Some(key) = key {
+    conn.pragma_update(None, "key", key)?;
+    if !conn.prepare("PRAGMA cipher_version")?.exists([])? {
+      return Err(rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+        Some(String::from(
+          "encryption requires the sqlcipher feature of the crate"
+        ))
+      ));
+    }
+    // A wrong key is only detected when the first page is read.
+    conn.query_row("SELECT count(*) FROM sqlite_schema", [], |_| Ok(()))?;
+  }
+  Ok(())
+}
+
+/// Describes the columns of a table or view as an array of [CObject]
+/// items. Fails if the table does not exist.
+fn db_columns(
+  conn: &rusqlite::Connection,
+  table: &str
+) -> Result<CObject, rusqlite::Error> {
+  // Gather the foreign keys by the column that references them.
+  let mut foreign_keys = CObject::new_object();
+  let mut stmt = conn.prepare(
+    "SELECT \"from\", \"table\", \"to\", on_update, on_delete
+      FROM pragma_foreign_key_list(?1)"
+  )?;
+  let mut rows = stmt.query([table])?;
+  while let Some(row) = rows.next()? {
+    let column: String = row.get(0)?;
+    foreign_keys[column.as_str()] = json::object!{
+      "table": row.get::<usize, String>(1)?,
+      "column": row.get::<usize, Option<String>>(2)?,
+      "on_update": row.get::<usize, String>(3)?,
+      "on_delete": row.get::<usize, String>(4)?,
+    };
+  }
+
+  // Now describe each of the columns.
+  let mut columns = CObject::new_array();
+  let mut stmt = conn.prepare(
+    "SELECT name, type, \"notnull\", dflt_value, pk
+      FROM pragma_table_info(?1) ORDER BY cid"
+  )?;
+  let mut rows = stmt.query([table])?;
+  while let Some(row) = rows.next()? {
+    let name: String = row.get(0)?;
+    let _ = columns.push(json::object!{
+      "name": name.as_str(),
+      "type": row.get::<usize, String>(1)?,
+      "not_null": row.get::<usize, bool>(2)?,
+      "default": row.get::<usize, Option<String>>(3)?,
+      "primary_key": row.get::<usize, i64>(4)?,
+      "foreign_key": foreign_keys[name.as_str()].clone(),
+    });
+  }
+  if columns.is_empty() {
+    return Err(rusqlite::Error::SqliteFailure(
+      rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+      Some(format!("no such table: {}", table))
+    ));
+  }
+  Ok(columns)
+}
+
+/// Opens a connection to the database applying any key registered via the
+/// [db_key] function.
+fn db_connect(
+  db_path: &str
+) -> Result<rusqlite::Connection, rusqlite::Error> {
+  db_connect_with(db_path, rusqlite::OpenFlags::default())
+}
+
+/// Opens a connection to the database with the flags applying any key
+/// registered via the [db_key] function.
+fn db_connect_with(
+  db_path: &str,
+  flags: rusqlite::OpenFlags,
+) -> Result<rusqlite::Connection, rusqlite::Error> {
+  let conn = rusqlite::Connection::open_with_flags(db_path, flags)?;
+  db_apply_key(&conn, db_path)?;
+  Ok(conn)
+}
+
+/// Determines if the path identifies an in-memory database (i.e. :memory:,
+/// file::memory:, or a file: URI with mode=memory).
+fn db_is_memory(db_path: &str) -> bool {
+  if db_path == ":memory:" {
+    return true;
+  }
+  let Some(uri) = db_path.strip_prefix("file:") else {
+    return false;
+  };
+  let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+  path == ":memory:" || query.split('&').any(|v| v == "mode=memory")
+}
+
+/// Imports the rows into the table in batches of batch_size rows, each
+/// batch in its own transaction. The table is created from the column names
+/// and the types of the first batch if it does not exist. Returns the
+/// number of rows inserted (0 without any columns to insert).
+fn db_import<I>(
+  db_path: &str,
+  table: &str,
+  columns: &[String],
+  mut rows: I,
+  batch_size: usize,
+  conflict: CDbConflict
+) -> Result<usize, CDbError>
+where
+  I: Iterator<Item = Result<Vec<rusqlite::types::Value>, CDbError>>
+{
+  db_try_exists(db_path)?;
+  if columns.is_empty() {
+    return Ok(0);
+  }
+  let batch_size = if batch_size == 0 { usize::MAX } else { batch_size };
+  let conn = db_connect(db_path)?;
+  let mut batch = Vec::new();
+  for row in rows.by_ref().take(batch_size) {
+    batch.push(row?);
+  }
+
+  // Create the table typing each column by the values in the first batch.
+  let exists = conn.prepare("SELECT 1 FROM pragma_table_info(?1)")?
+    .exists([table])?;
+  if !exists {
+    let definitions = columns.iter()
+      .enumerate()
+      .map(|(x, name)| {
+        let mut kind = "";
+        for value in batch.iter().filter_map(|v| v.get(x)) {
+          kind = match (kind, value) {
+            (_, rusqlite::types::Value::Null) => kind,
+            ("" | "INTEGER", rusqlite::types::Value::Integer(_)) => "INTEGER",
+            (
+              "" | "INTEGER" | "REAL",
+              rusqlite::types::Value::Integer(_)
+                | rusqlite::types::Value::Real(_)
+            ) => "REAL",
+            ("" | "BLOB", rusqlite::types::Value::Blob(_)) => "BLOB",
+            _ => "TEXT",
+          };
+        }
+        let kind = if kind.is_empty() { "TEXT" } else { kind };
+        format!("{} {}", db_quote(name), kind)
+      })
+      .collect::<Vec<String>>()
+      .join(", ");
+    let sql = format!("CREATE TABLE {} ({})", db_quote(table), definitions);
+    conn.execute_batch(&sql).map_err(|why| CDbError::new(why, Some(&sql)))?;
+  }
+
+  // Now insert the batches.
+  let sql = format!(
+    "{} INTO {} ({}) VALUES ({})",
+    conflict.as_insert(),
+    db_quote(table),
+    columns.iter().map(|v| db_quote(v)).collect::<Vec<String>>().join(", "),
+    (1..=columns.len())
+      .map(|v| format!("?{}", v))
+      .collect::<Vec<String>>()
+      .join(", ")
+  );
+  let mut count = 0;
+  while !batch.is_empty() {
+    let insert = || -> Result<usize, rusqlite::Error> {
+      conn.execute_batch("BEGIN IMMEDIATE;")?;
+      let mut stmt = conn.prepare_cached(&sql)?;
+      let mut inserted = 0;
+      for row in &batch {
+        inserted += stmt.execute(rusqlite::params_from_iter(row.iter()))?;
+      }
+      conn.execute_batch("COMMIT;")?;
+      Ok(inserted)
+    };
+    match insert() {
+      Ok(v) => count += v,
+      Err(why) => {
+        let _ = conn.execute_batch("ROLLBACK;");
+        return Err(CDbError::new(why, Some(&sql)));
+      },
+    }
+    batch.clear();
+    for row in rows.by_ref().take(batch_size) {
+      batch.push(row?);
+    }
+  }
+  Ok(count)
+}
+
+/// Infers the type of a CSV field. An empty field is NULL, whole numbers
+/// are INTEGER, other numbers are REAL, and everything else (including
+/// numbers with leading zeros like zip codes) is TEXT.
+fn db_infer_value(field: &str) -> rusqlite::types::Value {
+  let trimmed = field.trim();
+  let digits = trimmed.trim_start_matches('-');
+  let leading_zero = digits.len() > 1
+    && digits.starts_with('0')
+    && !digits.starts_with("0.");
+  if trimmed.is_empty() {
+    rusqlite::types::Value::Null
+  } else if leading_zero {
+    rusqlite::types::Value::Text(field.to_string())
+  } else if let Ok(v) = trimmed.parse::<i64>() {
+    rusqlite::types::Value::Integer(v)
+  } else if let Ok(v) = trimmed.parse::<f64>() {
+    match v.is_finite() {
+      true => rusqlite::types::Value::Real(v),
+      false => rusqlite::types::Value::Text(field.to_string()),
+    }
+  } else {
+    rusqlite::types::Value::Text(field.to_string())
+  }
+}
+
+/// Gathers the type, name, and SQL of the user defined schema objects.
+fn db_schema(
+  conn: &rusqlite::Connection
+) -> Result<Vec<(String, String, String)>, rusqlite::Error> {
+  let mut stmt = conn.prepare(
+    "SELECT type, name, sql FROM sqlite_schema
+      WHERE substr(name, 1, 7) <> 'sqlite_' AND sql IS NOT NULL
+      ORDER BY type, name"
+  )?;
+  let rows = stmt.query_map([], |row| {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+  })?;
+  rows.collect()
+}
+
+/// Makes an online backup of the src database to the dest file while other
+/// connections continue to use it. The dest file is created if it does not
+/// exist and its contents replaced if it does. The optional progress
+/// callback is called after each step of the copy.
+///
+/// **Example:**
+/// ```
+/// let temp_path = codemelted::runtime_temp_path();
+/// let db_file = format!("{}/test_db_backup.db", temp_path);
+/// let backup_file = format!("{}/test_db_backup.bak", temp_path);
+/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&backup_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+///   INSERT INTO person (name) VALUES ('Steven');
+/// "));
+///
+/// fn on_progress(p: rusqlite::backup::Progress) {
+///   assert!(p.remaining <= p.pagecount);
+/// }
+/// codemelted::db_backup(&db_file, &backup_file, Some(on_progress)).unwrap();
+/// let rows = codemelted::db_query(
+///   &backup_file,
+///   "SELECT name FROM person",
+///   [],
+///   |row| row.get::<usize, String>(0)
+/// );
+/// assert_eq!(rows, ["Steven"]);
+/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&backup_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_backup(
+  src_path: &str,
+  dest_path: &str,
+  progress: Option<CDbBackupCB>
+) -> Result<(), CDbError> {
+  db_try_exists(src_path)?;
+  let src = db_connect(src_path)?;
+  let mut dest = db_connect(dest_path)?;
+  db_copy(&src, &mut dest, progress)?;
+  Ok(())
+}
+
+/// Describes the columns of a table or view as a [CObject] array. Each
+/// column object holds its name, declared type, not_null flag, default
+/// value expression, primary_key position (0 when not part of the key), and
+/// the foreign_key table / column / on_update / on_delete it references
+/// (null when it references nothing).
+///
+/// **Example:**
+/// ```
+/// let db_file = format!(
+///   "{}/test_db_describe.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE team (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+///   CREATE TABLE person (
+///     id      INTEGER PRIMARY KEY,
+///     name    TEXT NOT NULL DEFAULT 'unknown',
+///     team_id INTEGER REFERENCES team (id) ON DELETE CASCADE
+///   );
+/// "));
+/// let columns = codemelted::db_describe(&db_file, "person").unwrap();
+/// assert_eq!(columns.len(), 3);
+/// assert_eq!(columns[0]["name"], "id");
+/// assert_eq!(columns[0]["primary_key"], 1);
+/// assert_eq!(columns[1]["type"], "TEXT");
+/// assert_eq!(columns[1]["not_null"], true);
+/// assert_eq!(columns[1]["default"], "'unknown'");
+/// assert!(columns[1]["foreign_key"].is_null());
+/// assert_eq!(columns[2]["foreign_key"]["table"], "team");
+/// assert_eq!(columns[2]["foreign_key"]["on_delete"], "CASCADE");
+/// assert!(codemelted::db_describe(&db_file, "nothing").is_err());
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_describe(db_path: &str, table: &str) -> Result<CObject, CDbError> {
+  db_try_exists(db_path)?;
+  let conn = db_connect(db_path)?;
+  Ok(db_columns(&conn, table)?)
+}
+
+/// Ensures a database exists as any of the [db_query] and [db_update]
+/// functions will panic if the specified database does not exist. So this
+/// will aid in ensuring expected configurations. In-memory databases
+/// always exist and file: URIs are checked by their file path.
+///
+/// **Example:**
+/// ```no_run
+/// let db_file = format!("{}/test.db", codemelted::runtime_temp_path());
+/// assert!(!codemelted::db_exists(&db_file, false));
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_exists(db_path: &str, should_panic: bool) -> bool {
+  if db_is_memory(db_path) {
+    return true;
+  }
+  let file_path = match db_path.strip_prefix("file:") {
+    Some(v) => v.split('?').next().unwrap_or_default(),
+    None => db_path,
+  };
+  match CDiskOsFileSystem.exists(file_path, CDiskType::File) {
+    true => true,
+    false => {
+      if should_panic {
+        panic!("SyntaxError: {} database does not exist!", db_path);
+      }
+      false
+    },
+  }
+}
+
+/// Registers (Some) or removes (None) the encryption key of a database.
+/// Every connection the db_* functions and [db_open] make to the database
+/// then applies the key so the file is encrypted at rest via SQLCipher. A
+/// new database is encrypted when created, an existing plaintext database
+/// can't be opened with a key. Requires the crate's sqlcipher feature,
+/// without it an Err is returned and no key is registered.
+///
+/// **Example:**
+/// ```
+/// let db_file = format!("{}/test_db_key.db", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&db_file);
+/// #[cfg(feature = "sqlcipher")]
+/// {
+///   codemelted::db_key(&db_file, Some("secret")).unwrap();
+///   codemelted::db_manage(&db_file, true, Some(
+///     "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT)"
+///   ));
+///   let data = codemelted::disk_read_file(&db_file, false).unwrap();
+///   assert!(!data.as_bytes().unwrap().starts_with(b"SQLite format 3"));
+///   codemelted::db_key(&db_file, None).unwrap();
+///   assert!(codemelted::db_try_query(
+///     &db_file,
+///     "SELECT * FROM person",
+///     [],
+///     |row| row.get::<usize, i64>(0)
+///   ).is_err());
+/// }
+/// #[cfg(not(feature = "sqlcipher"))]
+/// {
+///   let why = codemelted::db_key(&db_file, Some("secret")).unwrap_err();
+///   assert_eq!(
+///     why.message(),
+///     "encryption requires the sqlcipher feature of the crate"
+///   );
+///   assert_eq!(why.sql(), Some("PRAGMA cipher_version"));
+/// }
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_key(db_path: &str, key: Option<&str>) -> Result<(), CDbError> {
+  let mut keys = DB_KEYS.lock().unwrap();
+  keys.retain(|v| v.0 != db_path);
+  if let Some(key) = key {
+    // Make sure the library can actually encrypt before accepting a key.
+    let conn = rusqlite::Connection::open_in_memory()?;
+    let sql = "PRAGMA cipher_version";
+    let supported = conn.prepare(sql)
+      .and_then(|mut stmt| stmt.exists([]))
+      .map_err(|why| CDbError::new(why, Some(sql)))?;
+    if !supported {
+      return Err(CDbError::new(
+        rusqlite::Error::SqliteFailure(
+          rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+          Some(String::from(
+            "encryption requires the sqlcipher feature of the crate"
+          ))
+        ),
+        Some(sql)
+      ));
+    }
+    keys.push((db_path.to_string(), key.to_string()));
+  }
+  Ok(())
+}
+
+/// Lists the names of the tables, views, indexes, or triggers defined in
+/// the database sorted by name. SQLite's internal tables and automatic
+/// indexes are not included.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CDbObjectType;
+///
+/// let db_file = format!("{}/test_db_list.db", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT UNIQUE);
+///   CREATE TABLE sqlite1_log (msg TEXT);
+///   CREATE INDEX person_name ON person (name);
+///   CREATE VIEW names AS SELECT name FROM person;
+/// "));
+/// let tables = codemelted::db_list(&db_file, CDbObjectType::Table).unwrap();
+/// assert_eq!(tables, ["person", "sqlite1_log"]);
+/// let indexes = codemelted::db_list(&db_file, CDbObjectType::Index).unwrap();
+/// assert_eq!(indexes, ["person_name"]);
+/// let views = codemelted::db_list(&db_file, CDbObjectType::View).unwrap();
+/// assert_eq!(views, ["names"]);
+/// assert!(
+///   codemelted::db_list(&db_file, CDbObjectType::Trigger).unwrap().is_empty()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_list(
+  db_path: &str,
+  kind: CDbObjectType
+) -> Result<Vec<String>, CDbError> {
+  db_try_query(
+    db_path,
+    "SELECT name FROM sqlite_schema
+      WHERE type = ?1 AND substr(name, 1, 7) <> 'sqlite_' ORDER BY name",
+    [kind.as_str()],
+    |row| row.get::<usize, String>(0)
+  )
+}
+
+/// Exports every row of the table to a CSV file replacing any existing
+/// file. NULL values are empty fields and BLOB values are their JSON array
+/// of byte values. Returns the number of rows written.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CDbConflict;
+///
+/// let temp_path = codemelted::runtime_temp_path();
+/// let db_file = format!("{}/test_db_export_csv.db", temp_path);
+/// let csv_file = format!("{}/test_db_export_csv.csv", temp_path);
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE device (id INTEGER PRIMARY KEY, name TEXT, zip TEXT);
+///   INSERT INTO device (name, zip) VALUES ('pump, main', '01234');
+///   INSERT INTO device (name, zip) VALUES (NULL, '98765');
+/// "));
+/// let rows = codemelted::db_export_csv(&db_file, "device", &csv_file, true)
+///   .unwrap();
+/// assert_eq!(rows, 2);
+/// let data = codemelted::disk_read_file(&csv_file, true).unwrap();
+/// assert_eq!(
+///   data.as_string().unwrap(),
+///   "id,name,zip\n1,\"pump, main\",01234\n2,,98765\n"
+/// );
+///
+/// // Import it into a new table inferring the column types.
+/// let rows = codemelted::db_import_csv(
+///   &db_file,
+///   "device_copy",
+///   &csv_file,
+///   true,
+///   100,
+///   CDbConflict::Fail
+/// ).unwrap();
+/// assert_eq!(rows, 2);
+/// let columns = codemelted::db_describe(&db_file, "device_copy").unwrap();
+/// assert_eq!(columns[0]["type"], "INTEGER");
+/// assert_eq!(columns[2]["type"], "TEXT");
+/// let result = codemelted::db_query_object(
+///   &db_file,
+///   "SELECT * FROM device_copy ORDER BY id",
+///   []
+/// );
+/// assert_eq!(result.rows()[0]["zip"], "01234");
+/// assert!(result.rows()[1]["name"].is_null());
+///
+/// // An empty file imports nothing.
+/// codemelted::disk_write_file(
+///   &csv_file,
+///   codemelted::CFileContents::String(String::new()),
+///   false
+/// ).unwrap();
+/// let rows = codemelted::db_import_csv(
+///   &db_file,
+///   "device_empty",
+///   &csv_file,
+///   false,
+///   100,
+///   CDbConflict::Fail
+/// ).unwrap();
+/// assert_eq!(rows, 0);
+/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&csv_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_export_csv(
+  db_path: &str,
+  table: &str,
+  csv_path: &str,
+  has_header: bool
+) -> Result<usize, CDbError> {
+  db_try_exists(db_path)?;
+  let conn = db_connect(db_path)?;
+  let sql = format!("SELECT * FROM {}", db_quote(table));
+  let mut writer = csv::Writer::from_path(csv_path)
+    .map_err(|why| CDbError::failure(
+      rusqlite::ffi::SQLITE_CANTOPEN,
+      &why.to_string()
+    ))?;
+  let io_error = |why: csv::Error| CDbError::failure(
+    rusqlite::ffi::SQLITE_IOERR,
+    &why.to_string()
+  );
+
+  let mut export = || -> Result<usize, CDbError> {
+    let mut stmt = conn.prepare(&sql)?;
+    let count = stmt.column_count();
+    if has_header {
+      writer.write_record(stmt.column_names()).map_err(io_error)?;
+    }
+    let mut rows = stmt.query([])?;
+    let mut written = 0;
+    while let Some(row) = rows.next()? {
+      let mut record = Vec::new();
+      for x in 0..count {
+        let value = CDbResultSet::to_object(row.get_ref(x)?);
+        record.push(match value {
+          CObject::Null => String::new(),
+          CObject::Short(_) | CObject::String(_) => value.to_string(),
+          _ => value.dump(),
+        });
+      }
+      writer.write_record(&record).map_err(io_error)?;
+      written += 1;
+    }
+    writer.flush().map_err(|why| io_error(csv::Error::from(why)))?;
+    Ok(written)
+  };
+  export().map_err(|why| CDbError::new(why.error, Some(&sql)))
+}
+
+/// Exports every row of the table to a JSON file as an array of objects
+/// keyed by column name (see [CDbResultSet]) replacing any existing file.
+/// Returns the number of rows written.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CDbConflict;
+///
+/// let temp_path = codemelted::runtime_temp_path();
+/// let db_file = format!("{}/test_db_export_json.db", temp_path);
+/// let json_file = format!("{}/test_db_export_json.json", temp_path);
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE device (id INTEGER PRIMARY KEY, name TEXT, data BLOB);
+///   INSERT INTO device (name, data) VALUES ('pump', x'0102');
+/// "));
+/// let rows = codemelted::db_export_json(&db_file, "device", &json_file)
+///   .unwrap();
+/// assert_eq!(rows, 1);
+/// let data = codemelted::disk_read_file(&json_file, true).unwrap();
+/// assert_eq!(
+///   data.as_string().unwrap(),
+///   r#"[{"id":1,"name":"pump","data":[1,2]}]"#
+/// );
+///
+/// // Importing it back ignores the row already in the table.
+/// let rows = codemelted::db_import_json(
+///   &db_file,
+///   "device",
+///   &json_file,
+///   100,
+///   CDbConflict::Ignore
+/// ).unwrap();
+/// assert_eq!(rows, 0);
+/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&json_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_export_json(
+  db_path: &str,
+  table: &str,
+  json_path: &str
+) -> Result<usize, CDbError> {
+  db_try_exists(db_path)?;
+  let conn = db_connect(db_path)?;
+  let sql = format!("SELECT * FROM {}", db_quote(table));
+  let result = CDbResultSet::new(&conn, &sql, [])
+    .map_err(|why| CDbError::new(why, Some(&sql)))?;
+  std::fs::write(json_path, result.as_json())
+    .map_err(|why| CDbError::failure(
+      rusqlite::ffi::SQLITE_CANTOPEN,
+      &format!("{}: {}", json_path, why)
+    ))?;
+  Ok(result.len())
+}
+
+/// Creates a FTS5 virtual table named {table}_fts indexing the specified
+/// columns of the content table along with the triggers keeping it in sync
+/// as rows are inserted, updated, or deleted via [db_update] or any other
+/// connection. Existing rows are indexed. The optional tokenize argument
+/// selects the FTS5 tokenizer (i.e. "porter unicode61"). An empty columns
+/// list is an Err.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CDbFtsQuery;
+///
+/// let db_file = format!("{}/test_db_fts.db", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE note (id INTEGER PRIMARY KEY, title TEXT, body TEXT);
+///   INSERT INTO note (title, body) VALUES ('Pump', 'The pump is leaking');
+/// "));
+/// assert!(codemelted::db_fts_create(&db_file, "note", &[], None).is_err());
+/// codemelted::db_fts_create(&db_file, "note", &["title", "body"], None)
+///   .unwrap();
+/// codemelted::db_update(
+///   &db_file,
+///   "INSERT INTO note (title, body) VALUES (?1, ?2)",
+///   ["Valve", "Replaced the valve and the pump seal"]
+/// );
+///
+/// let result = codemelted::db_fts_search(
+///   &db_file,
+///   "note",
+///   &CDbFtsQuery::new("pump").highlight("[", "]")
+/// ).unwrap();
+/// assert_eq!(result.len(), 2);
+/// assert_eq!(result.columns(), ["rowid", "rank", "title", "body", "snippet"]);
+/// assert_eq!(result.rows()[0]["rowid"], 1);
+/// assert_eq!(result.rows()[0]["title"], "[Pump]");
+/// assert_eq!(result.rows()[1]["body"], "Replaced the valve and the [pump] seal");
+///
+/// codemelted::db_update(&db_file, "DELETE FROM note WHERE id = 1", []);
+/// let result = codemelted::db_fts_search(
+///   &db_file,
+///   "note",
+///   &CDbFtsQuery::new("pump")
+/// ).unwrap();
+/// assert_eq!(result.len(), 1);
+/// codemelted::db_fts_rebuild(&db_file, "note").unwrap();
+/// codemelted::db_fts_drop(&db_file, "note").unwrap();
+/// assert!(codemelted::db_fts_search(
+///   &db_file,
+///   "note",
+///   &CDbFtsQuery::new("pump")
+/// ).is_err());
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_fts_create(
+  db_path: &str,
+  table: &str,
+  columns: &[&str],
+  tokenize: Option<&str>
+) -> Result<(), CDbError> {
+  if columns.is_empty() {
+    return Err(CDbError::failure(
+      rusqlite::ffi::SQLITE_MISUSE,
+      "db_fts_create requires at least one column"
+    ));
+  }
+  db_try_exists(db_path)?;
+  let fts = format!("{}_fts", table);
+  let names = columns.iter()
+    .map(|v| db_quote(v))
+    .collect::<Vec<String>>()
+    .join(", ");
+  let values = |prefix: &str| columns.iter()
+    .map(|v| format!("{}.{}", prefix, db_quote(v)))
+    .collect::<Vec<String>>()
+    .join(", ");
+  let tokenize = match tokenize {
+    Some(v) => format!(", tokenize = '{}'", v.replace('\'', "''")),
+    None => String::new(),
+  };
+
+  // Create the index and triggers then index any existing rows.
+  let sql = format!(
+    "BEGIN IMMEDIATE;
+    CREATE VIRTUAL TABLE {fts_q} USING fts5({names},
+      content = '{content}', content_rowid = 'rowid'{tokenize});
+    CREATE TRIGGER {ai} AFTER INSERT ON {table_q} BEGIN
+      INSERT INTO {fts_q} (rowid, {names})
+        VALUES (new.rowid, {new_values});
+    END;
+    CREATE TRIGGER {ad} AFTER DELETE ON {table_q} BEGIN
+      INSERT INTO {fts_q} ({fts_q}, rowid, {names})
+        VALUES ('delete', old.rowid, {old_values});
+    END;
+    CREATE TRIGGER {au} AFTER UPDATE ON {table_q} BEGIN
+      INSERT INTO {fts_q} ({fts_q}, rowid, {names})
+        VALUES ('delete', old.rowid, {old_values});
+      INSERT INTO {fts_q} (rowid, {names})
+        VALUES (new.rowid, {new_values});
+    END;
+    INSERT INTO {fts_q} ({fts_q}) VALUES ('rebuild');
+    COMMIT;",
+    fts_q = db_quote(&fts),
+    table_q = db_quote(table),
+    content = table.replace('\'', "''"),
+    ai = db_quote(&format!("{}_ai", fts)),
+    ad = db_quote(&format!("{}_ad", fts)),
+    au = db_quote(&format!("{}_au", fts)),
+    new_values = values("new"),
+    old_values = values("old"),
+  );
+  let conn = db_connect(db_path)?;
+  conn.execute_batch(&sql).map_err(|why| {
+    let _ = conn.execute_batch("ROLLBACK;");
+    CDbError::new(why, Some(&sql))
+  })
+}
+
+/// Drops the {table}_fts virtual table and sync triggers created by the
+/// [db_fts_create] function. The content table is not touched. See
+/// [db_fts_create] for an example.
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_fts_drop(db_path: &str, table: &str) -> Result<(), CDbError> {
+  db_try_exists(db_path)?;
+  let fts = format!("{}_fts", table);
+  let sql = format!(
+    "BEGIN IMMEDIATE;
+    DROP TRIGGER IF EXISTS {};
+    DROP TRIGGER IF EXISTS {};
+    DROP TRIGGER IF EXISTS {};
+    DROP TABLE IF EXISTS {};
+    COMMIT;",
+    db_quote(&format!("{}_ai", fts)),
+    db_quote(&format!("{}_ad", fts)),
+    db_quote(&format!("{}_au", fts)),
+    db_quote(&fts),
+  );
+  let conn = db_connect(db_path)?;
+  conn.execute_batch(&sql).map_err(|why| {
+    let _ = conn.execute_batch("ROLLBACK;");
+    CDbError::new(why, Some(&sql))
+  })
+}
+
+/// Rebuilds the {table}_fts index from the content table and merges its
+/// b-trees for faster queries. Use after bulk loads done with the triggers
+/// dropped or if the index gets out of sync. See [db_fts_create] for an
+/// example.
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_fts_rebuild(db_path: &str, table: &str) -> Result<(), CDbError> {
+  db_try_exists(db_path)?;
+  let fts = db_quote(&format!("{}_fts", table));
+  let sql = format!(
+    "INSERT INTO {fts} ({fts}) VALUES ('rebuild');
+    INSERT INTO {fts} ({fts}) VALUES ('optimize');"
+  );
+  let conn = db_connect(db_path)?;
+  conn.execute_batch(&sql).map_err(|why| CDbError::new(why, Some(&sql)))
+}
+
+/// Searches the {table}_fts index created by the [db_fts_create] function
+/// returning the matches ordered by bm25 rank (best first). Each row holds
+/// the rowid of the content table row, its rank, each indexed column with
+/// the matched terms highlighted, and a snippet of the best matching
+/// column. See [db_fts_create] for an example.
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_fts_search(
+  db_path: &str,
+  table: &str,
+  query: &CDbFtsQuery
+) -> Result<CDbResultSet, CDbError> {
+  db_try_exists(db_path)?;
+  let fts = format!("{}_fts", table);
+  let conn = db_connect(db_path)?;
+  let columns = db_columns(&conn, &fts)?;
+  let fts = db_quote(&fts);
+  let highlights = columns.members()
+    .enumerate()
+    .map(|(x, v)| format!(
+      "highlight({}, {}, ?2, ?3) AS {}",
+      fts,
+      x,
+      db_quote(v["name"].as_str().unwrap_or_default())
+    ))
+    .collect::<Vec<String>>()
+    .join(", ");
+  let sql = format!(
+    "SELECT {fts}.rowid AS rowid, rank, {highlights},
+      snippet({fts}, -1, ?2, ?3, ?4, ?5) AS snippet
+      FROM {fts} WHERE {fts} MATCH ?1 ORDER BY rank LIMIT ?6 OFFSET ?7"
+  );
+  CDbResultSet::new(&conn, &sql, rusqlite::params![
+    query.query,
+    query.open,
+    query.close,
+    query.ellipsis,
+    query.tokens as i64,
+    query.limit as i64,
+    query.offset as i64,
+  ]).map_err(|why| CDbError::new(why, Some(&sql)))
+}
+
+/// Imports the rows of a CSV file into the table inferring the type of each
+/// field (empty is NULL, then INTEGER, REAL, or TEXT). With has_header the
+/// first row names the columns, otherwise the columns are those of the
+/// existing table or column1..N for a new one. The table is created if it
+/// does not exist. Rows are inserted batch_size (0 for all) at a time with
+/// each batch in its own transaction so a failure only rolls back the
+/// current batch. Returns the number of rows inserted (0 for an empty file
+/// leaving the table as is). See [db_export_csv] for an example.
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_import_csv(
+  db_path: &str,
+  table: &str,
+  csv_path: &str,
+  has_header: bool,
+  batch_size: usize,
+  conflict: CDbConflict
+) -> Result<usize, CDbError> {
+  db_try_exists(db_path)?;
+  let parse_error = |why: csv::Error| CDbError::failure(
+    rusqlite::ffi::SQLITE_MISMATCH,
+    &format!("{}: {}", csv_path, why)
+  );
+  let mut reader = csv::ReaderBuilder::new()
+    .has_headers(has_header)
+    .from_path(csv_path)
+    .map_err(|why| CDbError::failure(
+      rusqlite::ffi::SQLITE_CANTOPEN,
+      &format!("{}: {}", csv_path, why)
+    ))?;
+
+  // Work out the columns of the import.
+  let columns: Vec<String> = if has_header {
+    reader.headers()
+      .map_err(parse_error)?
+      .iter()
+      .map(|v| v.trim().to_string())
+      .collect()
+  } else {
+    let width = match reader.headers() {
+      Ok(v) => v.len(),
+      Err(why) => return Err(parse_error(why)),
+    };
+    let conn = db_connect(db_path)?;
+    let existing = match db_columns(&conn, table) {
+      Ok(v) => v.members()
+        .map(|v| v["name"].to_string())
+        .collect::<Vec<String>>(),
+      Err(_) => (1..=width).map(|v| format!("column{}", v)).collect(),
+    };
+    existing.into_iter().take(width).collect()
+  };
+
+  let rows = reader.into_records().map(|record| {
+    let record = record.map_err(parse_error)?;
+    Ok(record.iter().map(db_infer_value).collect())
+  });
+  db_import(db_path, table, &columns, rows, batch_size, conflict)
+}
+
+/// Imports a JSON file holding an array of objects (like those written by
+/// [db_export_json]) into the table. The columns are the keys found across
+/// the objects. Numbers, strings, booleans (1 / 0), and null keep their
+/// type, arrays of byte values are BLOBs, and other arrays / objects are
+/// stored as JSON text. The table is created if it does not exist. Rows are
+/// inserted batch_size (0 for all) at a time with each batch in its own
+/// transaction. Returns the number of rows inserted. See [db_export_json]
+/// for an example.
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_import_json(
+  db_path: &str,
+  table: &str,
+  json_path: &str,
+  batch_size: usize,
+  conflict: CDbConflict
+) -> Result<usize, CDbError> {
+  db_try_exists(db_path)?;
+  let data = std::fs::read_to_string(json_path).map_err(|why| {
+    CDbError::failure(
+      rusqlite::ffi::SQLITE_CANTOPEN,
+      &format!("{}: {}", json_path, why)
+    )
+  })?;
+  let data = match json_parse(&data) {
+    Some(v) if v.is_array() => v,
+    _ => return Err(CDbError::failure(
+      rusqlite::ffi::SQLITE_MISMATCH,
+      &format!("{}: expected a JSON array of objects", json_path)
+    )),
+  };
+
+  // The columns are every key in the order first seen.
+  let mut columns = Vec::<String>::new();
+  for item in data.members() {
+    for (key, _) in item.entries() {
+      if !columns.iter().any(|v| v == key) {
+        columns.push(key.to_string());
+      }
+    }
+  }
+
+  let to_value = |value: &CObject| match value {
+    CObject::Null => rusqlite::types::Value::Null,
+    CObject::Boolean(v) => rusqlite::types::Value::Integer(*v as i64),
+    CObject::Number(_) => match value.as_i64() {
+      Some(v) if value.as_f64() == Some(v as f64) => {
+        rusqlite::types::Value::Integer(v)
+      },
+      _ => rusqlite::types::Value::Real(value.as_f64().unwrap_or_default()),
+    },
+    CObject::Short(_) | CObject::String(_) => {
+      rusqlite::types::Value::Text(value.to_string())
+    },
+    CObject::Array(v) if !v.is_empty()
+      && v.iter().all(|b| b.as_u8().is_some()) => {
+      rusqlite::types::Value::Blob(
+        v.iter().filter_map(|b| b.as_u8()).collect()
+      )
+    },
+    _ => rusqlite::types::Value::Text(value.dump()),
+  };
+  let rows = data.members().map(|item| {
+    Ok(columns.iter().map(|v| to_value(&item[v.as_str()])).collect())
+  });
+  db_import(db_path, table, &columns, rows, batch_size, conflict)
+}
+
+/// Runs PRAGMA integrity_check against the database returning each of the
+/// reported problems. A healthy database returns a single "ok" entry.
+///
+/// **Example:**
+/// ```
+/// let db_file = format!(
+///   "{}/test_db_integrity_check.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some(
+///   "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL)"
+/// ));
+/// let results = codemelted::db_integrity_check(&db_file).unwrap();
+/// assert_eq!(results, ["ok"]);
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_integrity_check(db_path: &str) -> Result<Vec<String>, CDbError> {
+  db_try_query(
+    db_path,
+    "PRAGMA integrity_check",
+    [],
+    |row| row.get::<usize, String>(0)
+  )
+}
+
+/// Builds the file: URI of a named in-memory database shared by every
+/// connection in the process. Use it as the db_path of any db_* function.
+/// The database lives as long as one connection to it remains open, so
+/// hold a [CDatabase] from [db_open] for its lifetime. A plain :memory:
+/// db_path is private to a single connection instead.
+///
+/// **Example:**
+/// ```
+/// let db_path = codemelted::db_memory("test_db_memory");
+/// assert!(codemelted::db_exists(&db_path, false));
+/// assert!(codemelted::db_exists("file::memory:?cache=shared", false));
+/// let db = codemelted::db_open(&db_path, true, 2, &[]).unwrap();
+/// db.manage("CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT)")
+///   .unwrap();
+/// codemelted::db_update(
+///   &db_path,
+///   "INSERT INTO person (name) VALUES (?1)",
+///   ["Steven"]
+/// );
+/// let rows = db.query("SELECT name FROM person", [], |row| {
+///   row.get::<usize, String>(0)
+/// }).unwrap();
+/// assert_eq!(rows, ["Steven"]);
+///
+/// // A plain :memory: database stays on the one connection holding it.
+/// let private = codemelted::db_open(":memory:", true, 4, &[]).unwrap();
+/// private.manage("CREATE TABLE note (body TEXT)").unwrap();
+/// std::thread::scope(|scope| {
+///   for _ in 0..4 {
+///     scope.spawn(|| {
+///       private.update("INSERT INTO note VALUES ('hi')", []).unwrap();
+///     });
+///   }
+/// });
+/// let rows = private.query("SELECT body FROM note", [], |row| {
+///   row.get::<usize, String>(0)
+/// }).unwrap();
+/// assert_eq!(rows.len(), 4);
+///
+/// // Once the last connection closes the database is gone.
+/// drop(db);
+/// let result = codemelted::db_try_query(
+///   &db_path,
+///   "SELECT name FROM person",
+///   [],
+///   |row| row.get::<usize, String>(0)
+/// );
+/// assert!(result.is_err());
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_memory(name: &str) -> String {
+  format!("file:{}?mode=memory&cache=shared", name)
+}
+
+/// Manages the sqlite3 database on disk by creating the initial database
+/// if it does not already exist along with execute Data Definition Language
+/// SQL statements to manage the database. The sql can be a script of
+/// multiple statements separated by semicolons.
+///
+/// **Example:**
+/// ```no_run
+/// let db_file = format!("{}/test.db", codemelted::runtime_temp_path());
+/// assert!(!codemelted::db_exists(&db_file, false));
+/// codemelted::db_manage(&db_file, true, None);
+/// assert!(codemelted::db_exists(&db_file, false));
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+///
+/// **Example (SQL Script):**
+/// ```
+/// let db_file = format!("{}/test_db_script.db", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE device (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+///   CREATE INDEX device_name ON device (name);
+/// "));
+/// let rows = codemelted::db_query(
+///   &db_file,
+///   "SELECT name FROM sqlite_master WHERE type = 'index'",
+///   [],
+///   |row| row.get::<usize, String>(0)
+/// );
+/// assert_eq!(rows.len(), 1);
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_manage(db_path: &str, create_db: bool, sql: Option<&str>) {
+  // If not creating the database on connection, check to make sure
+  // it is where we expect.
+  if !create_db {
+    db_exists(db_path, true);
+  }
+
+  // Alrighty, time to go carry out the DDL statement and panic if
+  // something about it fails.
+  match db_connect(db_path) {
+    Ok(conn) => {
+      match sql {
+        // Welp, we were asked to perform the DDL statement. Go do it
+        // and see what happens.
+        Some(stmt) => {
+          match conn.execute_batch(stmt) {
+            Ok(_) => {},
+            Err(why) => panic!("SyntaxError: db_manage {}", why),
+          }
+        },
+        // The sql was None so it may have been to just create the
+        // database.
+        None => {},
+      }
+    },
+    Err(why) => panic!("SyntaxError: db_manage {}", why),
+  };
+}
+
+/// Migrates the schema of the sqlite database (creating it if it does not
+/// exist) to the target_version via the ordered [CDbMigration] items. None
+/// targets the latest version. Migrating up runs each pending up script
+/// and migrating down runs the down scripts of the applied versions above
+/// the target in reverse order. Each step runs within its own transaction
+/// so a failing step is rolled back and stops the migration. The version
+/// is tracked via the [CDbMigrationTracker]. A dry_run returns the plan
+/// without changing the database.
+///
+/// Returns the versions applied (up) or reverted (down) in the order they
+/// were run. An Err is returned without changing the database if migration
+/// versions are duplicated or a down script is needed but not provided. A
+/// dry run never changes the database.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDbMigration, CDbMigrationTracker, CDbObjectType};
+///
+/// let db_file = format!("{}/test_db_migrate.db", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&db_file);
+/// let migrations = [
+///   CDbMigration::new(
+///     1,
+///     "create_device",
+///     "CREATE TABLE device (id INTEGER PRIMARY KEY, name TEXT);",
+///     Some("DROP TABLE device;")
+///   ),
+///   CDbMigration::new(
+///     2,
+///     "add_serial",
+///     "ALTER TABLE device ADD COLUMN serial TEXT;",
+///     Some("ALTER TABLE device DROP COLUMN serial;")
+///   ),
+/// ];
+///
+/// // See what is pending, then migrate to the latest version.
+/// let plan = codemelted::db_migrate(
+///   &db_file, &migrations, CDbMigrationTracker::UserVersion, None, true
+/// ).unwrap();
+/// assert_eq!(plan, vec![1, 2]);
+/// let applied = codemelted::db_migrate(
+///   &db_file, &migrations, CDbMigrationTracker::UserVersion, None, false
+/// ).unwrap();
+/// assert_eq!(applied, vec![1, 2]);
+///
+/// // Roll back to version 1.
+/// let reverted = codemelted::db_migrate(
+///   &db_file, &migrations, CDbMigrationTracker::UserVersion, Some(1), false
+/// ).unwrap();
+/// assert_eq!(reverted, vec![2]);
+///
+/// // A dry run leaves the database as is and bad migrations are an Err.
+/// let plan = codemelted::db_migrate(
+///   &db_file, &migrations, CDbMigrationTracker::Table, None, true
+/// ).unwrap();
+/// assert_eq!(plan, vec![1, 2]);
+/// let tables = codemelted::db_list(&db_file, CDbObjectType::Table).unwrap();
+/// assert_eq!(tables, ["device"]);
+/// let duplicated = [migrations[0].clone(), migrations[0].clone()];
+/// assert!(codemelted::db_migrate(
+///   &db_file, &duplicated, CDbMigrationTracker::UserVersion, None, false
+/// ).is_err());
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+///
+/// **Example (Directory of .sql Files):**
+/// ```
+/// use codemelted::{CDbMigration, CDbMigrationTracker, CFileContents};
+///
+/// let dir = format!("{}/test_db_migrations", codemelted::runtime_temp_path());
+/// let db_file = format!("{}/app.db", dir);
+/// let _ = codemelted::disk_rm(&dir);
+/// codemelted::disk_mkdir(&dir).unwrap();
+/// codemelted::disk_write_file(
+///   &format!("{}/001_create_note.sql", dir),
+///   CFileContents::String("CREATE TABLE note (body TEXT);".to_string()),
+///   false
+/// ).unwrap();
+/// codemelted::disk_write_file(
+///   &format!("{}/001_create_note.down.sql", dir),
+///   CFileContents::String("DROP TABLE note;".to_string()),
+///   false
+/// ).unwrap();
+///
+/// let migrations = CDbMigration::from_dir(&dir).unwrap();
+/// assert_eq!(migrations.len(), 1);
+/// let applied = codemelted::db_migrate(
+///   &db_file, &migrations, CDbMigrationTracker::Table, None, false
+/// ).unwrap();
+/// assert_eq!(applied, vec![1]);
+/// let _ = codemelted::disk_rm(&dir);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_migrate(
+  db_path: &str,
+  migrations: &[CDbMigration],
+  tracker: CDbMigrationTracker,
+  target_version: Option<u32>,
+  dry_run: bool,
+) -> Result<Vec<u32>, rusqlite::Error> {
+  // Order the migrations and make sure they make sense.
+  let mut migrations: Vec<&CDbMigration> = migrations.iter().collect();
+  migrations.sort_by_key(|m| m.version);
+  let invalid = |message: String| rusqlite::Error::SqliteFailure(
+    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+    Some(message)
+  );
+  for pair in migrations.windows(2) {
+    if pair[0].version == pair[1].version {
+      return Err(invalid(format!(
+        "db_migrate duplicate migration version {}",
+        pair[0].version
+      )));
+    }
+  }
+  let latest = migrations.last().map(|m| m.version).unwrap_or(0);
+  let target = target_version.unwrap_or(latest);
+
+  // Create the database if necessary and find where it is at.
+  if !db_exists(db_path, false) {
+    if dry_run {
+      return Ok(migrations.iter()
+        .filter(|m| m.version <= target)
+        .map(|m| m.version)
+        .collect());
+    }
+    db_manage(db_path, true, None);
+  }
+  let conn = db_connect(db_path)?;
+  let current = tracker.version(&conn)?;
+
+  // Build the plan of steps to carry out.
+  let (steps, applying): (Vec<&CDbMigration>, bool) = if target >= current {
+    (
+      migrations.iter()
+        .filter(|m| m.version > current && m.version <= target)
+        .copied()
+        .collect(),
+      true,
+    )
+  } else {
+    (
+      migrations.iter()
+        .rev()
+        .filter(|m| m.version <= current && m.version > target)
+        .copied()
+        .collect(),
+      false,
+    )
+  };
+  let missing_down = steps.iter().find(|m| !applying && m.down.is_none());
+  if let Some(m) = missing_down {
+    return Err(invalid(format!(
+      "db_migrate migration {} has no down script",
+      m.version
+    )));
+  }
+  if dry_run {
+    return Ok(steps.iter().map(|m| m.version).collect());
+  }
+  tracker.prepare(&conn)?;
+
+  // Now carry out each step within its own transaction.
+  let mut completed = Vec::new();
+  for step in steps {
+    let (sql, version) = if applying {
+      (step.up.as_str(), step.version)
+    } else {
+      let previous = migrations.iter()
+        .filter(|m| m.version < step.version)
+        .map(|m| m.version)
+        .next_back()
+        .unwrap_or(0);
+      (step.down.as_deref().unwrap_or_default(), previous)
+    };
+    conn.execute_batch("BEGIN IMMEDIATE;")?;
+    let result = conn.execute_batch(sql)
+      .and_then(|_| tracker.record(&conn, step, applying, version));
+    match result {
+      Ok(_) => conn.execute_batch("COMMIT;")?,
+      Err(why) => {
+        let _ = conn.execute_batch("ROLLBACK;");
+        return Err(why);
+      },
+    }
+    completed.push(step.version);
+  }
+  Ok(completed)
+}
+
+/// Opens a [CDatabase] holding a pool of up to pool_size persistent
+/// connections to the sqlite database with each [CDbPragma] applied as the
+/// connections are opened. Unlike [db_query] / [db_update], which connect on
+/// every call, the pool keeps the connections open and can be shared
+/// between threads. An error is returned if the database can't be opened
+/// (or create_db is false and it does not exist) or a PRAGMA fails. A
+/// private in-memory database (i.e. :memory:) exists only on the one
+/// connection holding it so its pool is limited to that connection. Use
+/// [db_memory] for an in-memory database shared across the pool.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDatabase, CDbPragma, CProtocolHandler};
+///
+/// let db_file = format!("{}/test_db_open.db", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&db_file);
+/// let db = codemelted::db_open(&db_file, true, 4, &[
+///   CDbPragma::WalMode(true),
+///   CDbPragma::BusyTimeout(5000),
+///   CDbPragma::ForeignKeys(true),
+/// ]).unwrap();
+/// db.manage("CREATE TABLE log (id INTEGER PRIMARY KEY, msg TEXT)").unwrap();
+///
+/// // Share the pool with a background worker.
+/// fn worker_cb(db: Option<CDatabase>) -> Option<CDatabase> {
+///   let db = db.unwrap();
+///   db.update("INSERT INTO log (msg) VALUES (?1)", ["from worker"]).unwrap();
+///   Some(db)
+/// }
+/// let mut worker = codemelted::async_worker("db_worker", worker_cb);
+/// let _ = worker.post_message(Some(db.clone()));
+/// db.update("INSERT INTO log (msg) VALUES (?1)", ["from main"]).unwrap();
+///
+/// // Wait for the worker's reply before shutting it down.
+/// while worker.get_message(None).unwrap().is_none() {
+///   codemelted::async_sleep(10);
+/// }
+/// worker.terminate();
+///
+/// let rows = db.query("SELECT msg FROM log", [], |row| {
+///   row.get::<usize, String>(0)
+/// }).unwrap();
+/// assert_eq!(rows.len(), 2);
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+///
+/// **Example (Transactions):**
+/// ```
+/// let db_file = format!("{}/test_db_tx.db", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&db_file);
+/// let db = codemelted::db_open(&db_file, true, 1, &[]).unwrap();
+/// db.batch("
+///   CREATE TABLE account (name TEXT PRIMARY KEY, balance INTEGER);
+///   INSERT INTO account VALUES ('a', 100), ('b', 0);
+/// ").unwrap();
+///
+/// // Bulk insert reusing one prepared statement.
+/// let rows = db.update_many(
+///   "INSERT INTO account VALUES (?1, ?2)",
+///   [("c", 1), ("d", 2), ("e", 3)]
+/// ).unwrap();
+/// assert_eq!(rows, 3);
+///
+/// // A failure rolls the whole transaction back.
+/// let result = db.transaction(|tx| {
+///   tx.update("UPDATE account SET balance = balance - 50 WHERE name = 'a'", [])?;
+///   tx.update("INSERT INTO account VALUES ('a', 0)", [])
+/// });
+/// assert!(result.is_err());
+///
+/// // A failed savepoint only rolls back its own work.
+/// db.transaction(|tx| {
+///   tx.update("UPDATE account SET balance = balance - 50 WHERE name = 'a'", [])?;
+///   let _ = tx.savepoint(|sp| sp.update("INSERT INTO account VALUES ('b', 0)", []));
+///   tx.update("UPDATE account SET balance = balance + 50 WHERE name = 'b'", [])
+/// }).unwrap();
+///
+/// let balances = db.query(
+///   "SELECT balance FROM account WHERE name IN ('a', 'b') ORDER BY name",
+///   [],
+///   |row| row.get::<usize, i64>(0)
+/// ).unwrap();
+/// assert_eq!(balances, vec![50, 50]);
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+///
+/// **Example (Change Notifications):**
+/// ```
+/// use codemelted::{CDbChangeEvent, CDbChangeOperation, CProtocolHandler};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static COMMITS: AtomicUsize = AtomicUsize::new(0);
+/// fn on_change(event: CDbChangeEvent) {
+///   if event.operation() == CDbChangeOperation::Commit {
+///     COMMITS.fetch_add(1, Ordering::SeqCst);
+///   }
+/// }
+///
+/// let db_file = format!(
+///   "{}/test_db_changes.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// let db = codemelted::db_open(&db_file, true, 2, &[]).unwrap();
+/// db.manage("CREATE TABLE device (id INTEGER PRIMARY KEY, name TEXT)")
+///   .unwrap();
+/// let subscription = db.subscribe(on_change);
+/// let mut changes = db.subscribe_protocol("device_changes");
+///
+/// // Committed changes along with a savepoint that was rolled back.
+/// db.update("INSERT INTO device (name) VALUES ('pump')", []).unwrap();
+/// db.transaction(|tx| {
+///   tx.update("UPDATE device SET name = 'valve' WHERE id = 1", [])?;
+///   let _ = tx.savepoint(|sp| {
+///     sp.update("DELETE FROM device WHERE id = 1", [])?;
+///     sp.update("INSERT INTO nothing VALUES (1)", [])
+///   });
+///   Ok(())
+/// }).unwrap();
+///
+/// // A transaction that is rolled back.
+/// let _ = db.transaction(|tx| {
+///   tx.update("DELETE FROM device WHERE id = 1", [])?;
+///   tx.update("INSERT INTO nothing VALUES (1)", [])
+/// });
+///
+/// let mut events = Vec::new();
+/// while let Some(event) = changes.get_message(None).unwrap() {
+///   events.push((event.operation(), event.table(), event.rowid()));
+/// }
+/// assert_eq!(events, [
+///   (CDbChangeOperation::Insert, String::from("device"), 1),
+///   (CDbChangeOperation::Commit, String::new(), 0),
+///   (CDbChangeOperation::Update, String::from("device"), 1),
+///   (CDbChangeOperation::Commit, String::new(), 0),
+///   (CDbChangeOperation::Rollback, String::new(), 0),
+/// ]);
+/// assert_eq!(COMMITS.load(Ordering::SeqCst), 2);
+///
+/// db.unsubscribe(subscription);
+/// changes.terminate();
+/// assert!(!changes.is_running());
+/// assert!(changes.post_message(None).is_err());
+/// drop(db);
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_open(
+  db_path: &str,
+  create_db: bool,
+  pool_size: usize,
+  pragmas: &[CDbPragma]
+) -> Result<CDatabase, rusqlite::Error> {
+  CDatabase::new(db_path, create_db, pool_size, pragmas)
+}
+
+/// Will query a table specified by the db_path to retrieve the data
+/// as a vector of the given type. This represents the Data Query
+/// Language (DQL) SELECT statement.
+///
+/// **Example:**
+/// ```
+/// // Create the database file and table.
+/// let db_file = format!("{}/test.db", codemelted::runtime_temp_path());
+/// assert!(!codemelted::db_exists(&db_file, false));
+/// let sql = "CREATE TABLE person (
+///   id    INTEGER PRIMARY KEY,
+///   name  TEXT NOT NULL,
+///   data  BLOB
+/// )";
+/// codemelted::db_manage(&db_file, true, Some(sql));
+/// assert!(codemelted::db_exists(&db_file, false));
+///
+/// // Go add a person to the database.
+/// #[derive(Debug)]
+/// struct Person {
+///   id: i32,
+///   name: String,
+///   data: Option<Vec<u8>>,
+/// }
+///
+/// let sql = "INSERT INTO person (name, data) VALUES (?1, ?2)";
+/// let me = Person {
+///    id: 0,
+///    name: "Steven".to_string(),
+///    data: None,
+/// };
+/// let rows = codemelted::db_update(
+///   &db_file,
+///   sql,
+///   (&me.name, &me.data)
+/// );
+/// assert!(rows == 1);
+///
+/// // Now lets query the data back
+/// let sql = "SELECT id, name, data FROM person";
+/// let row_data = codemelted::db_query(
+///   &db_file,
+///   sql,
+///   [],
+///   |row| {
+///     Ok(Person {
+///       id: row.get(0)?,
+///       name: row.get(1)?,
+///       data: row.get(2)?,
+///     })
+///   }
+/// );
+/// assert!(row_data.len() == 1);
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_query<
+    P: rusqlite::Params,
+    T,
+    F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>>(
+      db_path: &str,
+      sql: &str,
+      params: P,
+      f: F
+) -> Vec<T> {
+  // Ensure database exists and then carry out the query.
+  db_exists(db_path, true);
+  match db_try_query(db_path, sql, params, f) {
+    Ok(v) => v,
+    Err(why) => panic!("SyntaxError: db_query {}", why),
+  }
+}
+
+/// Queries the database building a [CDbRowMapper] struct from each row.
+/// The struct picks its fields by column name so no derive macro or SELECT
+/// ordering is needed. Panics like [db_query] on any error.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CDbRowMapper;
+///
+/// struct Person {
+///   id: i64,
+///   name: String,
+/// }
+/// impl CDbRowMapper for Person {
+///   fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+///     Ok(Person { id: row.get("id")?, name: row.get("name")? })
+///   }
+/// }
+///
+/// let db_file = format!(
+///   "{}/test_db_query_as.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+///   INSERT INTO person (name) VALUES ('Steven');
+/// "));
+/// let people = codemelted::db_query_as::<_, Person>(
+///   &db_file,
+///   "SELECT name, id FROM person",
+///   []
+/// );
+/// assert_eq!(people.len(), 1);
+/// assert_eq!(people[0].id, 1);
+/// assert_eq!(people[0].name, "Steven");
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_query_as<P: rusqlite::Params, T: CDbRowMapper>(
+  db_path: &str,
+  sql: &str,
+  params: P
+) -> Vec<T> {
+  db_query(db_path, sql, params, T::from_row)
+}
+
+/// Queries the database returning a [CDbResultSet] of [CObject] rows keyed
+/// by column name. It can be exported via [CDbResultSet::as_json] or the
+/// [CCsvFormat] trait. Panics like [db_query] on any error.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CCsvFormat;
+///
+/// let db_file = format!(
+///   "{}/test_db_query_object.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT, data BLOB);
+///   INSERT INTO person (name, data) VALUES ('Steven, Jr.', x'0102');
+///   INSERT INTO person (name, data) VALUES (NULL, NULL);
+/// "));
+/// let result = codemelted::db_query_object(
+///   &db_file,
+///   "SELECT * FROM person ORDER BY id",
+///   []
+/// );
+/// assert_eq!(result.len(), 2);
+/// assert_eq!(result.columns(), ["id", "name", "data"]);
+/// assert_eq!(result.rows()[0]["id"], 1);
+/// assert_eq!(result.rows()[0]["data"][1], 2);
+/// assert!(result.rows()[1]["name"].is_null());
+/// assert_eq!(
+///   result.as_json(),
+///   r#"[{"id":1,"name":"Steven, Jr.","data":[1,2]},{"id":2,"name":null,"data":null}]"#
+/// );
+/// assert_eq!(result.csv_header(), "id,name,data");
+/// assert_eq!(result.as_csv(), "1,\"Steven, Jr.\",\"[1,2]\"\n2,,\n");
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_query_object<P: rusqlite::Params>(
+  db_path: &str,
+  sql: &str,
+  params: P
+) -> CDbResultSet {
+  // Ensure database exists and we can connect to it.
+  db_exists(db_path, true);
+  match db_connect(db_path) {
+    Ok(conn) => match CDbResultSet::new(&conn, sql, params) {
+      Ok(v) => v,
+      Err(why) => panic!("SyntaxError: db_query_object {}", why),
+    },
+    // Failed to make the database connection.
+    Err(why) => panic!("SyntaxError: db_query_object {}", why),
+  }
+}
+
+/// Restores the db_path database from a backup_path file made via
+/// [db_backup] or [db_snapshot]. The backup_path file is only opened for
+/// reading. The database is created if it does not exist and its contents
+/// replaced if it does. Connections held on it while restoring will cause a
+/// busy error.
+///
+/// **Example:**
+/// ```
+/// let temp_path = codemelted::runtime_temp_path();
+/// let db_file = format!("{}/test_db_restore.db", temp_path);
+/// let backup_file = format!("{}/test_db_restore.bak", temp_path);
+/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&backup_file);
+/// codemelted::db_manage(&db_file, true, Some("
+///   CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+///   INSERT INTO person (name) VALUES ('Steven');
+/// "));
+/// codemelted::db_backup(&db_file, &backup_file, None).unwrap();
+/// codemelted::db_update(&db_file, "DELETE FROM person", []);
+///
+/// codemelted::db_restore(&backup_file, &db_file, None).unwrap();
+/// let rows = codemelted::db_query(
+///   &db_file,
+///   "SELECT name FROM person",
+///   [],
+///   |row| row.get::<usize, String>(0)
+/// );
+/// assert_eq!(rows, ["Steven"]);
+/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&backup_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_restore(
+  backup_path: &str,
+  db_path: &str,
+  progress: Option<CDbBackupCB>
+) -> Result<(), CDbError> {
+  db_try_exists(backup_path)?;
+  let src = db_connect_with(
+    backup_path,
+    rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY
+      | rusqlite::OpenFlags::SQLITE_OPEN_URI
+  )?;
+  let mut dest = db_connect(db_path)?;
+  db_copy(&src, &mut dest, progress)?;
+  Ok(())
+}
+
+/// Compares the schema of database a against database b. The result
+/// [CObject] holds the added (only in b), removed (only in a), and changed
+/// (differing SQL) schema objects. Each changed table also identifies the
+/// columns added, removed, or changed between the two.
+///
+/// **Example:**
+/// ```
+/// let temp_path = codemelted::runtime_temp_path();
+/// let a_file = format!("{}/test_db_schema_diff_a.db", temp_path);
+/// let b_file = format!("{}/test_db_schema_diff_b.db", temp_path);
+/// let _ = codemelted::disk_rm(&a_file);
+/// let _ = codemelted::disk_rm(&b_file);
+/// codemelted::db_manage(&a_file, true, Some("
+///   CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT);
+///   CREATE TABLE legacy (id INTEGER PRIMARY KEY);
+/// "));
+/// codemelted::db_manage(&b_file, true, Some("
+///   CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL,
+///     email TEXT);
+///   CREATE INDEX person_email ON person (email);
+/// "));
+/// let diff = codemelted::db_schema_diff(&a_file, &b_file).unwrap();
+/// assert_eq!(diff["added"][0]["name"], "person_email");
+/// assert_eq!(diff["removed"][0]["name"], "legacy");
+/// assert_eq!(diff["changed"][0]["name"], "person");
+/// assert_eq!(diff["changed"][0]["columns"]["added"][0], "email");
+/// assert_eq!(diff["changed"][0]["columns"]["changed"][0], "name");
+/// assert!(diff["changed"][0]["columns"]["removed"].is_empty());
+///
+/// let diff = codemelted::db_schema_diff(&a_file, &a_file).unwrap();
+/// assert!(diff["changed"].is_empty());
+/// let _ = codemelted::disk_rm(&a_file);
+/// let _ = codemelted::disk_rm(&b_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_schema_diff(a_path: &str, b_path: &str) -> Result<CObject, CDbError> {
+  db_try_exists(a_path)?;
+  db_try_exists(b_path)?;
+  let a_conn = db_connect(a_path)?;
+  let b_conn = db_connect(b_path)?;
+  let a_schema = db_schema(&a_conn)?;
+  let b_schema = db_schema(&b_conn)?;
+
+  // Whitespace is not significant when comparing the SQL definitions.
+  let normalize = |sql: &str| sql.split_whitespace()
+    .collect::<Vec<&str>>()
+    .join(" ");
+  let find = |schema: &[(String, String, String)], kind: &str, name: &str| {
+    schema.iter()
+      .find(|v| v.0 == kind && v.1 == name)
+      .map(|v| v.2.clone())
+  };
+  let entry = |kind: &str, name: &str, sql: &str| json::object!{
+    "type": kind,
+    "name": name,
+    "sql": sql,
+  };
+
+  let mut diff = json::object!{
+    "added": [],
+    "removed": [],
+    "changed": [],
+  };
+  for (kind, name, sql) in &b_schema {
+    if find(&a_schema, kind, name).is_none() {
+      let _ = diff["added"].push(entry(kind, name, sql));
+    }
+  }
+  for (kind, name, a_sql) in &a_schema {
+    let Some(b_sql) = find(&b_schema, kind, name) else {
+      let _ = diff["removed"].push(entry(kind, name, a_sql));
+      continue;
+    };
+    if normalize(a_sql) == normalize(&b_sql) {
+      continue;
+    }
+    let mut changed = json::object!{
+      "type": kind.as_str(),
+      "name": name.as_str(),
+      "from": a_sql.as_str(),
+      "to": b_sql.as_str(),
+    };
+    if kind == "table" {
+      let a_columns = db_columns(&a_conn, name)?;
+      let b_columns = db_columns(&b_conn, name)?;
+      let column = |columns: &CObject, name: &CObject| {
+        columns.members().find(|v| v["name"] == *name).cloned()
+      };
+      let mut columns = json::object!{
+        "added": [],
+        "removed": [],
+        "changed": [],
+      };
+      for b in b_columns.members() {
+        match column(&a_columns, &b["name"]) {
+          None => {
+            let _ = columns["added"].push(b["name"].clone());
+          },
+          Some(a) if a != *b => {
+            let _ = columns["changed"].push(b["name"].clone());
+          },
+          _ => {},
+        }
+      }
+      for a in a_columns.members() {
+        if column(&b_columns, &a["name"]).is_none() {
+          let _ = columns["removed"].push(a["name"].clone());
+        }
+      }
+      changed["columns"] = columns;
+    }
+    let _ = diff["changed"].push(changed);
+  }
+  Ok(diff)
+}
+
+/// Writes a compacted, transactionally consistent snapshot of the database
+/// to the dest file via VACUUM INTO. The dest file must not already exist.
+///
+/// **Example:**
+/// ```
+/// let temp_path = codemelted::runtime_temp_path();
+/// let db_file = format!("{}/test_db_snapshot.db", temp_path);
+/// let snapshot_file = format!("{}/test_db_snapshot.snap", temp_path);
+/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&snapshot_file);
+/// codemelted::db_manage(&db_file, true, Some(
+///   "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL)"
+/// ));
+/// codemelted::db_snapshot(&db_file, &snapshot_file).unwrap();
+/// assert_eq!(
+///   codemelted::db_integrity_check(&snapshot_file).unwrap(),
+///   ["ok"]
+/// );
+/// assert!(codemelted::db_snapshot(&db_file, &snapshot_file).is_err());
+/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&snapshot_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_snapshot(db_path: &str, dest_path: &str) -> Result<(), CDbError> {
+  db_try_exists(db_path)?;
+  let sql = "VACUUM INTO ?1";
+  let conn = db_connect(db_path)?;
+  conn.execute(sql, [dest_path])
+    .map_err(|why| CDbError::new(why, Some(sql)))?;
+  Ok(())
+}
+
+/// The non-panicking version of [db_exists] with should_panic set to true.
+/// Returns a [CDbError] with a SQLITE_CANTOPEN code when the database does
+/// not exist.
+///
+/// **Example:**
+/// ```
+/// let db_file = format!(
+///   "{}/test_db_try_exists.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let why = codemelted::db_try_exists(&db_file).unwrap_err();
+/// assert_eq!(why.code(), Some(rusqlite::ErrorCode::CannotOpen));
+/// assert!(why.sql().is_none());
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_try_exists(db_path: &str) -> Result<(), CDbError> {
+  if db_exists(db_path, false) {
+    return Ok(());
+  }
+  Err(CDbError::from(rusqlite::Error::SqliteFailure(
+    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+    Some(format!("{} database does not exist!", db_path))
+  )))
+}
+
+/// The non-panicking version of [db_query] returning a [CDbError] on any
+/// failure to connect, prepare, bind, or map the query.
+///
+/// **Example:**
+/// ```
+/// let db_file = format!(
+///   "{}/test_db_try_query.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some(
+///   "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL)"
+/// ));
+/// let why = codemelted::db_try_query(
+///   &db_file,
+///   "SELECT age FROM person",
+///   [],
+///   |row| row.get::<usize, i64>(0)
+/// ).unwrap_err();
+/// assert_eq!(why.sql(), Some("SELECT age FROM person"));
+/// assert!(why.message().contains("no such column"));
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_try_query<
+    P: rusqlite::Params,
+    T,
+    F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>>(
+      db_path: &str,
+      sql: &str,
+      params: P,
+      f: F
+) -> Result<Vec<T>, CDbError> {
+  // Ensure database exists and we can connect to it.
+  db_try_exists(db_path)?;
+  let conn = db_connect(db_path)?;
+
+  // Prepare, bind, and map the query capturing the SQL with any failure.
+  let query = || -> Result<Vec<T>, rusqlite::Error> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params, f)?;
+    rows.collect()
+  };
+  query().map_err(|why| CDbError::new(why, Some(sql)))
+}
+
+/// The non-panicking version of [db_update] returning a [CDbError] on any
+/// failure allowing constraint violations and busy databases to be handled
+/// by the caller.
+///
+/// **Example:**
+/// ```
+/// let db_file = format!(
+///   "{}/test_db_try_update.db",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&db_file);
+/// codemelted::db_manage(&db_file, true, Some(
+///   "CREATE TABLE person (id INTEGER PRIMARY KEY, email TEXT UNIQUE)"
+/// ));
+/// let sql = "INSERT INTO person (email) VALUES (?1)";
+/// let rows = codemelted::db_try_update(&db_file, sql, ["me@example.com"]);
+/// assert_eq!(rows.unwrap(), 1);
+///
+/// let why = codemelted::db_try_update(&db_file, sql, ["me@example.com"])
+///   .unwrap_err();
+/// assert!(why.is_constraint());
+/// assert!(why.is_unique_violation());
+/// assert!(!why.is_busy());
+/// assert_eq!(why.constraint().unwrap(), "person.email");
+/// assert_eq!(why.sql(), Some(sql));
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_try_update<P: rusqlite::Params>(
+  db_path: &str,
+  sql: &str,
+  params: P
+) -> Result<usize, CDbError> {
+  // Ensure database exists and we can connect to it.
+  db_try_exists(db_path)?;
+  let conn = db_connect(db_path)?;
+
+  // Prepare and execute the statement capturing the SQL with any failure.
+  let update = || -> Result<usize, rusqlite::Error> {
+    let mut stmt = conn.prepare(sql)?;
+    stmt.execute(params)
+  };
+  update().map_err(|why| CDbError::new(why, Some(sql)))
+}
+
+/// Provides the ability Data Manipulation Language (DML) statements
+/// (i.e. INSERT, DELETE, or UPDATE) with the number of rows updated
+/// based on the transaction.
+///
+/// **Example:**
+/// ```no_run
+/// // Create the database file and table.
+/// let db_file = format!("{}/test.db", codemelted::runtime_temp_path());
+/// assert!(!codemelted::db_exists(&db_file, false));
+/// let sql = "CREATE TABLE person (
+///   id    INTEGER PRIMARY KEY,
+///   name  TEXT NOT NULL,
+///   data  BLOB
+/// )";
+/// codemelted::db_manage(&db_file, true, Some(sql));
+/// assert!(codemelted::db_exists(&db_file, false));
+///
+/// // Go add a person to the database.
+/// #[derive(Debug)]
+/// struct Person {
+///   id: i32,
+///   name: String,
+///   data: Option<Vec<u8>>,
+/// }
+///
+/// let sql = "INSERT INTO person (name, data) VALUES (?1, ?2)";
+/// let me = Person {
+///    id: 0,
+///    name: "Steven".to_string(),
+///    data: None,
+/// };
+/// let rows = codemelted::db_update(
+///   &db_file,
+///   sql,
+///   (&me.name, &me.data)
+/// );
+/// assert!(rows == 1);
+///
+/// // Now lets query the data back
+/// let sql = "SELECT id, name, data FROM person";
+/// let row_data = codemelted::db_query(
+///   &db_file,
+///   sql,
+///   [],
+///   |row| {
+///     Ok(Person {
+///       id: row.get(0)?,
+///       name: row.get(1)?,
+///       data: row.get(2)?,
+///     })
+///   }
+/// );
+/// assert!(row_data.len() == 1);
+/// let _ = codemelted::disk_rm(&db_file);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_update<P: rusqlite::Params>(
+  db_path: &str,
+  sql: &str,
+  params: P
+) -> usize {
+  // Ensure database exists and then carry out the update.
+  db_exists(db_path, true);
+  match db_try_update(db_path, sql, params) {
+    Ok(v) => v,
+    Err(why) => panic!("SyntaxError: db_update {}", why),
+  }
+}
+
+/// Determines the setup version of sqlite supported by the module
+///
+/// **Example:**
+/// ```
+/// assert!(codemelted::db_version().len() > 0);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn db_version() -> String {
+  rusqlite::version().to_string()
+}
+
+// ============================================================================
+// [DISK UC IMPLEMENTATION] ===================================================
+// ============================================================================
+
+/// Identifies the format of the archive created by [disk_archive] /
+/// extracted by [disk_extract].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDiskArchiveFormat {
+  /// An uncompressed tape archive.
+  Tar,
+  /// A tape archive compressed with gzip.
+  TarGz,
+  /// A tape archive compressed with zstandard.
+  TarZst,
+  /// A zip archive with deflate compression.
+  Zip,
+}
+impl CDiskArchiveFormat {
+  /// Identifies the format via the extension of the filename (.tar,
+  /// .tar.gz / .tgz, .tar.zst / .tzst, .zip).
+  pub fn from_filename(filename: &str) -> Option<CDiskArchiveFormat> {
+    let filename = filename.to_lowercase();
+    if filename.ends_with(".tar") {
+      Some(CDiskArchiveFormat::Tar)
+    } else if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+      Some(CDiskArchiveFormat::TarGz)
+    } else if filename.ends_with(".tar.zst") || filename.ends_with(".tzst") {
+      Some(CDiskArchiveFormat::TarZst)
+    } else if filename.ends_with(".zip") {
+      Some(CDiskArchiveFormat::Zip)
+    } else {
+      None
+    }
+  }
+}
+
+/// Options of the [disk_archive] / [disk_extract] calls. Defaults to all
+/// entries without a progress callback.
+#[derive(Clone, Debug, Default)]
+pub struct CDiskArchiveOptions {
+  includes: Vec<String>,
+  progress: Option<CDiskProgressCB>,
+}
+impl CDiskArchiveOptions {
+  /// Creates the default options.
+  pub fn new() -> CDiskArchiveOptions {
+    CDiskArchiveOptions::default()
+  }
+
+  /// Only archives / extracts the entries matching the glob (i.e. "*.log").
+  /// Call again to add further globs. A glob matches the path within the
+  /// archive or the entry's name.
+  pub fn include(mut self, glob: &str) -> CDiskArchiveOptions {
+    self.includes.push(CDiskWalker::validate(glob));
+    self
+  }
+
+  /// Sets the callback receiving the [CDiskProgress] after each file.
+  pub fn progress(mut self, progress: CDiskProgressCB) -> CDiskArchiveOptions {
+    self.progress = Some(progress);
+    self
+  }
+
+  /// True if the path within the archive is included.
+  fn is_included(&self, set: &globset::GlobSet, name: &str) -> bool {
+    self.includes.is_empty() || set.is_match(name)
+      || name.rsplit('/').next().is_some_and(|v| set.is_match(v))
+  }
+}
+
+/// Identifies how [disk_cp_with] / [disk_mv_with] handle a file that already
+/// exists at the destination.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDiskConflict {
+  /// Stops the copy / move with an [std::io::ErrorKind::AlreadyExists].
+  Fail,
+  /// Replaces the existing file.
+  Overwrite,
+  /// Keeps the existing file writing to "name (1).ext", "name (2).ext", etc.
+  Rename,
+  /// Keeps the existing file leaving the src file in place.
+  Skip,
+}
+
+/// The progress of a [disk_cp_with] / [disk_mv_with] / [disk_archive] /
+/// [disk_extract] call reported to the [CDiskProgressCB] after each file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CDiskProgress {
+  path: String,
+  files_done: usize,
+  files_total: usize,
+  bytes_done: u64,
+  bytes_total: u64,
+}
+impl CDiskProgress {
+  /// The bytes of the files processed so far.
+  pub fn bytes_done(&self) -> u64 {
+    self.bytes_done
+  }
+
+  /// The bytes of all the files to process.
+  pub fn bytes_total(&self) -> u64 {
+    self.bytes_total
+  }
+
+  /// The number of files processed so far (skipped files included).
+  pub fn files_done(&self) -> usize {
+    self.files_done
+  }
+
+  /// The number of files to process.
+  pub fn files_total(&self) -> usize {
+    self.files_total
+  }
+
+  /// The destination path of the last file processed.
+  pub fn path(&self) -> String {
+    self.path.to_string()
+  }
+
+  /// Adds the processed file sending the progress to the callback.
+  fn report(&mut self, path: &str, bytes: u64, cb: Option<CDiskProgressCB>) {
+    if let Some(progress) = cb {
+      self.path = path.to_string();
+      self.files_done += 1;
+      self.bytes_done += bytes;
+      progress(self.clone());
+    }
+  }
+}
+
+/// Callback to receive the [CDiskProgress] of a [disk_cp_with] /
+/// [disk_mv_with] / [disk_archive] / [disk_extract] call.
+pub type CDiskProgressCB = fn(CDiskProgress);
+
+/// Options of the [disk_cp_with] / [disk_mv_with] calls. Defaults to
+/// [CDiskConflict::Overwrite] preserving permissions and timestamps without
+/// a progress callback.
+#[derive(Clone, Debug)]
+pub struct CDiskCopyOptions {
+  conflict: CDiskConflict,
+  preserve: bool,
+  progress: Option<CDiskProgressCB>,
+}
+impl Default for CDiskCopyOptions {
+  fn default() -> Self {
+    CDiskCopyOptions::new()
+  }
+}
+impl CDiskCopyOptions {
+  /// Creates the default options.
+  pub fn new() -> CDiskCopyOptions {
+    CDiskCopyOptions {
+      conflict: CDiskConflict::Overwrite,
+      preserve: true,
+      progress: None,
+    }
+  }
+
+  /// Sets how a file already at the destination is handled.
+  pub fn conflict(mut self, conflict: CDiskConflict) -> CDiskCopyOptions {
+    self.conflict = conflict;
+    self
+  }
+
+  /// Sets whether the permissions and access / modified times of the files
+  /// and directories are carried over to the copies.
+  pub fn preserve(mut self, preserve: bool) -> CDiskCopyOptions {
+    self.preserve = preserve;
+    self
+  }
+
+  /// Sets the callback receiving the [CDiskProgress] after each file.
+  pub fn progress(mut self, progress: CDiskProgressCB) -> CDiskCopyOptions {
+    self.progress = Some(progress);
+    self
+  }
+}
+
+/// Carries out the recursive copy of the [CDiskFileSystem::disk_cp_with] /
+/// [CDiskFileSystem::disk_mv_with] calls removing the src files as they are
+/// copied when moving. Host files are streamed with their links carried
+/// over while other file systems copy what the links lead to.
+struct CDiskCopyTask<'a, F: CDiskFileSystem + ?Sized> {
+  fs: &'a F,
+  host: bool,
+  options: &'a CDiskCopyOptions,
+  progress: CDiskProgress,
+  remove_src: bool,
+}
+impl<'a, F: CDiskFileSystem + ?Sized> CDiskCopyTask<'a, F> {
+  /// Creates the task totaling the files to copy when progress is wanted.
+  /// The host flag marks the fs as the [CDiskOsFileSystem].
+  fn new(
+    fs: &'a F,
+    host: bool,
+    src: &str,
+    options: &'a CDiskCopyOptions,
+    remove_src: bool,
+  ) -> CDiskCopyTask<'a, F> {
+    let mut task = CDiskCopyTask {
+      fs,
+      host,
+      options,
+      progress: CDiskProgress::default(),
+      remove_src,
+    };
+    if options.progress.is_some() {
+      task.total(src);
+    }
+    task
+  }
+
+  /// Copies the src file / directory to the dest.
+  fn copy(&mut self, src: &str, dest: &str) -> Result<(), std::io::Error> {
+    let kind = self.fs.info(src, false)?.kind();
+    let mut dest = dest.to_string();
+    if kind == CFileKind::Directory {
+      let metadata = match self.host && self.options.preserve {
+        true => Some(std::fs::symlink_metadata(src)?),
+        false => None,
+      };
+      if self.fs.info(&dest, false).is_ok()
+          && !self.fs.exists(&dest, CDiskType::Directory) {
+        match self.resolve(&dest)? {
+          Some(v) => dest = v,
+          None => return Ok(()),
+        }
+      }
+      self.fs.create_dir_all(&dest)?;
+      for name in self.fs.read_dir(src)? {
+        let path = |v: &str| {
+          std::path::Path::new(v).join(&name).to_string_lossy().to_string()
+        };
+        self.copy(&path(src), &path(&dest))?;
+      }
+      if let Some(metadata) = metadata {
+        // Directories can't be opened for their times on all platforms.
+        if let Ok(dir) = std::fs::File::open(&dest) {
+          let _ = dir.set_times(CDiskCopyTask::<F>::times(&metadata));
+        }
+        std::fs::set_permissions(&dest, metadata.permissions())?;
+      }
+      if self.remove_src {
+        // Left behind when holding skipped files.
+        let _ = self.fs.remove_dir(src);
+      }
+      return Ok(());
+    }
+
+    if self.fs.info(&dest, false).is_ok() {
+      match self.resolve(&dest)? {
+        Some(v) => dest = v,
+        None => return Ok(()),
+      }
+    }
+    let bytes = match self.host {
+      true => self.copy_host(src, &dest)?,
+      false => {
+        let data = self.fs.read(src)?;
+        self.fs.write(&dest, &data, CDiskWriteMode::Truncate)?;
+        data.len() as u64
+      },
+    };
+    if self.remove_src {
+      self.fs.remove_file(src)?;
+    }
+    self.report(&dest, bytes);
+    Ok(())
+  }
+
+  /// Copies the src file / symbolic link of the host to the dest returning
+  /// the bytes copied.
+  fn copy_host(&self, src: &str, dest: &str) -> Result<u64, std::io::Error> {
+    let metadata = std::fs::symlink_metadata(src)?;
+    if metadata.is_symlink() {
+      disk_make_link(&std::fs::read_link(src)?, std::path::Path::new(dest))?;
+    } else {
+      let mut reader = std::fs::File::open(src)?;
+      let mut writer = std::fs::File::create(dest)?;
+      std::io::copy(&mut reader, &mut writer)?;
+      if self.options.preserve {
+        writer.set_times(CDiskCopyTask::<F>::times(&metadata))?;
+        writer.set_permissions(metadata.permissions())?;
+      }
+    }
+    Ok(metadata.len())
+  }
+
+  /// Moves the src to the dest via a rename falling back to copying it
+  /// when the rename fails (i.e. across filesystems) or the dest must be
+  /// merged into.
+  fn move_to(&mut self, src: &str, dest: &str) -> Result<(), std::io::Error> {
+    let replaceable = self.options.conflict == CDiskConflict::Overwrite
+      && self.fs.exists(src, CDiskType::File)
+      && self.fs.exists(dest, CDiskType::File);
+    if (self.fs.info(dest, false).is_err() || replaceable)
+        && self.fs.rename(src, dest).is_ok() {
+      if let Some(progress) = self.options.progress {
+        self.progress.path = dest.to_string();
+        self.progress.files_done = self.progress.files_total;
+        self.progress.bytes_done = self.progress.bytes_total;
+        progress(self.progress.clone());
+      }
+      return Ok(());
+    }
+    self.copy(src, dest)
+  }
+
+  /// Sends the progress of the processed file to the callback.
+  fn report(&mut self, dest: &str, bytes: u64) {
+    self.progress.report(dest, bytes, self.options.progress);
+  }
+
+  /// Applies the [CDiskConflict] to the existing dest returning the path to
+  /// write to or None to skip the file.
+  fn resolve(&mut self, dest: &str) -> Result<Option<String>, std::io::Error> {
+    let exists = std::io::Error::new(
+      std::io::ErrorKind::AlreadyExists,
+      format!("{} already exists", dest),
+    );
+    match self.options.conflict {
+      CDiskConflict::Fail => Err(exists),
+      CDiskConflict::Overwrite => {
+        // A directory is never replaced by a file.
+        if self.fs.info(dest, false)?.kind() == CFileKind::Directory {
+          return Err(exists);
+        }
+        self.fs.remove_file(dest)?;
+        Ok(Some(dest.to_string()))
+      },
+      CDiskConflict::Rename => {
+        let path = std::path::Path::new(dest);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path.extension()
+          .map(|v| format!(".{}", v.to_string_lossy()))
+          .unwrap_or_default();
+        let mut n = 1;
+        loop {
+          let path = path.with_file_name(format!("{} ({}){}", stem, n, ext))
+            .to_string_lossy()
+            .to_string();
+          if self.fs.info(&path, false).is_err() {
+            return Ok(Some(path));
+          }
+          n += 1;
+        }
+      },
+      CDiskConflict::Skip => {
+        self.report(dest, 0);
+        Ok(None)
+      },
+    }
+  }
+
+  /// Gets the access / modified times to preserve. The modified time
+  /// stands in for the access time where it is not reported and times the
+  /// platform doesn't report are left as is.
+  fn times(metadata: &std::fs::Metadata) -> std::fs::FileTimes {
+    let mut times = std::fs::FileTimes::new();
+    if let Ok(modified) = metadata.modified() {
+      times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = metadata.accessed().or_else(|_| metadata.modified()) {
+      times = times.set_accessed(accessed);
+    }
+    times
+  }
+
+  /// Totals the files and bytes under the src.
+  fn total(&mut self, src: &str) {
+    let Ok(info) = self.fs.info(src, false) else {
+      return;
+    };
+    if info.kind() == CFileKind::Directory {
+      for name in self.fs.read_dir(src).unwrap_or_default() {
+        let path = std::path::Path::new(src).join(&name);
+        self.total(&path.to_string_lossy());
+      }
+    } else {
+      self.progress.files_total += 1;
+      self.progress.bytes_total += info.size();
+    }
+  }
+}
+
+/// Identifies the type of src on the disk when attempting to see if it
+/// [disk_exists] or not.
+pub enum CDiskType {
+  /// Does not matter the type, just does it exist or not.
+  Either,
+  /// Only true if it is a directory.
+  Directory,
+  /// Only true if it is a file.
+  File,
+}
+
+/// An entry found by the [CDiskWalker] created via [disk_walk] holding its
+/// path, depth below the walked directory, and [disk_metadata].
+#[derive(Debug)]
+pub struct CDiskEntry {
+  path: String,
+  depth: usize,
+  is_symlink: bool,
+  metadata: std::fs::Metadata,
+}
+impl CDiskEntry {
+  /// The depth below the walked directory (1 for its direct children).
+  pub fn depth(&self) -> usize {
+    self.depth
+  }
+
+  /// The final component of the path.
+  pub fn file_name(&self) -> String {
+    std::path::Path::new(&self.path)
+      .file_name()
+      .map(|v| v.to_string_lossy().to_string())
+      .unwrap_or_default()
+  }
+
+  /// True if the entry is a directory.
+  pub fn is_dir(&self) -> bool {
+    self.metadata.is_dir()
+  }
+
+  /// True if the entry is a file.
+  pub fn is_file(&self) -> bool {
+    self.metadata.is_file()
+  }
+
+  /// True if the entry is a symbolic link (followed or not).
+  pub fn is_symlink(&self) -> bool {
+    self.is_symlink
+  }
+
+  /// The metadata of the entry. It describes the link itself when
+  /// symbolic links are not followed.
+  pub fn metadata(&self) -> &std::fs::Metadata {
+    &self.metadata
+  }
+
+  /// The full path of the entry.
+  pub fn path(&self) -> String {
+    self.path.to_string()
+  }
+}
+
+/// The result of a [disk_walk] call configuring a recursive walk of a
+/// directory. By default everything (including hidden files) is walked to
+/// any depth without following symbolic links. Call
+/// [CDiskWalker::entries] to carry out the walk.
+#[derive(Clone, Debug)]
+pub struct CDiskWalker {
+  src: String,
+  includes: Vec<String>,
+  excludes: Vec<String>,
+  max_depth: Option<usize>,
+  follow_links: bool,
+  hidden: bool,
+  ignore_files: Vec<String>,
+}
+impl CDiskWalker {
+  /// Private constructor supporting the [disk_walk] function.
+  fn new(src: &str) -> CDiskWalker {
+    CDiskWalker {
+      src: src.to_string(),
+      includes: Vec::new(),
+      excludes: Vec::new(),
+      max_depth: None,
+      follow_links: false,
+      hidden: true,
+      ignore_files: Vec::new(),
+    }
+  }
+
+  /// Builds the glob set matching the patterns.
+  fn glob_set(patterns: &[String]) -> globset::GlobSet {
+    let mut builder = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+      // Validated when added so this can't fail.
+      builder.add(globset::Glob::new(pattern).unwrap());
+    }
+    builder.build().unwrap()
+  }
+
+  /// Validates the glob pattern.
+  fn validate(pattern: &str) -> String {
+    match globset::Glob::new(pattern) {
+      Ok(_) => pattern.to_string(),
+      Err(why) => panic!("SyntaxError: CDiskWalker {}", why),
+    }
+  }
+
+  /// Carries out the walk returning each entry found sorted by name within
+  /// its directory. Entries that can't be read are returned as an Err
+  /// without stopping the walk.
+  pub fn entries(
+    self
+  ) -> impl Iterator<Item = Result<CDiskEntry, std::io::Error>> {
+    let root = std::path::PathBuf::from(&self.src);
+    let includes = CDiskWalker::glob_set(&self.includes);
+    let excludes = CDiskWalker::glob_set(&self.excludes);
+    let is_match = |set: &globset::GlobSet, root: &std::path::Path,
+        path: &std::path::Path| {
+      let relative = path.strip_prefix(root).unwrap_or(path);
+      set.is_match(relative)
+        || path.file_name().is_some_and(|v| set.is_match(v))
+    };
+
+    let mut builder = ignore::WalkBuilder::new(&root);
+    builder.standard_filters(false)
+      .parents(false)
+      .hidden(!self.hidden)
+      .follow_links(self.follow_links)
+      .max_depth(self.max_depth)
+      .sort_by_file_name(|a, b| a.cmp(b));
+    for name in &self.ignore_files {
+      builder.add_custom_ignore_filename(name);
+    }
+    let filter_root = root.clone();
+    builder.filter_entry(move |entry| {
+      !is_match(&excludes, &filter_root, entry.path())
+    });
+
+    builder.build().filter_map(move |entry| {
+      let entry = match entry {
+        Ok(v) => v,
+        Err(why) => return Some(Err(
+          why.into_io_error().unwrap_or(std::io::Error::other("walk failed"))
+        )),
+      };
+      if entry.depth() == 0 {
+        return None;
+      }
+      if !includes.is_empty() && !is_match(&includes, &root, entry.path()) {
+        return None;
+      }
+      let metadata = match entry.metadata() {
+        Ok(v) => v,
+        Err(why) => return Some(Err(
+          why.into_io_error().unwrap_or(std::io::Error::other("no metadata"))
+        )),
+      };
+      Some(Ok(CDiskEntry {
+        path: entry.path().to_string_lossy().to_string(),
+        depth: entry.depth(),
+        is_symlink: entry.path_is_symlink(),
+        metadata,
+      }))
+    })
+  }
+
+  /// Skips the entries matching the glob (i.e. "target" or "*.log").
+  /// Matching directories are not descended into. A glob matches the path
+  /// relative to the walked directory or the entry's name.
+  pub fn exclude(mut self, glob: &str) -> CDiskWalker {
+    self.excludes.push(CDiskWalker::validate(glob));
+    self
+  }
+
+  /// Sets whether symbolic links are followed (true) or returned as links
+  /// without descending into them (false).
+  pub fn follow_links(mut self, follow: bool) -> CDiskWalker {
+    self.follow_links = follow;
+    self
+  }
+
+  /// Sets whether hidden (dot) files and directories are walked.
+  pub fn hidden(mut self, include: bool) -> CDiskWalker {
+    self.hidden = include;
+    self
+  }
+
+  /// Applies the gitignore-style rules of any file with this name (i.e.
+  /// ".gitignore") found in the walked directories.
+  pub fn ignore_file(mut self, name: &str) -> CDiskWalker {
+    self.ignore_files.push(name.to_string());
+    self
+  }
+
+  /// Only returns the entries matching the glob (i.e. "*.rs"). Call again
+  /// to add further globs. Directories are still descended into.
+  pub fn include(mut self, glob: &str) -> CDiskWalker {
+    self.includes.push(CDiskWalker::validate(glob));
+    self
+  }
+
+  /// Limits how deep below the walked directory the walk goes (1 for just
+  /// its direct children).
+  pub fn max_depth(mut self, depth: usize) -> CDiskWalker {
+    self.max_depth = Some(depth);
+    self
+  }
+}
+
+/// Defines the operations of a file system. The path based disk functions
+/// carry out the `disk_*` methods of the [CDiskOsFileSystem] (i.e.
+/// [disk_cp_with] calls [CDiskFileSystem::disk_cp_with]) with [disk_exists],
+/// [disk_info], [disk_mkdir], and [disk_rm] carrying out [Self::exists],
+/// [Self::info], [Self::create_dir_all], and [Self::remove_all]. Calling
+/// the methods of another file system works against it instead. The
+/// `disk_*` methods default to carrying out the required operations with
+/// the ones handing out host objects (i.e. [Self::disk_lock],
+/// [Self::disk_open_reader], [Self::disk_walk]) failing with an
+/// [std::io::ErrorKind::Unsupported] unless backed by the host like the
+/// [CDiskOsFileSystem] / [CDiskSandboxFileSystem]. Implemented by those
+/// along with [CDiskMemoryFileSystem] and [CDiskOverlayFileSystem] and
+/// implementable for other storage.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskConflict, CDiskCopyOptions, CDiskFileSystem};
+/// use codemelted::{CDiskMemoryFileSystem, CDiskOsFileSystem};
+/// use codemelted::{CDiskOverlayFileSystem, CDiskSandboxFileSystem};
+/// use codemelted::{CDiskType, CFileContents};
+/// use std::sync::Arc;
+///
+/// let fs = CDiskMemoryFileSystem::new();
+/// fs.create_dir_all("/app/data").unwrap();
+/// fs.disk_write_file(
+///   "/app/data/settings.json",
+///   CFileContents::String(String::from("{}")),
+///   false
+/// ).unwrap();
+/// fs.disk_cp("/app/data", "/app/backup").unwrap();
+/// assert!(fs.disk_cp("/app/data", "/app/data/nested").is_err());
+/// let options = CDiskCopyOptions::new().conflict(CDiskConflict::Rename);
+/// fs.disk_cp_with("/app/data", "/app/backup", &options).unwrap();
+/// assert!(fs.exists("/app/backup/settings (1).json", CDiskType::File));
+/// fs.disk_mv_with("/app/backup/settings.json", "/app", &options).unwrap();
+/// assert_eq!(fs.info("/app/settings.json", true).unwrap().size(), 2);
+/// assert_eq!(
+///   fs.disk_ls("/app").unwrap_err().kind(),
+///   std::io::ErrorKind::Unsupported
+/// );
+///
+/// let root = format!("{}/test_disk_fs", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&root);
+/// codemelted::disk_mkdir(&root).unwrap();
+/// let sandbox = CDiskSandboxFileSystem::new(&root).unwrap();
+/// sandbox.disk_write_file(
+///   "../../escape.txt",
+///   CFileContents::String(String::from("confined")),
+///   false
+/// ).unwrap();
+/// let filename = format!("{}/escape.txt", root);
+/// assert!(codemelted::disk_exists(&filename, CDiskType::File));
+///
+/// let overlay = CDiskOverlayFileSystem::new(
+///   Arc::new(CDiskOsFileSystem::new())
+/// );
+/// overlay.disk_write_file(
+///   &filename,
+///   CFileContents::String(String::from("-changed")),
+///   true
+/// ).unwrap();
+/// let data = overlay.disk_read_file(&filename, true).unwrap();
+/// assert_eq!(data.as_string().unwrap(), "confined-changed");
+/// let data = codemelted::disk_read_file(&filename, true).unwrap();
+/// assert_eq!(data.as_string().unwrap(), "confined");
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+pub trait CDiskFileSystem: Send + Sync {
+  /// Creates the directory along with any missing parent directories.
+  fn create_dir_all(&self, path: &str) -> Result<(), std::io::Error>;
+
+  /// Retrieves the [CFileInfo] of the entry.
+  fn info(
+    &self,
+    path: &str,
+    follow_links: bool,
+  ) -> Result<CFileInfo, std::io::Error>;
+
+  /// Reads the contents of the file.
+  fn read(&self, path: &str) -> Result<Vec<u8>, std::io::Error>;
+
+  /// Lists the names of the entries of the directory sorted by name.
+  fn read_dir(&self, path: &str) -> Result<Vec<String>, std::io::Error>;
+
+  /// Removes the empty directory.
+  fn remove_dir(&self, path: &str) -> Result<(), std::io::Error>;
+
+  /// Removes the file.
+  fn remove_file(&self, path: &str) -> Result<(), std::io::Error>;
+
+  /// Renames the entry replacing a file / empty directory at the
+  /// destination.
+  fn rename(&self, from: &str, to: &str) -> Result<(), std::io::Error>;
+
+  /// Writes the data to the file per the [CDiskWriteMode].
+  fn write(
+    &self,
+    path: &str,
+    data: &[u8],
+    mode: CDiskWriteMode,
+  ) -> Result<(), std::io::Error>;
+
+  /// Copies the file / directory tree to the destination path replacing
+  /// the files already there. A directory can't be copied into its own
+  /// tree.
+  fn copy(&self, src: &str, dest: &str) -> Result<(), std::io::Error> {
+    if self.info(src, true)?.kind() != CFileKind::Directory {
+      let data = self.read(src)?;
+      return self.write(dest, &data, CDiskWriteMode::Truncate);
+    }
+    if disk_fs_normalize(dest).starts_with(disk_fs_normalize(src)) {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{} is within {}", dest, src),
+      ));
+    }
+    self.create_dir_all(dest)?;
+    for name in self.read_dir(src)? {
+      let path = |v: &str| {
+        std::path::Path::new(v).join(&name).to_string_lossy().to_string()
+      };
+      self.copy(&path(src), &path(dest))?;
+    }
+    Ok(())
+  }
+
+  /// Determines if the entry exists as the [CDiskType].
+  fn exists(&self, path: &str, disk_type: CDiskType) -> bool {
+    match self.info(path, true).map(|v| v.kind()) {
+      Ok(CFileKind::Directory) => !matches!(disk_type, CDiskType::File),
+      Ok(CFileKind::File) => !matches!(disk_type, CDiskType::Directory),
+      _ => false,
+    }
+  }
+
+  /// Removes the file / directory tree.
+  fn remove_all(&self, path: &str) -> Result<(), std::io::Error> {
+    if self.info(path, false)?.kind() != CFileKind::Directory {
+      return self.remove_file(path);
+    }
+    for name in self.read_dir(path)? {
+      let child = std::path::Path::new(path).join(&name);
+      self.remove_all(&child.to_string_lossy())?;
+    }
+    self.remove_dir(path)
+  }
+
+  /// Archives the src file / directory into the archive file of the host
+  /// (see [disk_archive]).
+  fn disk_archive(
+    &self,
+    src: &str,
+    archive: &str,
+    format: CDiskArchiveFormat,
+    options: &CDiskArchiveOptions,
+  ) -> Result<usize, std::io::Error> {
+    let _ = (src, archive, format, options);
+    Err(disk_fs_unsupported("disk_archive"))
+  }
+
+  /// Sets the permission bits of the host src (see [disk_chmod]).
+  fn disk_chmod(&self, src: &str, mode: u32) -> Result<(), std::io::Error> {
+    let _ = (src, mode);
+    Err(disk_fs_unsupported("disk_chmod"))
+  }
+
+  /// Changes the owning user and / or group ids of the host src (see
+  /// [disk_chown]).
+  fn disk_chown(
+    &self,
+    src: &str,
+    uid: Option<u32>,
+    gid: Option<u32>,
+  ) -> Result<(), std::io::Error> {
+    let _ = (src, uid, gid);
+    Err(disk_fs_unsupported("disk_chown"))
+  }
+
+  /// Copies the src file / directory tree to the dest with the default
+  /// [CDiskCopyOptions] (see [disk_cp]).
+  fn disk_cp(&self, src: &str, dest: &str) -> Result<(), std::io::Error> {
+    self.disk_cp_with(src, dest, &CDiskCopyOptions::new())
+  }
+
+  /// Copies the src file / directory tree to the dest per the
+  /// [CDiskCopyOptions] (see [disk_cp_with]). Outside of the host the
+  /// permissions and timestamps aren't preserved and symbolic links are
+  /// copied as the files they lead to.
+  fn disk_cp_with(
+    &self,
+    src: &str,
+    dest: &str,
+    options: &CDiskCopyOptions,
+  ) -> Result<(), std::io::Error> {
+    let dest = disk_fs_dest(self, src, dest);
+    disk_fs_validate(self, src, &dest)?;
+    CDiskCopyTask::new(self, false, src, options, false).copy(src, &dest)
+  }
+
+  /// Extracts the host archive file into the host dest directory (see
+  /// [disk_extract]).
+  fn disk_extract(
+    &self,
+    archive: &str,
+    dest: &str,
+    format: CDiskArchiveFormat,
+    options: &CDiskArchiveOptions,
+  ) -> Result<usize, std::io::Error> {
+    let _ = (archive, dest, format, options);
+    Err(disk_fs_unsupported("disk_extract"))
+  }
+
+  /// Finds the files with identical contents under the src directory tree
+  /// (see [disk_find_duplicates]).
+  fn disk_find_duplicates(
+    &self,
+    src: &str,
+    algorithm: CDiskHashAlgorithm,
+  ) -> Result<Vec<Vec<String>>, std::io::Error> {
+    let mut files = Vec::new();
+    disk_fs_files(self, src, &mut files)?;
+    let mut sizes = std::collections::HashMap::<u64, Vec<String>>::new();
+    for (path, size) in files.into_iter().filter(|v| v.1 > 0) {
+      sizes.entry(size).or_default().push(path);
+    }
+
+    let mut duplicates = Vec::new();
+    for paths in sizes.into_values().filter(|v| v.len() > 1) {
+      let mut hashes = std::collections::HashMap::<String, Vec<String>>::new();
+      for path in paths {
+        let hash = self.disk_hash_file(&path, algorithm)?;
+        hashes.entry(hash).or_default().push(path);
+      }
+      duplicates.extend(hashes.into_values().filter(|v| v.len() > 1));
+    }
+    duplicates.sort();
+    Ok(duplicates)
+  }
+
+  /// Creates a hard link at the link path to the src file of the host (see
+  /// [disk_hard_link]).
+  fn disk_hard_link(
+    &self,
+    src: &str,
+    link: &str,
+  ) -> Result<(), std::io::Error> {
+    let _ = (src, link);
+    Err(disk_fs_unsupported("disk_hard_link"))
+  }
+
+  /// Hashes the file via the [CDiskHashAlgorithm] (see [disk_hash_file]).
+  /// Only the host reads it in chunks rather than into memory.
+  fn disk_hash_file(
+    &self,
+    filename: &str,
+    algorithm: CDiskHashAlgorithm,
+  ) -> Result<String, std::io::Error> {
+    let data = self.read(filename)?;
+    CDiskHasher::new(algorithm).read(&mut std::io::Cursor::new(data))
+  }
+
+  /// Creates a checksum manifest of the src file / directory tree (see
+  /// [disk_hash_manifest]).
+  fn disk_hash_manifest(
+    &self,
+    src: &str,
+    algorithm: CDiskHashAlgorithm,
+  ) -> Result<String, std::io::Error> {
+    let is_dir = self.exists(src, CDiskType::Directory);
+    let src_path = std::path::Path::new(src);
+    let mut files = Vec::new();
+    if is_dir {
+      disk_fs_files(self, src, &mut files)?;
+      files.retain(|(path, _)| {
+        let path = std::path::Path::new(path);
+        path.parent() != Some(src_path) || !path.file_name().is_some_and(|v| {
+          DISK_MANIFEST_NAMES.contains(&v.to_string_lossy().as_ref())
+        })
+      });
+    } else {
+      files.push((src.to_string(), 0));
+    }
+    let mut manifest = String::new();
+    for (path, _) in files {
+      let name = match is_dir {
+        true => std::path::Path::new(&path).strip_prefix(src_path)
+          .unwrap_or(std::path::Path::new(&path))
+          .to_path_buf(),
+        false => std::path::PathBuf::from(
+          src_path.file_name().unwrap_or_default()
+        ),
+      };
+      let name = name.components()
+        .map(|v| v.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/");
+      let hash = self.disk_hash_file(&path, algorithm)?;
+      manifest.push_str(&format!("{}  {}\n", hash, name));
+    }
+    Ok(manifest)
+  }
+
+  /// Verifies the files listed in the manifest file against their hashes
+  /// (see [disk_hash_verify]).
+  fn disk_hash_verify(
+    &self,
+    manifest: &str,
+    algorithm: CDiskHashAlgorithm,
+  ) -> Result<Vec<(String, CDiskHashStatus)>, std::io::Error> {
+    let base = std::path::Path::new(manifest).parent()
+      .unwrap_or(std::path::Path::new(""))
+      .to_path_buf();
+    let data = String::from_utf8(self.read(manifest)?).map_err(
+      |why| std::io::Error::new(std::io::ErrorKind::InvalidData, why)
+    )?;
+    let mut results = Vec::new();
+    for line in data.lines() {
+      if line.trim().is_empty() {
+        continue;
+      }
+      // Text mode uses two spaces and binary mode a space and asterisk.
+      let entry = line.split_once(' ').and_then(|(hash, rest)| {
+        Some((hash, rest.strip_prefix([' ', '*'])?))
+      });
+      let Some((hash, name)) = entry else {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::InvalidData,
+          format!("malformed manifest line: {}", line),
+        ));
+      };
+      let filename = base.join(name).to_string_lossy().to_string();
+      let status = match self.disk_hash_file(&filename, algorithm) {
+        Ok(v) if v.eq_ignore_ascii_case(hash) => CDiskHashStatus::Ok,
+        Ok(_) => CDiskHashStatus::Failed,
+        Err(_) => CDiskHashStatus::Missing,
+      };
+      results.push((name.to_string(), status));
+    }
+    Ok(results)
+  }
+
+  /// Takes an advisory lock of the host file (see [disk_lock]).
+  fn disk_lock(
+    &self,
+    filename: &str,
+    kind: CDiskLockKind,
+    wait: CDiskLockWait,
+  ) -> Result<CDiskLock, std::io::Error> {
+    let _ = (filename, kind, wait);
+    Err(disk_fs_unsupported("disk_lock"))
+  }
+
+  /// Lists the entries of the host src directory (see [disk_ls]). Use
+  /// [Self::read_dir] to list the names of any file system.
+  fn disk_ls(&self, src: &str) -> Result<std::fs::ReadDir, std::io::Error> {
+    let _ = src;
+    Err(disk_fs_unsupported("disk_ls"))
+  }
+
+  /// Memory maps the host file for read access (see [disk_map_file]).
+  fn disk_map_file(&self, filename: &str) -> Result<CFileMap, std::io::Error> {
+    let _ = filename;
+    Err(disk_fs_unsupported("disk_map_file"))
+  }
+
+  /// Retrieves the metadata of the host src (see [disk_metadata]). Use
+  /// [Self::info] for the [CFileInfo] of any file system.
+  fn disk_metadata(
+    &self,
+    src: &str,
+  ) -> Result<std::fs::Metadata, std::io::Error> {
+    let _ = src;
+    Err(disk_fs_unsupported("disk_metadata"))
+  }
+
+  /// Moves the src file / directory tree to the dest with the default
+  /// [CDiskCopyOptions] (see [disk_mv]).
+  fn disk_mv(&self, src: &str, dest: &str) -> Result<(), std::io::Error> {
+    self.disk_mv_with(src, dest, &CDiskCopyOptions::new())
+  }
+
+  /// Moves the src file / directory tree to the dest per the
+  /// [CDiskCopyOptions] (see [disk_mv_with]) copying like
+  /// [Self::disk_cp_with] when it can't be renamed.
+  fn disk_mv_with(
+    &self,
+    src: &str,
+    dest: &str,
+    options: &CDiskCopyOptions,
+  ) -> Result<(), std::io::Error> {
+    let dest = disk_fs_dest(self, src, dest);
+    disk_fs_validate(self, src, &dest)?;
+    CDiskCopyTask::new(self, false, src, options, true).move_to(src, &dest)
+  }
+
+  /// Opens the host file for buffered reads (see [disk_open_reader]).
+  fn disk_open_reader(
+    &self,
+    filename: &str,
+  ) -> Result<CFileReader, std::io::Error> {
+    let _ = filename;
+    Err(disk_fs_unsupported("disk_open_reader"))
+  }
+
+  /// Opens the host file for buffered writes (see [disk_open_writer]).
+  fn disk_open_writer(
+    &self,
+    filename: &str,
+    mode: CDiskWriteMode,
+  ) -> Result<CFileWriter, std::io::Error> {
+    let _ = (filename, mode);
+    Err(disk_fs_unsupported("disk_open_writer"))
+  }
+
+  /// Takes ownership of the host lock / PID file (see [disk_pid_file]).
+  fn disk_pid_file(
+    &self,
+    filename: &str,
+    wait: CDiskLockWait,
+  ) -> Result<CDiskPidFile, std::io::Error> {
+    let _ = (filename, wait);
+    Err(disk_fs_unsupported("disk_pid_file"))
+  }
+
+  /// Reads the file as a string or bytes (see [disk_read_file]).
+  fn disk_read_file(
+    &self,
+    filename: &str,
+    is_string: bool,
+  ) -> Result<CFileContents, std::io::Error> {
+    let data = self.read(filename)?;
+    match is_string {
+      true => String::from_utf8(data).map(CFileContents::String).map_err(
+        |why| std::io::Error::new(std::io::ErrorKind::InvalidInput, why)
+      ),
+      false => Ok(CFileContents::Bytes(data)),
+    }
+  }
+
+  /// Reads the target path of the host symbolic link (see
+  /// [disk_read_link]).
+  fn disk_read_link(&self, link: &str) -> Result<String, std::io::Error> {
+    let _ = link;
+    Err(disk_fs_unsupported("disk_read_link"))
+  }
+
+  /// Sets the last access and / or modified times of the host src (see
+  /// [disk_set_times]).
+  fn disk_set_times(
+    &self,
+    src: &str,
+    accessed: Option<std::time::SystemTime>,
+    modified: Option<std::time::SystemTime>,
+  ) -> Result<(), std::io::Error> {
+    let _ = (src, accessed, modified);
+    Err(disk_fs_unsupported("disk_set_times"))
+  }
+
+  /// Creates a host symbolic link at the link path pointing to the target
+  /// (see [disk_symlink]).
+  fn disk_symlink(
+    &self,
+    target: &str,
+    link: &str,
+  ) -> Result<(), std::io::Error> {
+    let _ = (target, link);
+    Err(disk_fs_unsupported("disk_symlink"))
+  }
+
+  /// Creates a uniquely named host directory with the default
+  /// [CDiskTempOptions] (see [disk_temp_dir]).
+  fn disk_temp_dir(&self) -> Result<CDiskTempDir, std::io::Error> {
+    self.disk_temp_dir_with(&CDiskTempOptions::new())
+  }
+
+  /// Creates a uniquely named host directory per the options (see
+  /// [disk_temp_dir_with]).
+  fn disk_temp_dir_with(
+    &self,
+    options: &CDiskTempOptions,
+  ) -> Result<CDiskTempDir, std::io::Error> {
+    let _ = options;
+    Err(disk_fs_unsupported("disk_temp_dir_with"))
+  }
+
+  /// Creates a uniquely named host file with the default
+  /// [CDiskTempOptions] (see [disk_temp_file]).
+  fn disk_temp_file(&self) -> Result<CDiskTempFile, std::io::Error> {
+    self.disk_temp_file_with(&CDiskTempOptions::new())
+  }
+
+  /// Creates a uniquely named host file per the options (see
+  /// [disk_temp_file_with]).
+  fn disk_temp_file_with(
+    &self,
+    options: &CDiskTempOptions,
+  ) -> Result<CDiskTempFile, std::io::Error> {
+    let _ = options;
+    Err(disk_fs_unsupported("disk_temp_file_with"))
+  }
+
+  /// Calculates the disk usage of the host src directory tree (see
+  /// [disk_usage]).
+  fn disk_usage(
+    &self,
+    src: &str,
+    largest: usize,
+    parallel: bool,
+  ) -> Result<CDiskUsage, std::io::Error> {
+    let _ = (src, largest, parallel);
+    Err(disk_fs_unsupported("disk_usage"))
+  }
+
+  /// Creates a [CDiskWalker] of the host src directory (see [disk_walk]).
+  fn disk_walk(&self, src: &str) -> Result<CDiskWalker, std::io::Error> {
+    let _ = src;
+    Err(disk_fs_unsupported("disk_walk"))
+  }
+
+  /// Watches the host path for changes with the default
+  /// [CDiskWatchOptions] (see [disk_watch]).
+  fn disk_watch(
+    &self,
+    path: &str,
+    recursive: bool,
+  ) -> Result<CDiskWatcher, std::io::Error> {
+    self.disk_watch_with(path, recursive, &CDiskWatchOptions::new())
+  }
+
+  /// Watches the host path for changes per the options (see
+  /// [disk_watch_with]).
+  fn disk_watch_with(
+    &self,
+    path: &str,
+    recursive: bool,
+    options: &CDiskWatchOptions,
+  ) -> Result<CDiskWatcher, std::io::Error> {
+    let _ = (path, recursive, options);
+    Err(disk_fs_unsupported("disk_watch_with"))
+  }
+
+  /// Writes the file replacing its contents or appending to them (see
+  /// [disk_write_file]).
+  fn disk_write_file(
+    &self,
+    filename: &str,
+    data: CFileContents,
+    append: bool,
+  ) -> Result<(), std::io::Error> {
+    let mode = match append {
+      true => CDiskWriteMode::Append,
+      false => CDiskWriteMode::Truncate,
+    };
+    self.disk_write_file_with(filename, data, mode, false)
+  }
+
+  /// Writes the file via the [CDiskWriteMode] optionally backing up an
+  /// existing file to "{filename}.bak" (see [disk_write_file_with]).
+  fn disk_write_file_with(
+    &self,
+    filename: &str,
+    data: CFileContents,
+    mode: CDiskWriteMode,
+    backup: bool,
+  ) -> Result<(), std::io::Error> {
+    let data = match data {
+      CFileContents::Bytes(data) => data,
+      CFileContents::String(data) => data.into_bytes(),
+    };
+    if backup && self.exists(filename, CDiskType::File) {
+      if mode == CDiskWriteMode::CreateNew {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::AlreadyExists,
+          format!("{} already exists", filename),
+        ));
+      }
+      self.copy(filename, &format!("{}.bak", filename))?;
+    }
+    self.write(filename, &data, mode)
+  }
+}
+
+/// The entries of a [CDiskMemoryFileSystem] by path holding the contents
+/// of files (None for directories) and their modified time.
+type CDiskMemoryEntries = std::collections::BTreeMap<
+  std::path::PathBuf,
+  (Option<Vec<u8>>, std::time::SystemTime)
+>;
+
+/// A [CDiskFileSystem] held in memory for testing code against a file
+/// system without touching the disk. Paths are resolved from the root with
+/// `.` / `..` handled lexically. Symbolic links are not supported.
+#[derive(Debug)]
+pub struct CDiskMemoryFileSystem {
+  entries: std::sync::Mutex<CDiskMemoryEntries>,
+}
+impl Default for CDiskMemoryFileSystem {
+  fn default() -> Self {
+    CDiskMemoryFileSystem::new()
+  }
+}
+impl CDiskMemoryFileSystem {
+  /// Creates an empty file system holding only the root directory.
+  pub fn new() -> CDiskMemoryFileSystem {
+    let mut entries = std::collections::BTreeMap::new();
+    entries.insert(
+      std::path::PathBuf::from("/"),
+      (None, std::time::SystemTime::now()),
+    );
+    CDiskMemoryFileSystem { entries: std::sync::Mutex::new(entries) }
+  }
+
+  /// Creates the error of the operation on the path.
+  fn error(
+    kind: std::io::ErrorKind,
+    path: &std::path::Path,
+  ) -> std::io::Error {
+    std::io::Error::new(kind, format!("{}: {}", path.display(), kind))
+  }
+
+  /// Validates the parent of the path is a directory.
+  fn parent_dir(
+    entries: &CDiskMemoryEntries,
+    path: &std::path::Path,
+  ) -> Result<(), std::io::Error> {
+    let parent = path.parent().unwrap_or(path);
+    match entries.get(parent) {
+      Some((None, _)) => Ok(()),
+      Some(_) => Err(Self::error(std::io::ErrorKind::NotADirectory, parent)),
+      None => Err(Self::error(std::io::ErrorKind::NotFound, parent)),
+    }
+  }
+}
+impl CDiskFileSystem for CDiskMemoryFileSystem {
+  fn create_dir_all(&self, path: &str) -> Result<(), std::io::Error> {
+    let path = disk_fs_normalize(path);
+    let mut entries = self.entries.lock().unwrap();
+    let mut dirs = path.ancestors().collect::<Vec<_>>();
+    dirs.reverse();
+    for dir in dirs {
+      match entries.get(dir) {
+        Some((None, _)) => continue,
+        Some(_) => {
+          return Err(Self::error(std::io::ErrorKind::NotADirectory, dir));
+        },
+        None => {
+          entries.insert(dir.to_path_buf(),
+            (None, std::time::SystemTime::now()));
+        },
+      }
+    }
+    Ok(())
+  }
+
+  fn info(
+    &self,
+    path: &str,
+    _follow_links: bool,
+  ) -> Result<CFileInfo, std::io::Error> {
+    let normalized = disk_fs_normalize(path);
+    let entries = self.entries.lock().unwrap();
+    let Some((data, modified)) = entries.get(&normalized) else {
+      return Err(Self::error(std::io::ErrorKind::NotFound, &normalized));
+    };
+    Ok(CFileInfo {
+      path: path.to_string(),
+      kind: match data {
+        Some(_) => CFileKind::File,
+        None => CFileKind::Directory,
+      },
+      size: data.as_ref().map(|v| v.len() as u64).unwrap_or_default(),
+      accessed: None,
+      created: None,
+      modified: Some(*modified),
+      mode: None,
+      readonly: false,
+      uid: None,
+      gid: None,
+    })
+  }
+
+  fn read(&self, path: &str) -> Result<Vec<u8>, std::io::Error> {
+    let path = disk_fs_normalize(path);
+    match self.entries.lock().unwrap().get(&path) {
+      Some((Some(data), _)) => Ok(data.clone()),
+      Some(_) => Err(Self::error(std::io::ErrorKind::IsADirectory, &path)),
+      None => Err(Self::error(std::io::ErrorKind::NotFound, &path)),
+    }
+  }
+
+  fn read_dir(&self, path: &str) -> Result<Vec<String>, std::io::Error> {
+    let path = disk_fs_normalize(path);
+    let entries = self.entries.lock().unwrap();
+    match entries.get(&path) {
+      Some((None, _)) => Ok(entries.keys()
+        .filter(|v| v.parent() == Some(path.as_path()))
+        .filter_map(|v| v.file_name())
+        .map(|v| v.to_string_lossy().to_string())
+        .collect()),
+      Some(_) => Err(Self::error(std::io::ErrorKind::NotADirectory, &path)),
+      None => Err(Self::error(std::io::ErrorKind::NotFound, &path)),
+    }
+  }
+
+  fn remove_dir(&self, path: &str) -> Result<(), std::io::Error> {
+    let path = disk_fs_normalize(path);
+    let mut entries = self.entries.lock().unwrap();
+    match entries.get(&path) {
+      Some((None, _)) if path.parent().is_none() => {
+        Err(Self::error(std::io::ErrorKind::PermissionDenied, &path))
+      },
+      Some((None, _)) => {
+        if entries.keys().any(|v| v.parent() == Some(path.as_path())) {
+          return Err(Self::error(
+            std::io::ErrorKind::DirectoryNotEmpty, &path
+          ));
+        }
+        entries.remove(&path);
+        Ok(())
+      },
+      Some(_) => Err(Self::error(std::io::ErrorKind::NotADirectory, &path)),
+      None => Err(Self::error(std::io::ErrorKind::NotFound, &path)),
+    }
+  }
+
+  fn remove_file(&self, path: &str) -> Result<(), std::io::Error> {
+    let path = disk_fs_normalize(path);
+    let mut entries = self.entries.lock().unwrap();
+    match entries.get(&path) {
+      Some((Some(_), _)) => {
+        entries.remove(&path);
+        Ok(())
+      },
+      Some(_) => Err(Self::error(std::io::ErrorKind::IsADirectory, &path)),
+      None => Err(Self::error(std::io::ErrorKind::NotFound, &path)),
+    }
+  }
+
+  fn rename(&self, from: &str, to: &str) -> Result<(), std::io::Error> {
+    let from = disk_fs_normalize(from);
+    let to = disk_fs_normalize(to);
+    let mut entries = self.entries.lock().unwrap();
+    let Some((data, _)) = entries.get(&from) else {
+      return Err(Self::error(std::io::ErrorKind::NotFound, &from));
+    };
+    if from == to {
+      return Ok(());
+    } else if from.parent().is_none() || to.starts_with(&from) {
+      return Err(Self::error(std::io::ErrorKind::InvalidInput, &to));
+    }
+    Self::parent_dir(&entries, &to)?;
+    let is_dir = data.is_none();
+    match entries.get(&to) {
+      Some((None, _)) if !is_dir => {
+        return Err(Self::error(std::io::ErrorKind::IsADirectory, &to));
+      },
+      Some((None, _))
+          if entries.keys().any(|v| v.parent() == Some(to.as_path())) => {
+        return Err(Self::error(std::io::ErrorKind::DirectoryNotEmpty, &to));
+      },
+      Some((Some(_), _)) if is_dir => {
+        return Err(Self::error(std::io::ErrorKind::NotADirectory, &to));
+      },
+      _ => {},
+    }
+    let moved = entries.keys()
+      .filter(|v| v.starts_with(&from))
+      .cloned()
+      .collect::<Vec<_>>();
+    for path in moved {
+      let entry = entries.remove(&path).unwrap();
+      let dest = match path.strip_prefix(&from) {
+        Ok(v) if !v.as_os_str().is_empty() => to.join(v),
+        _ => to.clone(),
+      };
+      entries.insert(dest, entry);
+    }
+    Ok(())
+  }
+
+  fn write(
+    &self,
+    path: &str,
+    data: &[u8],
+    mode: CDiskWriteMode,
+  ) -> Result<(), std::io::Error> {
+    let path = disk_fs_normalize(path);
+    let mut entries = self.entries.lock().unwrap();
+    Self::parent_dir(&entries, &path)?;
+    let mut contents = match entries.get(&path) {
+      Some((None, _)) => {
+        return Err(Self::error(std::io::ErrorKind::IsADirectory, &path));
+      },
+      Some(_) if mode == CDiskWriteMode::CreateNew => {
+        return Err(Self::error(std::io::ErrorKind::AlreadyExists, &path));
+      },
+      Some((Some(v), _)) if mode == CDiskWriteMode::Append => v.clone(),
+      _ => Vec::new(),
+    };
+    contents.extend_from_slice(data);
+    entries.insert(path, (Some(contents), std::time::SystemTime::now()));
+    Ok(())
+  }
+}
+
+/// The [CDiskFileSystem] of the host operating system.
+#[derive(Clone, Copy, Debug)]
+pub struct CDiskOsFileSystem;
+impl Default for CDiskOsFileSystem {
+  fn default() -> Self {
+    CDiskOsFileSystem::new()
+  }
+}
+impl CDiskOsFileSystem {
+  /// Creates the host operating system file system.
+  pub fn new() -> CDiskOsFileSystem {
+    CDiskOsFileSystem
+  }
+}
+impl CDiskFileSystem for CDiskOsFileSystem {
+  fn create_dir_all(&self, path: &str) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(path)
+  }
+
+  fn info(
+    &self,
+    path: &str,
+    follow_links: bool,
+  ) -> Result<CFileInfo, std::io::Error> {
+    let metadata = match follow_links {
+      true => std::fs::metadata(path)?,
+      false => std::fs::symlink_metadata(path)?,
+    };
+    Ok(CFileInfo::new(path, &metadata))
+  }
+
+  fn read(&self, path: &str) -> Result<Vec<u8>, std::io::Error> {
+    std::fs::read(path)
+  }
+
+  fn read_dir(&self, path: &str) -> Result<Vec<String>, std::io::Error> {
+    let mut names = std::fs::read_dir(path)?
+      .map(|v| v.map(|v| v.file_name().to_string_lossy().to_string()))
+      .collect::<Result<Vec<_>, std::io::Error>>()?;
+    names.sort();
+    Ok(names)
+  }
+
+  fn remove_dir(&self, path: &str) -> Result<(), std::io::Error> {
+    std::fs::remove_dir(path)
+  }
+
+  fn remove_all(&self, path: &str) -> Result<(), std::io::Error> {
+    match std::fs::symlink_metadata(path)?.is_dir() {
+      true => std::fs::remove_dir_all(path),
+      false => std::fs::remove_file(path),
+    }
+  }
+
+  fn remove_file(&self, path: &str) -> Result<(), std::io::Error> {
+    std::fs::remove_file(path)
+  }
+
+  fn rename(&self, from: &str, to: &str) -> Result<(), std::io::Error> {
+    std::fs::rename(from, to)
+  }
+
+  fn write(
+    &self,
+    path: &str,
+    data: &[u8],
+    mode: CDiskWriteMode,
+  ) -> Result<(), std::io::Error> {
+    disk_write_os(std::path::Path::new(path), data, mode)
+  }
+
+  fn copy(&self, src: &str, dest: &str) -> Result<(), std::io::Error> {
+    let (src_path, dest_path) = (
+      std::path::Path::new(src),
+      std::path::Path::new(dest),
+    );
+    disk_copy_validate(src_path, dest_path)?;
+    let options = CDiskCopyOptions::new();
+    CDiskCopyTask::new(self, true, src, &options, false).copy(src, dest)
+  }
+
+  fn disk_archive(
+    &self,
+    src: &str,
+    archive: &str,
+    format: CDiskArchiveFormat,
+    options: &CDiskArchiveOptions,
+  ) -> Result<usize, std::io::Error> {
+    let src_path = std::path::Path::new(src);
+    let mut entries = Vec::new();
+    if src_path.is_dir() {
+      let mut walker = CDiskWalker::new(src);
+      for glob in &options.includes {
+        walker = walker.include(glob);
+      }
+      let archive_path = std::fs::canonicalize(archive).ok();
+      for entry in walker.entries() {
+        let entry = entry?;
+        let path = std::path::PathBuf::from(entry.path());
+        if archive_path.is_some()
+            && std::fs::canonicalize(&path).ok() == archive_path {
+          continue;
+        }
+        let name = path.strip_prefix(src_path).unwrap_or(&path)
+          .components()
+          .map(|v| v.as_os_str().to_string_lossy().to_string())
+          .collect::<Vec<String>>()
+          .join("/");
+        entries.push((path, name, entry.metadata().clone()));
+      }
+    } else {
+      let metadata = std::fs::symlink_metadata(src_path)?;
+      let name = src_path.file_name().unwrap_or_default().to_string_lossy();
+      entries.push((src_path.to_path_buf(), name.to_string(), metadata));
+    }
+
+    let mut progress = CDiskProgress::default();
+    for (_, _, metadata) in entries.iter().filter(|v| !v.2.is_dir()) {
+      progress.files_total += 1;
+      progress.bytes_total += metadata.len();
+    }
+    let file = std::fs::File::create(archive)?;
+    let cb = options.progress;
+    match format {
+      CDiskArchiveFormat::Tar => {
+        disk_archive_tar(file, &entries, &mut progress, cb)?.sync_all()?;
+      },
+      CDiskArchiveFormat::TarGz => {
+        let writer = flate2::write::GzEncoder::new(
+          file, flate2::Compression::default()
+        );
+        disk_archive_tar(writer, &entries, &mut progress, cb)?
+          .finish()?
+          .sync_all()?;
+      },
+      CDiskArchiveFormat::TarZst => {
+        let writer = zstd::Encoder::new(file, 0)?;
+        disk_archive_tar(writer, &entries, &mut progress, cb)?
+          .finish()?
+          .sync_all()?;
+      },
+      CDiskArchiveFormat::Zip => {
+        let mut zip = zip::ZipWriter::new(file);
+        for (path, name, metadata) in &entries {
+          let zip_options = disk_archive_zip_options(metadata);
+          if metadata.is_dir() {
+            zip.add_directory(format!("{}/", name), zip_options)?;
+            continue;
+          } else if metadata.is_symlink() {
+            let target = std::fs::read_link(path)?;
+            zip.add_symlink(name, target.to_string_lossy(), zip_options)?;
+          } else {
+            zip.start_file(name, zip_options)?;
+            std::io::copy(&mut std::fs::File::open(path)?, &mut zip)?;
+          }
+          progress.report(name, metadata.len(), cb);
+        }
+        zip.finish()?.sync_all()?;
+      },
+    }
+    Ok(progress.files_total)
+  }
+
+  fn disk_chmod(&self, src: &str, mode: u32) -> Result<(), std::io::Error> {
+    #[cfg(unix)]
+    let permissions = {
+      use std::os::unix::fs::PermissionsExt;
+      std::fs::Permissions::from_mode(mode)
+    };
+    #[cfg(not(unix))]
+    let permissions = {
+      let mut permissions = std::fs::metadata(src)?.permissions();
+      permissions.set_readonly(mode & 0o222 == 0);
+      permissions
+    };
+    std::fs::set_permissions(src, permissions)
+  }
+
+  fn disk_chown(
+    &self,
+    src: &str,
+    uid: Option<u32>,
+    gid: Option<u32>,
+  ) -> Result<(), std::io::Error> {
+    #[cfg(unix)]
+    return std::os::unix::fs::chown(src, uid, gid);
+    #[cfg(not(unix))]
+    {
+      let _ = (src, uid, gid);
+      Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "disk_chown is only supported on Unix hosts"
+      ))
+    }
+  }
+
+  fn disk_cp_with(
+    &self,
+    src: &str,
+    dest: &str,
+    options: &CDiskCopyOptions,
+  ) -> Result<(), std::io::Error> {
+    let dest = disk_fs_dest(self, src, dest);
+    let (src_path, dest_path) = (
+      std::path::Path::new(src),
+      std::path::Path::new(&dest),
+    );
+    disk_copy_validate(src_path, dest_path)?;
+    CDiskCopyTask::new(self, true, src, options, false).copy(src, &dest)
+  }
+
+  fn disk_extract(
+    &self,
+    archive: &str,
+    dest: &str,
+    format: CDiskArchiveFormat,
+    options: &CDiskArchiveOptions,
+  ) -> Result<usize, std::io::Error> {
+    std::fs::create_dir_all(dest)?;
+    let dest = &std::fs::canonicalize(dest)?;
+    let mut progress = CDiskProgress::default();
+    match format {
+      CDiskArchiveFormat::Zip => {
+        disk_extract_zip(archive, dest, options, &mut progress)
+      },
+      _ => {
+        let mut extract = |counting| disk_extract_tar(
+          archive, dest, format, options, &mut progress, counting
+        );
+        if options.progress.is_some() {
+          extract(true)?;
+        }
+        extract(false)
+      },
+    }
+  }
+
+  fn disk_hard_link(
+    &self,
+    src: &str,
+    link: &str,
+  ) -> Result<(), std::io::Error> {
+    std::fs::hard_link(src, link)
+  }
+
+  fn disk_hash_file(
+    &self,
+    filename: &str,
+    algorithm: CDiskHashAlgorithm,
+  ) -> Result<String, std::io::Error> {
+    let mut file = std::fs::File::open(filename)?;
+    CDiskHasher::new(algorithm).read(&mut file)
+  }
+
+  fn disk_lock(
+    &self,
+    filename: &str,
+    kind: CDiskLockKind,
+    wait: CDiskLockWait,
+  ) -> Result<CDiskLock, std::io::Error> {
+    let file = std::fs::OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(filename)
+      .or_else(|why| match kind {
+        // Read only files can still be locked for reading.
+        CDiskLockKind::Shared
+            if why.kind() == std::io::ErrorKind::PermissionDenied => {
+          std::fs::File::open(filename)
+        },
+        _ => Err(why),
+      })?;
+    disk_lock_file(&file, kind, wait)?;
+    Ok(CDiskLock { file, kind, path: filename.to_string() })
+  }
+
+  fn disk_ls(&self, src: &str) -> Result<std::fs::ReadDir, std::io::Error> {
+    std::fs::read_dir(src)
+  }
+
+  fn disk_map_file(&self, filename: &str) -> Result<CFileMap, std::io::Error> {
+    let file = std::fs::File::open(filename)?;
+    // SAFETY: The map is read only. Changes made to the file by others while
+    // mapped are the documented caller responsibility.
+    let mmap = unsafe { memmap2::Mmap::map(&file)? };
+    Ok(CFileMap { mmap })
+  }
+
+  fn disk_metadata(
+    &self,
+    src: &str,
+  ) -> Result<std::fs::Metadata, std::io::Error> {
+    std::path::Path::new(src).metadata()
+  }
+
+  fn disk_mv_with(
+    &self,
+    src: &str,
+    dest: &str,
+    options: &CDiskCopyOptions,
+  ) -> Result<(), std::io::Error> {
+    let dest = disk_fs_dest(self, src, dest);
+    let (src_path, dest_path) = (
+      std::path::Path::new(src),
+      std::path::Path::new(&dest),
+    );
+    disk_copy_validate(src_path, dest_path)?;
+    CDiskCopyTask::new(self, true, src, options, true).move_to(src, &dest)
+  }
+
+  fn disk_open_reader(
+    &self,
+    filename: &str,
+  ) -> Result<CFileReader, std::io::Error> {
+    let file = std::fs::File::open(filename)?;
+    Ok(CFileReader { reader: std::io::BufReader::new(file) })
+  }
+
+  fn disk_open_writer(
+    &self,
+    filename: &str,
+    mode: CDiskWriteMode,
+  ) -> Result<CFileWriter, std::io::Error> {
+    let path = std::path::Path::new(filename);
+    let mut options = std::fs::OpenOptions::new();
+    let mut atomic = None;
+    let file = match mode {
+      CDiskWriteMode::Append => options.append(true).create(true).open(path)?,
+      CDiskWriteMode::Atomic => {
+        let temp = disk_temp_path(path);
+        let file = options.write(true).create_new(true).open(&temp)?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+          file.set_permissions(metadata.permissions())?;
+        }
+        atomic = Some((temp, path.to_path_buf()));
+        file
+      },
+      CDiskWriteMode::CreateNew => {
+        options.write(true).create_new(true).open(path)?
+      },
+      CDiskWriteMode::Truncate => {
+        options.write(true).create(true).truncate(true).open(path)?
+      },
+    };
+    Ok(CFileWriter { writer: std::io::BufWriter::new(file), atomic })
+  }
+
+  fn disk_pid_file(
+    &self,
+    filename: &str,
+    wait: CDiskLockWait,
+  ) -> Result<CDiskPidFile, std::io::Error> {
+    let path = std::path::PathBuf::from(filename);
+    loop {
+      let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+      if let Err(why) = disk_lock_file(&file, CDiskLockKind::Exclusive, wait) {
+        let owner = std::fs::read_to_string(&path).unwrap_or_default();
+        return Err(std::io::Error::new(
+          why.kind(),
+          format!("{} is owned by process {}", filename, owner.trim()),
+        ));
+      }
+
+      // Starts over when the previous owner removed the file while waited on.
+      if !disk_same_file(&file, &path) {
+        continue;
+      }
+
+      let mut contents = String::new();
+      std::io::Read::read_to_string(&mut file, &mut contents)?;
+      let stale_pid = contents.trim().parse::<u32>().ok()
+        .filter(|v| *v != std::process::id());
+      file.set_len(0)?;
+      std::io::Seek::rewind(&mut file)?;
+      std::io::Write::write_all(
+        &mut file,
+        format!("{}\n", std::process::id()).as_bytes(),
+      )?;
+      file.sync_all()?;
+      return Ok(CDiskPidFile { file: Some(file), path, stale_pid });
+    }
+  }
+
+  fn disk_read_link(&self, link: &str) -> Result<String, std::io::Error> {
+    Ok(std::fs::read_link(link)?.to_string_lossy().to_string())
+  }
+
+  fn disk_set_times(
+    &self,
+    src: &str,
+    accessed: Option<std::time::SystemTime>,
+    modified: Option<std::time::SystemTime>,
+  ) -> Result<(), std::io::Error> {
+    #[cfg(windows)]
+    let file = {
+      use std::os::windows::fs::OpenOptionsExt;
+      // FILE_WRITE_ATTRIBUTES with FILE_FLAG_BACKUP_SEMANTICS for directories.
+      std::fs::OpenOptions::new()
+        .access_mode(0x100)
+        .custom_flags(0x02000000)
+        .open(src)?
+    };
+    #[cfg(not(windows))]
+    let file = std::fs::File::open(src)?;
+    let mut times = std::fs::FileTimes::new();
+    if let Some(v) = accessed {
+      times = times.set_accessed(v);
+    }
+    if let Some(v) = modified {
+      times = times.set_modified(v);
+    }
+    file.set_times(times)
+  }
+
+  fn disk_symlink(
+    &self,
+    target: &str,
+    link: &str,
+  ) -> Result<(), std::io::Error> {
+    disk_make_link(std::path::Path::new(target), std::path::Path::new(link))
+  }
+
+  fn disk_temp_dir_with(
+    &self,
+    options: &CDiskTempOptions,
+  ) -> Result<CDiskTempDir, std::io::Error> {
+    let (path, _) = disk_temp_create(options, |path| {
+      let mut builder = std::fs::DirBuilder::new();
+      #[cfg(unix)]
+      std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+      builder.create(path)
+    })?;
+    Ok(CDiskTempDir { path: Some(path) })
+  }
+
+  fn disk_temp_file_with(
+    &self,
+    options: &CDiskTempOptions,
+  ) -> Result<CDiskTempFile, std::io::Error> {
+    let (path, file) = disk_temp_create(options, |path| {
+      let mut opts = std::fs::OpenOptions::new();
+      opts.read(true).write(true).create_new(true);
+      #[cfg(unix)]
+      std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+      opts.open(path)
+    })?;
+    Ok(CDiskTempFile { file: Some(file), path: Some(path) })
+  }
+
+  fn disk_usage(
+    &self,
+    src: &str,
+    largest: usize,
+    parallel: bool,
+  ) -> Result<CDiskUsage, std::io::Error> {
+    let root = std::path::PathBuf::from(src);
+    if !std::fs::metadata(&root)?.is_dir() {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::NotADirectory,
+        format!("{} is not a directory", src),
+      ));
+    }
+    let task = CDiskUsageTask {
+      largest,
+      queue: std::sync::Mutex::new((vec![root.clone()], 0)),
+      ready: std::sync::Condvar::new(),
+      totals: std::sync::Mutex::new(Vec::new()),
+      files: std::sync::Mutex::new(Vec::new()),
+      inodes: std::sync::Mutex::new(std::collections::HashSet::new()),
+      skipped: std::sync::atomic::AtomicUsize::new(0),
+    };
+    let workers = match parallel {
+      true => {
+        std::thread::available_parallelism().map(|v| v.get()).unwrap_or(1)
+      },
+      false => 1,
+    };
+    std::thread::scope(|scope| {
+      for _ in 0..workers {
+        scope.spawn(|| task.work());
+      }
+    });
+
+    // Adds the totals of each directory into its parents deepest first.
+    let mut totals = task.totals.into_inner().unwrap();
+    totals.sort_by(|a, b| a.0.cmp(&b.0));
+    let index = totals.iter().enumerate()
+      .map(|(i, v)| (v.0.clone(), i))
+      .collect::<std::collections::HashMap<_, _>>();
+    let mut order = (0..totals.len()).collect::<Vec<usize>>();
+    order.sort_by_key(|v| std::cmp::Reverse(totals[*v].0.components().count()));
+    for i in order {
+      let parent = totals[i].0.parent().and_then(|v| index.get(v)).copied();
+      if let Some(parent) = parent {
+        let child = totals[i].1.clone();
+        totals[parent].1.apparent_size += child.apparent_size;
+        totals[parent].1.allocated_size += child.allocated_size;
+        totals[parent].1.files += child.files;
+      }
+    }
+    let breakdown = totals.into_iter().map(|v| v.1).collect::<Vec<_>>();
+    Ok(CDiskUsage {
+      total: breakdown[0].clone(),
+      directories: breakdown.len() - 1,
+      skipped: task.skipped.into_inner(),
+      breakdown,
+      largest: task.files.into_inner().unwrap(),
+    })
+  }
+
+  fn disk_walk(&self, src: &str) -> Result<CDiskWalker, std::io::Error> {
+    Ok(CDiskWalker::new(src))
+  }
+
+  fn disk_watch_with(
+    &self,
+    path: &str,
+    recursive: bool,
+    options: &CDiskWatchOptions,
+  ) -> Result<CDiskWatcher, std::io::Error> {
+    CDiskWatcher::new(path, recursive, options)
+  }
+}
+
+/// A copy-on-write [CDiskFileSystem] overlaying a read-only lower file
+/// system. Changes are held in a [CDiskMemoryFileSystem] upper layer with
+/// removals of lower entries hiding them so the lower file system is never
+/// modified.
+pub struct CDiskOverlayFileSystem {
+  lower: std::sync::Arc<dyn CDiskFileSystem>,
+  upper: CDiskMemoryFileSystem,
+  removed: std::sync::Mutex<std::collections::HashSet<std::path::PathBuf>>,
+}
+impl CDiskOverlayFileSystem {
+  /// Creates the overlay of the lower file system.
+  pub fn new(
+    lower: std::sync::Arc<dyn CDiskFileSystem>,
+  ) -> CDiskOverlayFileSystem {
+    CDiskOverlayFileSystem {
+      lower,
+      upper: CDiskMemoryFileSystem::new(),
+      removed: std::sync::Mutex::new(std::collections::HashSet::new()),
+    }
+  }
+
+  /// Removes the entry from the upper layer and hides the lower one.
+  fn hide(
+    &self,
+    path: &str,
+    remove: impl Fn(&CDiskMemoryFileSystem) -> Result<(), std::io::Error>,
+  ) -> Result<(), std::io::Error> {
+    let in_upper = self.upper.info(path, false).is_ok();
+    let in_lower = self.lower_info(path).is_some();
+    if in_upper {
+      remove(&self.upper)?;
+    } else if !in_lower {
+      return Err(CDiskMemoryFileSystem::error(
+        std::io::ErrorKind::NotFound, std::path::Path::new(path)
+      ));
+    }
+    if in_lower {
+      self.removed.lock().unwrap().insert(disk_fs_normalize(path));
+    }
+    Ok(())
+  }
+
+  /// Retrieves the info of the lower entry if not hidden by a removal.
+  fn lower_info(&self, path: &str) -> Option<CFileInfo> {
+    let removed = self.removed.lock().unwrap();
+    if disk_fs_normalize(path).ancestors().any(|v| removed.contains(v)) {
+      return None;
+    }
+    drop(removed);
+    self.lower.info(path, true).ok()
+  }
+
+  /// Copies the parent directories of the lower entry to the upper layer.
+  fn parent_dir(&self, path: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+      let parent = parent.to_string_lossy();
+      match self.info(&parent, true)?.kind() {
+        CFileKind::Directory => self.upper.create_dir_all(&parent)?,
+        _ => return Err(CDiskMemoryFileSystem::error(
+          std::io::ErrorKind::NotADirectory, std::path::Path::new(&*parent)
+        )),
+      }
+    }
+    Ok(())
+  }
+}
+impl CDiskFileSystem for CDiskOverlayFileSystem {
+  fn create_dir_all(&self, path: &str) -> Result<(), std::io::Error> {
+    let normalized = disk_fs_normalize(path);
+    for dir in normalized.ancestors() {
+      let kind = self.info(&dir.to_string_lossy(), true).map(|v| v.kind());
+      if kind.is_ok_and(|v| v != CFileKind::Directory) {
+        return Err(CDiskMemoryFileSystem::error(
+          std::io::ErrorKind::NotADirectory, dir
+        ));
+      }
+    }
+    self.upper.create_dir_all(path)
+  }
+
+  fn info(
+    &self,
+    path: &str,
+    follow_links: bool,
+  ) -> Result<CFileInfo, std::io::Error> {
+    match self.upper.info(path, follow_links) {
+      Ok(v) => Ok(v),
+      Err(why) => self.lower_info(path).ok_or(why),
+    }
+  }
+
+  fn read(&self, path: &str) -> Result<Vec<u8>, std::io::Error> {
+    match self.upper.info(path, true) {
+      Ok(_) => self.upper.read(path),
+      Err(why) if self.lower_info(path).is_none() => Err(why),
+      Err(_) => self.lower.read(path),
+    }
+  }
+
+  fn read_dir(&self, path: &str) -> Result<Vec<String>, std::io::Error> {
+    let upper = self.upper.read_dir(path);
+    let lower = match self.lower_info(path) {
+      Some(v) if v.kind() == CFileKind::Directory => {
+        Some(self.lower.read_dir(path)?)
+      },
+      _ => None,
+    };
+    let mut names = match (upper, lower) {
+      (Err(why), None) => return Err(why),
+      (upper, lower) => {
+        let mut names = upper.unwrap_or_default();
+        let removed = self.removed.lock().unwrap();
+        let dir = disk_fs_normalize(path);
+        names.extend(lower.unwrap_or_default().into_iter()
+          .filter(|v| !removed.contains(&dir.join(v))));
+        names
+      },
+    };
+    names.sort();
+    names.dedup();
+    Ok(names)
+  }
+
+  fn remove_dir(&self, path: &str) -> Result<(), std::io::Error> {
+    if !self.read_dir(path)?.is_empty() {
+      return Err(CDiskMemoryFileSystem::error(
+        std::io::ErrorKind::DirectoryNotEmpty, std::path::Path::new(path)
+      ));
+    }
+    self.hide(path, |upper| upper.remove_dir(path))
+  }
+
+  fn remove_file(&self, path: &str) -> Result<(), std::io::Error> {
+    if self.info(path, false)?.kind() == CFileKind::Directory {
+      return Err(CDiskMemoryFileSystem::error(
+        std::io::ErrorKind::IsADirectory, std::path::Path::new(path)
+      ));
+    }
+    self.hide(path, |upper| upper.remove_file(path))
+  }
+
+  fn rename(&self, from: &str, to: &str) -> Result<(), std::io::Error> {
+    let is_dir = self.info(from, false)?.kind() == CFileKind::Directory;
+    if disk_fs_normalize(from) == disk_fs_normalize(to) {
+      return Ok(());
+    } else if disk_fs_normalize(to).starts_with(disk_fs_normalize(from)) {
+      return Err(CDiskMemoryFileSystem::error(
+        std::io::ErrorKind::InvalidInput, std::path::Path::new(to)
+      ));
+    }
+    if let Ok(dest) = self.info(to, false) {
+      match (is_dir, dest.kind() == CFileKind::Directory) {
+        (false, true) => return Err(CDiskMemoryFileSystem::error(
+          std::io::ErrorKind::IsADirectory, std::path::Path::new(to)
+        )),
+        (true, false) => return Err(CDiskMemoryFileSystem::error(
+          std::io::ErrorKind::NotADirectory, std::path::Path::new(to)
+        )),
+        _ => self.remove_all(to)?,
+      }
+    }
+    self.copy(from, to)?;
+    self.remove_all(from)
+  }
+
+  fn write(
+    &self,
+    path: &str,
+    data: &[u8],
+    mode: CDiskWriteMode,
+  ) -> Result<(), std::io::Error> {
+    self.parent_dir(path)?;
+    let in_upper = self.upper.info(path, true).is_ok();
+    match self.lower_info(path) {
+      Some(_) if mode == CDiskWriteMode::CreateNew => {
+        Err(CDiskMemoryFileSystem::error(
+          std::io::ErrorKind::AlreadyExists, std::path::Path::new(path)
+        ))
+      },
+      Some(v) if !in_upper && v.kind() == CFileKind::Directory => {
+        Err(CDiskMemoryFileSystem::error(
+          std::io::ErrorKind::IsADirectory, std::path::Path::new(path)
+        ))
+      },
+      Some(_) if !in_upper && mode == CDiskWriteMode::Append => {
+        let mut contents = self.lower.read(path)?;
+        contents.extend_from_slice(data);
+        self.upper.write(path, &contents, CDiskWriteMode::Truncate)
+      },
+      _ => self.upper.write(path, data, mode),
+    }
+  }
+}
+
+/// A chroot style [CDiskFileSystem] confining paths to a root directory of
+/// the host operating system. Paths are resolved from the root with `..`
+/// unable to climb above it and symbolic links leading out of it denied.
+#[derive(Clone, Debug)]
+pub struct CDiskSandboxFileSystem {
+  root: std::path::PathBuf,
+}
+impl CDiskSandboxFileSystem {
+  /// Creates the sandbox of the existing root directory.
+  pub fn new(root: &str) -> Result<CDiskSandboxFileSystem, std::io::Error> {
+    let root = std::fs::canonicalize(root)?;
+    if !root.is_dir() {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::NotADirectory,
+        format!("{} is not a directory", root.display()),
+      ));
+    }
+    Ok(CDiskSandboxFileSystem { root })
+  }
+
+  /// Resolves the path to its host path within the root.
+  fn resolve(&self, path: &str) -> Result<String, std::io::Error> {
+    let normalized = disk_fs_normalize(path);
+    let relative = normalized.strip_prefix("/").unwrap_or(&normalized);
+    let resolved = self.root.join(relative);
+    let existing = resolved.ancestors()
+      .find(|v| std::fs::symlink_metadata(v).is_ok())
+      .and_then(|v| std::fs::canonicalize(v).ok());
+    if existing.is_none_or(|v| !v.starts_with(&self.root)) {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        format!("{} leads outside of the sandbox", path),
+      ));
+    }
+    Ok(resolved.to_string_lossy().to_string())
+  }
+}
+impl CDiskFileSystem for CDiskSandboxFileSystem {
+  fn create_dir_all(&self, path: &str) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.create_dir_all(&self.resolve(path)?)
+  }
+
+  fn info(
+    &self,
+    path: &str,
+    follow_links: bool,
+  ) -> Result<CFileInfo, std::io::Error> {
+    let mut info = CDiskOsFileSystem.info(&self.resolve(path)?, follow_links)?;
+    info.path = path.to_string();
+    Ok(info)
+  }
+
+  fn read(&self, path: &str) -> Result<Vec<u8>, std::io::Error> {
+    CDiskOsFileSystem.read(&self.resolve(path)?)
+  }
+
+  fn read_dir(&self, path: &str) -> Result<Vec<String>, std::io::Error> {
+    CDiskOsFileSystem.read_dir(&self.resolve(path)?)
+  }
+
+  fn remove_dir(&self, path: &str) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.remove_dir(&self.resolve(path)?)
+  }
+
+  fn remove_file(&self, path: &str) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.remove_file(&self.resolve(path)?)
+  }
+
+  fn rename(&self, from: &str, to: &str) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.rename(&self.resolve(from)?, &self.resolve(to)?)
+  }
+
+  fn write(
+    &self,
+    path: &str,
+    data: &[u8],
+    mode: CDiskWriteMode,
+  ) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.write(&self.resolve(path)?, data, mode)
+  }
+
+  fn disk_archive(
+    &self,
+    src: &str,
+    archive: &str,
+    format: CDiskArchiveFormat,
+    options: &CDiskArchiveOptions,
+  ) -> Result<usize, std::io::Error> {
+    let (src, archive) = (self.resolve(src)?, self.resolve(archive)?);
+    CDiskOsFileSystem.disk_archive(&src, &archive, format, options)
+  }
+
+  fn disk_chmod(&self, src: &str, mode: u32) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.disk_chmod(&self.resolve(src)?, mode)
+  }
+
+  fn disk_chown(
+    &self,
+    src: &str,
+    uid: Option<u32>,
+    gid: Option<u32>,
+  ) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.disk_chown(&self.resolve(src)?, uid, gid)
+  }
+
+  fn disk_extract(
+    &self,
+    archive: &str,
+    dest: &str,
+    format: CDiskArchiveFormat,
+    options: &CDiskArchiveOptions,
+  ) -> Result<usize, std::io::Error> {
+    let (archive, dest) = (self.resolve(archive)?, self.resolve(dest)?);
+    CDiskOsFileSystem.disk_extract(&archive, &dest, format, options)
+  }
+
+  fn disk_hard_link(
+    &self,
+    src: &str,
+    link: &str,
+  ) -> Result<(), std::io::Error> {
+    let (src, link) = (self.resolve(src)?, self.resolve(link)?);
+    CDiskOsFileSystem.disk_hard_link(&src, &link)
+  }
+
+  fn disk_lock(
+    &self,
+    filename: &str,
+    kind: CDiskLockKind,
+    wait: CDiskLockWait,
+  ) -> Result<CDiskLock, std::io::Error> {
+    let mut lock = CDiskOsFileSystem.disk_lock(
+      &self.resolve(filename)?, kind, wait
+    )?;
+    lock.path = filename.to_string();
+    Ok(lock)
+  }
+
+  fn disk_ls(&self, src: &str) -> Result<std::fs::ReadDir, std::io::Error> {
+    CDiskOsFileSystem.disk_ls(&self.resolve(src)?)
+  }
+
+  fn disk_map_file(&self, filename: &str) -> Result<CFileMap, std::io::Error> {
+    CDiskOsFileSystem.disk_map_file(&self.resolve(filename)?)
+  }
+
+  fn disk_metadata(
+    &self,
+    src: &str,
+  ) -> Result<std::fs::Metadata, std::io::Error> {
+    CDiskOsFileSystem.disk_metadata(&self.resolve(src)?)
+  }
+
+  fn disk_open_reader(
+    &self,
+    filename: &str,
+  ) -> Result<CFileReader, std::io::Error> {
+    CDiskOsFileSystem.disk_open_reader(&self.resolve(filename)?)
+  }
+
+  fn disk_open_writer(
+    &self,
+    filename: &str,
+    mode: CDiskWriteMode,
+  ) -> Result<CFileWriter, std::io::Error> {
+    CDiskOsFileSystem.disk_open_writer(&self.resolve(filename)?, mode)
+  }
+
+  fn disk_pid_file(
+    &self,
+    filename: &str,
+    wait: CDiskLockWait,
+  ) -> Result<CDiskPidFile, std::io::Error> {
+    CDiskOsFileSystem.disk_pid_file(&self.resolve(filename)?, wait)
+  }
+
+  fn disk_read_link(&self, link: &str) -> Result<String, std::io::Error> {
+    CDiskOsFileSystem.disk_read_link(&self.resolve(link)?)
+  }
+
+  fn disk_set_times(
+    &self,
+    src: &str,
+    accessed: Option<std::time::SystemTime>,
+    modified: Option<std::time::SystemTime>,
+  ) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.disk_set_times(&self.resolve(src)?, accessed, modified)
+  }
+
+  fn disk_symlink(
+    &self,
+    target: &str,
+    link: &str,
+  ) -> Result<(), std::io::Error> {
+    CDiskOsFileSystem.disk_symlink(target, &self.resolve(link)?)
+  }
+
+  fn disk_temp_dir_with(
+    &self,
+    options: &CDiskTempOptions,
+  ) -> Result<CDiskTempDir, std::io::Error> {
+    let options = options.clone().dir(&self.resolve(&options.dir)?);
+    CDiskOsFileSystem.disk_temp_dir_with(&options)
+  }
+
+  fn disk_temp_file_with(
+    &self,
+    options: &CDiskTempOptions,
+  ) -> Result<CDiskTempFile, std::io::Error> {
+    let options = options.clone().dir(&self.resolve(&options.dir)?);
+    CDiskOsFileSystem.disk_temp_file_with(&options)
+  }
+
+  fn disk_usage(
+    &self,
+    src: &str,
+    largest: usize,
+    parallel: bool,
+  ) -> Result<CDiskUsage, std::io::Error> {
+    CDiskOsFileSystem.disk_usage(&self.resolve(src)?, largest, parallel)
+  }
+
+  fn disk_walk(&self, src: &str) -> Result<CDiskWalker, std::io::Error> {
+    CDiskOsFileSystem.disk_walk(&self.resolve(src)?)
+  }
+
+  fn disk_watch_with(
+    &self,
+    path: &str,
+    recursive: bool,
+    options: &CDiskWatchOptions,
+  ) -> Result<CDiskWatcher, std::io::Error> {
+    let path = self.resolve(path)?;
+    CDiskOsFileSystem.disk_watch_with(&path, recursive, options)
+  }
+}
+
+/// Identifies the kind of advisory lock taken by [disk_lock].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDiskLockKind {
+  /// Only one process may hold the lock (i.e. for writing).
+  Exclusive,
+  /// Any number of processes may hold the lock while no one holds it
+  /// exclusively (i.e. for reading).
+  Shared,
+}
+
+/// Identifies how [disk_lock] / [disk_pid_file] wait on a lock held by
+/// another process.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDiskLockWait {
+  /// Waits until the lock is released.
+  Block,
+  /// Waits up to the milliseconds failing with
+  /// [std::io::ErrorKind::TimedOut].
+  Timeout(u64),
+  /// Fails right away with [std::io::ErrorKind::WouldBlock].
+  Try,
+}
+
+/// The result of a [disk_lock] call holding an advisory lock of the file
+/// until unlocked or dropped. Advisory locks only coordinate processes
+/// that lock the same file. They don't prevent others from accessing it.
+#[derive(Debug)]
+pub struct CDiskLock {
+  file: std::fs::File,
+  kind: CDiskLockKind,
+  path: String,
+}
+impl CDiskLock {
+  /// The kind of lock held.
+  pub fn kind(&self) -> CDiskLockKind {
+    self.kind
+  }
+
+  /// The path of the locked file.
+  pub fn path(&self) -> String {
+    self.path.to_string()
+  }
+
+  /// Releases the lock reporting any failure to do so.
+  pub fn unlock(self) -> Result<(), std::io::Error> {
+    self.file.unlock()
+  }
+}
+impl Drop for CDiskLock {
+  fn drop(&mut self) {
+    let _ = self.file.unlock();
+  }
+}
+
+/// The result of a [disk_pid_file] call holding the lock file recording
+/// the id of the process owning it. The file is exclusively locked while
+/// held so a file left behind by an exited process is detected as stale
+/// and taken over. Removed when released or dropped.
+#[derive(Debug)]
+pub struct CDiskPidFile {
+  file: Option<std::fs::File>,
+  path: std::path::PathBuf,
+  stale_pid: Option<u32>,
+}
+impl CDiskPidFile {
+  /// The path of the lock file.
+  pub fn path(&self) -> String {
+    self.path.to_string_lossy().to_string()
+  }
+
+  /// The id of the process owning the lock file (this process).
+  pub fn pid(&self) -> u32 {
+    std::process::id()
+  }
+
+  /// Removes the lock file and releases its lock reporting any failure to
+  /// do so.
+  pub fn release(mut self) -> Result<(), std::io::Error> {
+    self.remove()
+  }
+
+  /// Removes the lock file. On unix it is removed while still locked so a
+  /// process waiting on it starts over. Windows can't remove an open file
+  /// so it is emptied, unlocked, and closed first and then only removed if
+  /// no other process took it over in between.
+  fn remove(&mut self) -> Result<(), std::io::Error> {
+    let Some(file) = self.file.take() else {
+      return Ok(());
+    };
+    #[cfg(unix)]
+    {
+      let result = std::fs::remove_file(&self.path);
+      file.unlock()?;
+      result
+    }
+    #[cfg(not(unix))]
+    {
+      file.set_len(0)?;
+      file.unlock()?;
+      drop(file);
+      let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&self.path)?;
+      let owned = match file.try_lock() {
+        Ok(_) => file.metadata()?.len() == 0,
+        Err(std::fs::TryLockError::WouldBlock) => false,
+        Err(std::fs::TryLockError::Error(why)) => return Err(why),
+      };
+      drop(file);
+      if !owned {
+        return Ok(());
+      }
+      std::fs::remove_file(&self.path)
+    }
+  }
+
+  /// The id of the process that left behind the stale lock file taken
+  /// over, if any.
+  pub fn stale_pid(&self) -> Option<u32> {
+    self.stale_pid
+  }
+}
+impl Drop for CDiskPidFile {
+  fn drop(&mut self) {
+    let _ = self.remove();
+  }
+}
+
+/// Identifies the hash algorithm of the [disk_hash_file] /
+/// [CFileContents::hash] calls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDiskHashAlgorithm {
+  /// BLAKE3 with a 256-bit result.
+  Blake3,
+  /// CRC-32 (IEEE) checksum.
+  Crc32,
+  /// MD5 (legacy use only as it is not collision resistant).
+  Md5,
+  /// SHA-1 (legacy use only as it is not collision resistant).
+  Sha1,
+  /// SHA-256.
+  Sha256,
+}
+
+/// Holds the running state of a [CDiskHashAlgorithm] hash.
+enum CDiskHasher {
+  Blake3(Box<blake3::Hasher>),
+  Crc32(crc32fast::Hasher),
+  Md5(md5::Md5),
+  Sha1(sha1::Sha1),
+  Sha256(sha2::Sha256),
+}
+impl CDiskHasher {
+  /// Creates the hasher of the algorithm.
+  fn new(algorithm: CDiskHashAlgorithm) -> CDiskHasher {
+    use sha2::Digest;
+    match algorithm {
+      CDiskHashAlgorithm::Blake3 => {
+        CDiskHasher::Blake3(Box::new(blake3::Hasher::new()))
+      },
+      CDiskHashAlgorithm::Crc32 => CDiskHasher::Crc32(crc32fast::Hasher::new()),
+      CDiskHashAlgorithm::Md5 => CDiskHasher::Md5(md5::Md5::new()),
+      CDiskHashAlgorithm::Sha1 => CDiskHasher::Sha1(sha1::Sha1::new()),
+      CDiskHashAlgorithm::Sha256 => CDiskHasher::Sha256(sha2::Sha256::new()),
+    }
+  }
+
+  /// Hashes the rest of the reader returning the lowercase hex hash.
+  fn read<R: std::io::Read>(
+    mut self,
+    reader: &mut R,
+  ) -> Result<String, std::io::Error> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+      let read = reader.read(&mut buffer)?;
+      if read == 0 {
+        return Ok(self.finish());
+      }
+      self.update(&buffer[..read]);
+    }
+  }
+
+  /// Adds the data to the hash.
+  fn update(&mut self, data: &[u8]) {
+    use sha2::Digest;
+    match self {
+      CDiskHasher::Blake3(v) => {
+        v.update(data);
+      },
+      CDiskHasher::Crc32(v) => v.update(data),
+      CDiskHasher::Md5(v) => v.update(data),
+      CDiskHasher::Sha1(v) => v.update(data),
+      CDiskHasher::Sha256(v) => v.update(data),
+    }
+  }
+
+  /// Completes the hash returning it as lowercase hex.
+  fn finish(self) -> String {
+    use sha2::Digest;
+    let hash = match self {
+      CDiskHasher::Blake3(v) => v.finalize().as_bytes().to_vec(),
+      CDiskHasher::Crc32(v) => v.finalize().to_be_bytes().to_vec(),
+      CDiskHasher::Md5(v) => v.finalize().to_vec(),
+      CDiskHasher::Sha1(v) => v.finalize().to_vec(),
+      CDiskHasher::Sha256(v) => v.finalize().to_vec(),
+    };
+    hash.iter().map(|v| format!("{:02x}", v)).collect()
+  }
+}
+
+/// The result of each file checked by a [disk_hash_verify] call.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDiskHashStatus {
+  /// The hash of the file does not match the manifest.
+  Failed,
+  /// The file could not be read.
+  Missing,
+  /// The hash of the file matches the manifest.
+  Ok,
+}
+
+/// Options of the [disk_temp_dir_with] / [disk_temp_file_with] calls
+/// naming the temporary entry `{prefix}{unique id}{suffix}` within the dir.
+/// Defaults to the `codemelted_` prefix without a suffix within the
+/// [runtime_temp_path].
+#[derive(Clone, Debug)]
+pub struct CDiskTempOptions {
+  dir: String,
+  prefix: String,
+  suffix: String,
+}
+impl Default for CDiskTempOptions {
+  fn default() -> Self {
+    CDiskTempOptions::new()
+  }
+}
+impl CDiskTempOptions {
+  /// Creates the default options.
+  pub fn new() -> CDiskTempOptions {
+    CDiskTempOptions {
+      dir: runtime_temp_path(),
+      prefix: String::from("codemelted_"),
+      suffix: String::new(),
+    }
+  }
+
+  /// Sets the directory to create the temporary entry within.
+  pub fn dir(mut self, dir: &str) -> CDiskTempOptions {
+    self.dir = dir.to_string();
+    self
+  }
+
+  /// Sets the start of the temporary entry name.
+  pub fn prefix(mut self, prefix: &str) -> CDiskTempOptions {
+    self.prefix = prefix.to_string();
+    self
+  }
+
+  /// Sets the end of the temporary entry name (i.e. a file extension).
+  pub fn suffix(mut self, suffix: &str) -> CDiskTempOptions {
+    self.suffix = suffix.to_string();
+    self
+  }
+}
+
+/// The result of a [disk_temp_dir] / [disk_temp_dir_with] call holding a
+/// uniquely named directory that is removed along with its contents when
+/// dropped unless persisted.
+#[derive(Debug)]
+pub struct CDiskTempDir {
+  path: Option<std::path::PathBuf>,
+}
+impl CDiskTempDir {
+  /// Removes the directory and its contents reporting any failure to do so.
+  pub fn close(mut self) -> Result<(), std::io::Error> {
+    match self.path.take() {
+      Some(v) => std::fs::remove_dir_all(v),
+      None => Ok(()),
+    }
+  }
+
+  /// Creates the path of the name within the directory.
+  pub fn join(&self, name: &str) -> String {
+    self.as_path().join(name).to_string_lossy().to_string()
+  }
+
+  /// The path of the directory.
+  pub fn path(&self) -> String {
+    self.as_path().to_string_lossy().to_string()
+  }
+
+  /// Keeps the directory past the drop of the handle returning its path.
+  pub fn persist(mut self) -> String {
+    self.path.take().unwrap_or_default().to_string_lossy().to_string()
+  }
+
+  /// The path of the directory while held.
+  fn as_path(&self) -> &std::path::Path {
+    self.path.as_deref().unwrap_or(std::path::Path::new(""))
+  }
+}
+impl Drop for CDiskTempDir {
+  fn drop(&mut self) {
+    if let Some(path) = self.path.take() {
+      let _ = std::fs::remove_dir_all(path);
+    }
+  }
+}
+
+/// The result of a [disk_temp_file] / [disk_temp_file_with] call holding a
+/// uniquely named file opened for reading and writing that is removed when
+/// dropped unless persisted. Only the creating user may access it on unix.
+#[derive(Debug)]
+pub struct CDiskTempFile {
+  file: Option<std::fs::File>,
+  path: Option<std::path::PathBuf>,
+}
+impl CDiskTempFile {
+  /// Closes and removes the file reporting any failure to do so.
+  pub fn close(mut self) -> Result<(), std::io::Error> {
+    self.file.take();
+    match self.path.take() {
+      Some(v) => std::fs::remove_file(v),
+      None => Ok(()),
+    }
+  }
+
+  /// The opened file to hand to calls wanting a [std::fs::File] (i.e. the
+  /// stdio of a spawned process via [std::fs::File::try_clone]).
+  pub fn file(&self) -> &std::fs::File {
+    self.file.as_ref().expect("CDiskTempFile file is held until dropped")
+  }
+
+  /// The path of the file.
+  pub fn path(&self) -> String {
+    self.path.as_deref().unwrap_or(std::path::Path::new(""))
+      .to_string_lossy().to_string()
+  }
+
+  /// Flushes the file to disk and keeps it past the drop of the handle
+  /// returning its path.
+  pub fn persist(mut self) -> Result<String, std::io::Error> {
+    if let Some(file) = self.file.take() {
+      file.sync_all()?;
+    }
+    Ok(self.path.take().unwrap_or_default().to_string_lossy().to_string())
+  }
+
+  /// Flushes the file to disk and moves it to the filename (replacing any
+  /// file already there) returning its new path. If the flush or move fails
+  /// the handle is returned with the error still holding the file so it may
+  /// be retried or removed.
+  pub fn persist_as(
+    mut self,
+    filename: &str,
+  ) -> Result<String, (CDiskTempFile, std::io::Error)> {
+    let result = self.file().sync_all().and_then(|_| match &self.path {
+      Some(path) => std::fs::rename(path, filename),
+      None => Ok(()),
+    });
+    if let Err(why) = result {
+      return Err((self, why));
+    }
+    self.file.take();
+    self.path.take();
+    Ok(filename.to_string())
+  }
+}
+impl std::io::Read for CDiskTempFile {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut file = self.file();
+    file.read(buf)
+  }
+}
+impl std::io::Seek for CDiskTempFile {
+  fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+    let mut file = self.file();
+    file.seek(pos)
+  }
+}
+impl std::io::Write for CDiskTempFile {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    let mut file = self.file();
+    file.write(buf)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    let mut file = self.file();
+    file.flush()
+  }
+}
+impl Drop for CDiskTempFile {
+  fn drop(&mut self) {
+    self.file.take();
+    if let Some(path) = self.path.take() {
+      let _ = std::fs::remove_file(path);
+    }
+  }
+}
+
+/// The sizes of the files of a directory tree reported by a [disk_usage]
+/// call (within [CDiskUsage::breakdown]).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CDiskUsageEntry {
+  path: String,
+  apparent_size: u64,
+  allocated_size: u64,
+  files: usize,
+}
+impl CDiskUsageEntry {
+  /// The bytes allocated on disk for the files (the apparent size on hosts
+  /// that don't report it).
+  pub fn allocated_size(&self) -> u64 {
+    self.allocated_size
+  }
+
+  /// The bytes of the file contents.
+  pub fn apparent_size(&self) -> u64 {
+    self.apparent_size
+  }
+
+  /// The number of files (symbolic links included).
+  pub fn files(&self) -> usize {
+    self.files
+  }
+
+  /// The path of the directory.
+  pub fn path(&self) -> String {
+    self.path.to_string()
+  }
+}
+
+/// The result of a [disk_usage] call holding the total sizes of the
+/// directory tree, the totals of each of its directories, and its largest
+/// files. Hard linked files are counted once. Serializes to a [CObject] or
+/// to CSV (one row per directory) via [CCsvFormat].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CDiskUsage {
+  total: CDiskUsageEntry,
+  directories: usize,
+  skipped: usize,
+  breakdown: Vec<CDiskUsageEntry>,
+  largest: Vec<(String, u64)>,
+}
+impl CDiskUsage {
+  /// The bytes allocated on disk for all the files.
+  pub fn allocated_size(&self) -> u64 {
+    self.total.allocated_size
+  }
+
+  /// The bytes of all the file contents.
+  pub fn apparent_size(&self) -> u64 {
+    self.total.apparent_size
+  }
+
+  /// Serializes the usage into a [CObject].
+  pub fn as_object(&self) -> CObject {
+    let entry = |v: &CDiskUsageEntry| json::object!{
+      path: v.path.as_str(),
+      apparent_size: v.apparent_size,
+      allocated_size: v.allocated_size,
+      files: v.files,
+    };
+    let mut obj = entry(&self.total);
+    obj["directories"] = self.directories.into();
+    obj["skipped"] = self.skipped.into();
+    obj["breakdown"] = CObject::Array(
+      self.breakdown.iter().map(entry).collect()
+    );
+    obj["largest"] = CObject::Array(self.largest.iter()
+      .map(|(path, size)| json::object!{ path: path.as_str(), size: *size })
+      .collect());
+    obj
+  }
+
+  /// The totals of each directory of the tree (itself included) sorted by
+  /// path.
+  pub fn breakdown(&self) -> &[CDiskUsageEntry] {
+    &self.breakdown
+  }
+
+  /// The number of sub-directories.
+  pub fn directories(&self) -> usize {
+    self.directories
+  }
+
+  /// The number of files (symbolic links included).
+  pub fn files(&self) -> usize {
+    self.total.files
+  }
+
+  /// The largest files with their apparent size, largest first.
+  pub fn largest(&self) -> &[(String, u64)] {
+    &self.largest
+  }
+
+  /// The path of the directory tree.
+  pub fn path(&self) -> String {
+    self.total.path.to_string()
+  }
+
+  /// The number of entries that could not be read (i.e. permission denied).
+  pub fn skipped(&self) -> usize {
+    self.skipped
+  }
+}
+impl CCsvFormat for CDiskUsage {
+  fn csv_header(&self) -> String {
+    String::from("path,apparent_size,allocated_size,files")
+  }
+
+  fn as_csv(&self) -> String {
+    let mut csv_data = String::new();
+    for entry in &self.breakdown {
+      let path = CDbResultSet::to_csv_field(&CObject::from(entry.path()));
+      csv_data.push_str(&format!(
+        "{},{},{},{}\n",
+        path,
+        entry.apparent_size,
+        entry.allocated_size,
+        entry.files,
+      ));
+    }
+    csv_data
+  }
+}
+
+/// Carries out the [disk_usage] directory traversal via a queue of the
+/// directories to read shared by the worker threads.
+struct CDiskUsageTask {
+  largest: usize,
+  queue: std::sync::Mutex<(Vec<std::path::PathBuf>, usize)>,
+  ready: std::sync::Condvar,
+  totals: std::sync::Mutex<Vec<(std::path::PathBuf, CDiskUsageEntry)>>,
+  files: std::sync::Mutex<Vec<(String, u64)>>,
+  inodes: std::sync::Mutex<std::collections::HashSet<(u64, u64)>>,
+  skipped: std::sync::atomic::AtomicUsize,
+}
+impl CDiskUsageTask {
+  /// Reads the files of the directory returning its sub-directories.
+  fn read(&self, dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut total = CDiskUsageEntry {
+      path: dir.to_string_lossy().to_string(),
+      ..Default::default()
+    };
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let skip = || {
+      self.skipped.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    };
+    match std::fs::read_dir(dir) {
+      Ok(entries) => for entry in entries {
+        let Ok(entry) = entry else {
+          skip();
+          continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+          skip();
+          continue;
+        };
+        if metadata.is_dir() {
+          dirs.push(entry.path());
+          continue;
+        }
+        #[cfg(unix)]
+        let allocated = {
+          use std::os::unix::fs::MetadataExt;
+          if metadata.nlink() > 1 && !self.inodes.lock().unwrap()
+              .insert((metadata.dev(), metadata.ino())) {
+            continue;
+          }
+          metadata.blocks() * 512
+        };
+        #[cfg(not(unix))]
+        let allocated = metadata.len();
+        total.apparent_size += metadata.len();
+        total.allocated_size += allocated;
+        total.files += 1;
+        if self.largest > 0 {
+          files.push((entry.path().to_string_lossy().to_string(),
+            metadata.len()));
+        }
+      },
+      Err(_) => skip(),
+    }
+    if !files.is_empty() {
+      let mut largest = self.files.lock().unwrap();
+      largest.extend(files);
+      largest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+      largest.truncate(self.largest);
+    }
+    self.totals.lock().unwrap().push((dir.to_path_buf(), total));
+    dirs
+  }
+
+  /// Takes directories from the queue until all are read.
+  fn work(&self) {
+    loop {
+      let dir = {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+          if let Some(dir) = queue.0.pop() {
+            queue.1 += 1;
+            break dir;
+          }
+          if queue.1 == 0 {
+            return;
+          }
+          queue = self.ready.wait(queue).unwrap();
+        }
+      };
+      let dirs = self.read(&dir);
+      let mut queue = self.queue.lock().unwrap();
+      queue.0.extend(dirs);
+      queue.1 -= 1;
+      self.ready.notify_all();
+    }
+  }
+}
+
+/// Identifies the change reported by a [CDiskWatcher].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDiskWatchKind {
+  /// A file / directory was created (or moved into the watched path).
+  Create,
+  /// A file / directory was deleted (or moved out of the watched path).
+  Delete,
+  /// The contents or metadata of a file / directory changed.
+  Modify,
+  /// A file / directory was renamed within the watched path.
+  Rename,
+}
+
+/// A change received from a [CDiskWatcher].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CDiskWatchEvent {
+  kind: CDiskWatchKind,
+  path: String,
+  to: Option<String>,
+}
+impl CDiskWatchEvent {
+  /// The change that happened.
+  pub fn kind(&self) -> CDiskWatchKind {
+    self.kind
+  }
+
+  /// The path changed (the old path of a [CDiskWatchKind::Rename]).
+  pub fn path(&self) -> String {
+    self.path.to_string()
+  }
+
+  /// The new path of a [CDiskWatchKind::Rename].
+  pub fn to(&self) -> Option<String> {
+    self.to.clone()
+  }
+}
+
+/// Options of the [disk_watch_with] call. Defaults to a 100 millisecond
+/// debounce using the native watcher of the host operating system (i.e.
+/// inotify on Linux).
+#[derive(Clone, Debug)]
+pub struct CDiskWatchOptions {
+  debounce: u64,
+  poll: Option<u64>,
+}
+impl Default for CDiskWatchOptions {
+  fn default() -> Self {
+    CDiskWatchOptions::new()
+  }
+}
+impl CDiskWatchOptions {
+  /// Creates the default options.
+  pub fn new() -> CDiskWatchOptions {
+    CDiskWatchOptions { debounce: 100, poll: None }
+  }
+
+  /// Sets the milliseconds the path must be quiet before the collected
+  /// changes are reported. Repeated changes of a path within that window
+  /// are combined into one (i.e. a create followed by modifies is reported
+  /// as a create). 0 reports each change as it is received.
+  pub fn debounce(mut self, milliseconds: u64) -> CDiskWatchOptions {
+    self.debounce = milliseconds;
+    self
+  }
+
+  /// Scans the path for changes at the interval in milliseconds instead of
+  /// using the native watcher. Supports network filesystems the native
+  /// watcher can't see changes of. Renames are reported as a delete and
+  /// create.
+  pub fn poll(mut self, milliseconds: u64) -> CDiskWatchOptions {
+    self.poll = Some(milliseconds);
+    self
+  }
+}
+
+/// Collects the raw notify events of a [CDiskWatcher] pairing the renames
+/// and debouncing them before they are reported.
+struct CDiskWatchQueue {
+  debounce: std::time::Duration,
+  renames: Vec<(Option<usize>, String, std::time::Instant)>,
+  pending: Vec<CDiskWatchEvent>,
+  first: Option<std::time::Instant>,
+  last: Option<std::time::Instant>,
+}
+impl CDiskWatchQueue {
+  /// Creates the queue.
+  fn new(debounce: u64) -> CDiskWatchQueue {
+    CDiskWatchQueue {
+      debounce: std::time::Duration::from_millis(debounce),
+      renames: Vec::new(),
+      pending: Vec::new(),
+      first: None,
+      last: None,
+    }
+  }
+
+  /// Processes the raw events until the watcher is dropped or the
+  /// [CDiskWatcher] is no longer receiving.
+  fn run(
+    mut self,
+    raw_rx: std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+    tx: std::sync::mpsc::Sender<Result<CDiskWatchEvent, String>>,
+  ) {
+    let tick = std::time::Duration::from_millis(25);
+    loop {
+      let connected = match raw_rx.recv_timeout(tick) {
+        Ok(Ok(event)) => {
+          self.translate(event);
+          true
+        },
+        Ok(Err(why)) => tx.send(Err(why.to_string())).is_ok(),
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => true,
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => false,
+      };
+      for event in self.ready(!connected) {
+        if tx.send(Ok(event)).is_err() {
+          return;
+        }
+      }
+      if !connected {
+        return;
+      }
+    }
+  }
+
+  /// Adds the change combining it with a pending change of the path.
+  fn push(&mut self, kind: CDiskWatchKind, path: String, to: Option<String>) {
+    let now = std::time::Instant::now();
+    self.first.get_or_insert(now);
+    self.last = Some(now);
+    let found = self.pending.iter().position(|v| {
+      v.path == path && v.kind != CDiskWatchKind::Rename
+    });
+    if let (Some(index), None) = (found, &to) {
+      let combined = match (self.pending[index].kind, kind) {
+        (CDiskWatchKind::Create, CDiskWatchKind::Delete) => None,
+        (CDiskWatchKind::Create, _) => Some(CDiskWatchKind::Create),
+        (CDiskWatchKind::Delete, CDiskWatchKind::Create) => {
+          Some(CDiskWatchKind::Modify)
+        },
+        (_, kind) => Some(kind),
+      };
+      match combined {
+        Some(kind) => self.pending[index].kind = kind,
+        None => {
+          self.pending.remove(index);
+        },
+      }
+      return;
+    }
+    self.pending.push(CDiskWatchEvent { kind, path, to });
+  }
+
+  /// Retrieves the changes ready to report.
+  fn ready(&mut self, all: bool) -> Vec<CDiskWatchEvent> {
+    // Renames not completed within the grace period left the watched path.
+    let grace = self.debounce.max(std::time::Duration::from_millis(50));
+    let expired = self.renames.iter()
+      .filter(|v| all || v.2.elapsed() >= grace)
+      .map(|v| v.1.to_string())
+      .collect::<Vec<String>>();
+    self.renames.retain(|v| !all && v.2.elapsed() < grace);
+    for path in expired {
+      self.push(CDiskWatchKind::Delete, path, None);
+    }
+
+    // Reported once quiet or after being held for ten debounce periods.
+    let quiet = self.last.is_some_and(|v| v.elapsed() >= self.debounce);
+    let held = self.first.is_some_and(|v| v.elapsed() >= self.debounce * 10);
+    if all || quiet || held {
+      self.first = None;
+      self.last = None;
+      return std::mem::take(&mut self.pending);
+    }
+    Vec::new()
+  }
+
+  /// Translates the notify event into the [CDiskWatchKind] changes.
+  fn translate(&mut self, event: notify::Event) {
+    use notify::event::{EventKind, ModifyKind, RenameMode};
+    let tracker = event.tracker();
+    let mut paths = event.paths.into_iter()
+      .map(|v| v.to_string_lossy().to_string());
+    let Some(path) = paths.next() else {
+      return;
+    };
+    match event.kind {
+      EventKind::Create(_) => self.push(CDiskWatchKind::Create, path, None),
+      EventKind::Remove(_) => self.push(CDiskWatchKind::Delete, path, None),
+      EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+        self.renames.push((tracker, path, std::time::Instant::now()));
+      },
+      EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+        match self.renames.iter().position(|v| v.0 == tracker) {
+          Some(index) => {
+            let from = self.renames.remove(index).1;
+            self.push(CDiskWatchKind::Rename, from, Some(path));
+          },
+          None => self.push(CDiskWatchKind::Create, path, None),
+        }
+      },
+      // Already reported via the From / To pair.
+      EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {},
+      EventKind::Modify(ModifyKind::Name(_)) => {
+        if std::path::Path::new(&path).exists() {
+          self.push(CDiskWatchKind::Create, path, None);
+        } else {
+          self.push(CDiskWatchKind::Delete, path, None);
+        }
+      },
+      EventKind::Modify(_) => self.push(CDiskWatchKind::Modify, path, None),
+      _ => {},
+    }
+  }
+}
+
+/// The result of a [disk_watch] / [disk_watch_with] call receiving the
+/// changes of the watched path as [CDiskWatchEvent] items via the
+/// [CProtocolHandler::get_message] until terminated.
+pub struct CDiskWatcher {
+  id: String,
+  watcher: Option<Box<dyn notify::Watcher + Send>>,
+  protocol_rx: std::sync::mpsc::Receiver<Result<CDiskWatchEvent, String>>,
+}
+impl CDiskWatcher {
+  /// Private constructor to support the [disk_watch_with] function.
+  fn new(
+    path: &str,
+    recursive: bool,
+    options: &CDiskWatchOptions,
+  ) -> Result<CDiskWatcher, std::io::Error> {
+    let (raw_tx, raw_rx) = std::sync::mpsc::channel();
+    let (tx, protocol_rx) = std::sync::mpsc::channel();
+    let to_error = |why: notify::Error| match why.kind {
+      notify::ErrorKind::Io(why) => why,
+      notify::ErrorKind::PathNotFound => {
+        std::io::Error::new(std::io::ErrorKind::NotFound, why.to_string())
+      },
+      _ => std::io::Error::other(why.to_string()),
+    };
+    let mut watcher: Box<dyn notify::Watcher + Send> = match options.poll {
+      Some(interval) => Box::new(notify::PollWatcher::new(
+        raw_tx,
+        notify::Config::default()
+          .with_poll_interval(std::time::Duration::from_millis(interval)),
+      ).map_err(to_error)?),
+      None => Box::new(<notify::RecommendedWatcher as notify::Watcher>::new(
+        raw_tx,
+        notify::Config::default(),
+      ).map_err(to_error)?),
+    };
+    let mode = if recursive {
+      notify::RecursiveMode::Recursive
+    } else {
+      notify::RecursiveMode::NonRecursive
+    };
+    watcher.watch(std::path::Path::new(path), mode).map_err(to_error)?;
+    let queue = CDiskWatchQueue::new(options.debounce);
+    std::thread::spawn(move || queue.run(raw_rx, tx));
+    Ok(CDiskWatcher {
+      id: format!("disk_watch:{}", path),
+      watcher: Some(watcher),
+      protocol_rx,
+    })
+  }
+}
+impl CProtocolHandler<Option<CDiskWatchEvent>> for CDiskWatcher {
+  fn id(&mut self) -> String {
+    self.id.to_string()
+  }
+
+  fn get_message(
+    &mut self,
+    _request: Option<&str>
+  ) -> Result<Option<CDiskWatchEvent>, std::io::Error> {
+    match self.protocol_rx.try_recv() {
+      Ok(Ok(v)) => Ok(Some(v)),
+      Ok(Err(why)) => Err(std::io::Error::other(why)),
+      Err(std::sync::mpsc::TryRecvError::Empty) => Ok(None),
+      Err(why) => Err(std::io::Error::new(
+        std::io::ErrorKind::BrokenPipe,
+        why.to_string()
+      )),
+    }
+  }
+
+  fn is_running(&self) -> bool {
+    self.watcher.is_some()
+  }
+
+  fn post_message(
+    &mut self,
+    _data: Option<CDiskWatchEvent>
+  ) -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(
+      std::io::ErrorKind::Unsupported,
+      "disk watch events are receive only"
+    ))
+  }
+
+  fn terminate(&mut self) {
+    self.watcher = None;
+  }
+}
+
+/// Identifies how [disk_write_file_with] writes to the file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CDiskWriteMode {
+  /// Adds to the end of the file creating it if it does not exist.
+  Append,
+  /// Writes a temporary file next to the file, syncs it to disk, then
+  /// renames it over the file. Readers see either the old or new contents
+  /// even on a crash / power loss.
+  Atomic,
+  /// Creates the file failing with [std::io::ErrorKind::AlreadyExists] if it
+  /// exists.
+  CreateNew,
+  /// Replaces the contents of the file creating it if it does not exist.
+  Truncate,
+}
+
+/// Supports the [disk_read_file] and [disk_write_file] functions for
+/// reading / writing file contents from the host operating system disk.
+pub enum CFileContents {
+  /// Read / write files as Bytes.
+  Bytes(Vec<u8>),
+  /// Read / write files as String.
+  String(String),
+}
+impl CFileContents {
+  /// Utility method to retrieve the [CFileContents::Bytes] data.
+  pub fn as_bytes(&self) -> Option<Vec<u8>> {
+    match self {
+      CFileContents::Bytes(items) => Some(items.to_owned()),
+      CFileContents::String(_) => None,
+    }
+  }
+  /// Utility method to retrieve the [CFileContents::String] data.
+  pub fn as_string(&self) -> Option<String> {
+    match self {
+      CFileContents::Bytes(_) => None,
+      CFileContents::String(v) => Some(v.to_owned()),
+    }
+  }
+  /// Hashes the data (a string as its UTF-8 bytes) returning the lowercase
+  /// hex hash. See [disk_hash_file] for an example.
+  pub fn hash(&self, algorithm: CDiskHashAlgorithm) -> String {
+    let mut hasher = CDiskHasher::new(algorithm);
+    match self {
+      CFileContents::Bytes(v) => hasher.update(v),
+      CFileContents::String(v) => hasher.update(v.as_bytes()),
+    }
+    hasher.finish()
+  }
+}
+
+/// Identifies the text encoding of the lines read via
+/// [CFileReader::lines].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CFileEncoding {
+  /// Detects a UTF-8 / UTF-16 byte order mark falling back to
+  /// [CFileEncoding::Utf8Lossy] without one.
+  Auto,
+  /// ISO-8859-1 where each byte is a character.
+  Latin1,
+  /// UTF-16 big endian.
+  Utf16Be,
+  /// UTF-16 little endian.
+  Utf16Le,
+  /// UTF-8 failing with [std::io::ErrorKind::InvalidData] on invalid bytes.
+  Utf8,
+  /// UTF-8 replacing invalid bytes with the replacement character.
+  Utf8Lossy,
+}
+impl CFileEncoding {
+  /// Decodes the bytes into a string.
+  fn decode(&self, data: &[u8]) -> Result<String, std::io::Error> {
+    let utf16 = |data: &[u8], be: bool| {
+      let units = data.chunks(2).map(|v| match (v, be) {
+        ([a, b], true) => u16::from_be_bytes([*a, *b]),
+        ([a, b], false) => u16::from_le_bytes([*a, *b]),
+        _ => 0xFFFD,
+      });
+      char::decode_utf16(units)
+        .map(|v| v.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect::<String>()
+    };
+    match self {
+      CFileEncoding::Auto => match data {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => {
+          Ok(String::from_utf8_lossy(rest).to_string())
+        },
+        [0xFE, 0xFF, rest @ ..] => Ok(utf16(rest, true)),
+        [0xFF, 0xFE, rest @ ..] => Ok(utf16(rest, false)),
+        _ => Ok(String::from_utf8_lossy(data).to_string()),
+      },
+      CFileEncoding::Latin1 => {
+        Ok(data.iter().map(|v| char::from(*v)).collect())
+      },
+      CFileEncoding::Utf16Be => Ok(utf16(data, true)),
+      CFileEncoding::Utf16Le => Ok(utf16(data, false)),
+      CFileEncoding::Utf8 => String::from_utf8(data.to_vec()).map_err(|why| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, why.to_string())
+      }),
+      CFileEncoding::Utf8Lossy => {
+        Ok(String::from_utf8_lossy(data).to_string())
+      },
+    }
+  }
+}
+
+/// The portable metadata of a file / directory returned by [disk_info].
+/// Items the host operating system does not support are None.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CFileInfo {
+  path: String,
+  kind: CFileKind,
+  size: u64,
+  accessed: Option<std::time::SystemTime>,
+  created: Option<std::time::SystemTime>,
+  modified: Option<std::time::SystemTime>,
+  mode: Option<u32>,
+  readonly: bool,
+  uid: Option<u32>,
+  gid: Option<u32>,
+}
+impl CFileInfo {
+  /// Private constructor to support the [disk_info] function.
+  fn new(path: &str, metadata: &std::fs::Metadata) -> CFileInfo {
+    let kind = if metadata.is_symlink() {
+      CFileKind::Symlink
+    } else if metadata.is_dir() {
+      CFileKind::Directory
+    } else if metadata.is_file() {
+      CFileKind::File
+    } else {
+      CFileKind::Other
+    };
+    #[cfg(unix)]
+    let (mode, uid, gid) = {
+      use std::os::unix::fs::MetadataExt;
+      (Some(metadata.mode() & 0o7777), Some(metadata.uid()),
+        Some(metadata.gid()))
+    };
+    #[cfg(not(unix))]
+    let (mode, uid, gid) = (None, None, None);
+    CFileInfo {
+      path: path.to_string(),
+      kind,
+      size: metadata.len(),
+      accessed: metadata.accessed().ok(),
+      created: metadata.created().ok(),
+      modified: metadata.modified().ok(),
+      mode,
+      readonly: metadata.permissions().readonly(),
+      uid,
+      gid,
+    }
+  }
+
+  /// The last access time.
+  pub fn accessed(&self) -> Option<std::time::SystemTime> {
+    self.accessed
+  }
+
+  /// Serializes the info into a [CObject] with the times as RFC 3339 UTC
+  /// strings and missing items as null.
+  pub fn as_object(&self) -> CObject {
+    let time = |v: Option<std::time::SystemTime>| match v {
+      Some(v) => CObject::from(
+        chrono::DateTime::<chrono::Utc>::from(v).to_rfc3339()
+      ),
+      None => CObject::Null,
+    };
+    json::object!{
+      path: self.path.as_str(),
+      kind: self.kind.as_str(),
+      size: self.size,
+      accessed: time(self.accessed),
+      created: time(self.created),
+      modified: time(self.modified),
+      mode: self.mode,
+      readonly: self.readonly,
+      uid: self.uid,
+      gid: self.gid,
+    }
+  }
+
+  /// The creation time.
+  pub fn created(&self) -> Option<std::time::SystemTime> {
+    self.created
+  }
+
+  /// The group id of the owner.
+  pub fn gid(&self) -> Option<u32> {
+    self.gid
+  }
+
+  /// The [CFileKind] of the entry.
+  pub fn kind(&self) -> CFileKind {
+    self.kind
+  }
+
+  /// The Unix permission bits (i.e. 0o644).
+  pub fn mode(&self) -> Option<u32> {
+    self.mode
+  }
+
+  /// The last modified time.
+  pub fn modified(&self) -> Option<std::time::SystemTime> {
+    self.modified
+  }
+
+  /// The path of the entry.
+  pub fn path(&self) -> String {
+    self.path.to_string()
+  }
+
+  /// True if the entry can't be written.
+  pub fn readonly(&self) -> bool {
+    self.readonly
+  }
+
+  /// The size in bytes.
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  /// The user id of the owner.
+  pub fn uid(&self) -> Option<u32> {
+    self.uid
+  }
+}
+
+/// Identifies the kind of entry described by a [CFileInfo].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CFileKind {
+  /// A directory.
+  Directory,
+  /// A regular file.
+  File,
+  /// Anything else (i.e. a device, socket, or pipe).
+  Other,
+  /// A symbolic link.
+  Symlink,
+}
+impl CFileKind {
+  /// The name of the kind.
+  fn as_str(&self) -> &str {
+    match self {
+      CFileKind::Directory => "directory",
+      CFileKind::File => "file",
+      CFileKind::Other => "other",
+      CFileKind::Symlink => "symlink",
+    }
+  }
+}
+
+/// The result of a [disk_map_file] call providing read access to the file
+/// via a memory map of it. The file is paged in by the host operating
+/// system as it is accessed rather than read up front. Deref to the mapped
+/// bytes for direct access.
+pub struct CFileMap {
+  mmap: memmap2::Mmap,
+}
+impl CFileMap {
+  /// True if the file is empty.
+  pub fn is_empty(&self) -> bool {
+    self.mmap.is_empty()
+  }
+
+  /// The size of the file in bytes.
+  pub fn len(&self) -> usize {
+    self.mmap.len()
+  }
+
+  /// Copies out the len bytes at the offset (fewer at the end of the file).
+  pub fn range(&self, offset: usize, len: usize) -> CFileContents {
+    let start = offset.min(self.mmap.len());
+    let end = start.saturating_add(len).min(self.mmap.len());
+    CFileContents::Bytes(self.mmap[start..end].to_vec())
+  }
+}
+impl std::ops::Deref for CFileMap {
+  type Target = [u8];
+
+  fn deref(&self) -> &Self::Target {
+    &self.mmap
+  }
+}
+
+/// The result of a [disk_open_reader] call providing buffered reads of a
+/// file in chunks, lines, or ranges without reading it all into memory.
+/// It also implements [std::io::Read] / [std::io::BufRead] /
+/// [std::io::Seek] for use with other readers.
+pub struct CFileReader {
+  reader: std::io::BufReader<std::fs::File>,
+}
+impl CFileReader {
+  /// Iterates the rest of the file as [CFileContents::Bytes] chunks of the
+  /// size (the last one possibly smaller). The iteration ends after the
+  /// first error (i.e. a zero size).
+  pub fn chunks(
+    mut self,
+    size: usize,
+  ) -> impl Iterator<Item = Result<CFileContents, std::io::Error>> {
+    let mut failed = false;
+    std::iter::from_fn(move || {
+      if failed {
+        return None;
+      }
+      let chunk = self.read_chunk(size).transpose();
+      failed = matches!(chunk, Some(Err(_)));
+      chunk
+    })
+  }
+
+  /// The size of the file in bytes.
+  pub fn len(&self) -> Result<u64, std::io::Error> {
+    Ok(self.reader.get_ref().metadata()?.len())
+  }
+
+  /// True if the file is empty.
+  pub fn is_empty(&self) -> Result<bool, std::io::Error> {
+    Ok(self.len()? == 0)
+  }
+
+  /// Iterates the rest of the file as lines decoded via the
+  /// [CFileEncoding] with the line endings removed.
+  pub fn lines(
+    mut self,
+    encoding: CFileEncoding,
+  ) -> impl Iterator<Item = Result<String, std::io::Error>> {
+    let encoding = match encoding {
+      CFileEncoding::Auto => self.read_bom(),
+      _ => encoding,
+    };
+    std::iter::from_fn(move || {
+      let mut line = match self.read_line_bytes(encoding) {
+        Ok(Some(v)) => v,
+        Ok(None) => return None,
+        Err(why) => return Some(Err(why)),
+      };
+      let ending: &[&[u8]] = match encoding {
+        CFileEncoding::Utf16Be => &[&[0x00, 0x0A], &[0x00, 0x0D]],
+        CFileEncoding::Utf16Le => &[&[0x0A, 0x00], &[0x0D, 0x00]],
+        _ => &[&[0x0A], &[0x0D]],
+      };
+      for end in ending {
+        if line.ends_with(end) {
+          line.truncate(line.len() - end.len());
+        }
+      }
+      Some(encoding.decode(&line))
+    })
+  }
+
+  /// Reads the next chunk of up to size bytes or None at the end of the
+  /// file. A zero size is an [std::io::ErrorKind::InvalidInput] error.
+  pub fn read_chunk(
+    &mut self,
+    size: usize,
+  ) -> Result<Option<CFileContents>, std::io::Error> {
+    if size == 0 {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "chunk size must be greater than 0",
+      ));
+    }
+    let mut data = Vec::with_capacity(size);
+    std::io::Read::read_to_end(
+      &mut std::io::Read::take(&mut self.reader, size as u64),
+      &mut data,
+    )?;
+    if data.is_empty() {
+      return Ok(None);
+    }
+    Ok(Some(CFileContents::Bytes(data)))
+  }
+
+  /// Consumes the byte order mark at the start of the file returning the
+  /// [CFileEncoding] it identifies ([CFileEncoding::Utf8Lossy] without one).
+  fn read_bom(&mut self) -> CFileEncoding {
+    let Ok(start) = std::io::BufRead::fill_buf(&mut self.reader) else {
+      return CFileEncoding::Utf8Lossy;
+    };
+    let (encoding, len) = match start {
+      [0xEF, 0xBB, 0xBF, ..] => (CFileEncoding::Utf8Lossy, 3),
+      [0xFE, 0xFF, ..] => (CFileEncoding::Utf16Be, 2),
+      [0xFF, 0xFE, ..] => (CFileEncoding::Utf16Le, 2),
+      _ => (CFileEncoding::Utf8Lossy, 0),
+    };
+    std::io::BufRead::consume(&mut self.reader, len);
+    encoding
+  }
+
+  /// Reads the next line of raw bytes including its ending. A UTF-16 line
+  /// only ends at a 0x0A byte that is part of a newline code unit.
+  fn read_line_bytes(
+    &mut self,
+    encoding: CFileEncoding,
+  ) -> Result<Option<Vec<u8>>, std::io::Error> {
+    let mut line = Vec::new();
+    loop {
+      let read = std::io::BufRead::read_until(
+        &mut self.reader, 0x0A, &mut line
+      )?;
+      if read == 0 || !line.ends_with(&[0x0A]) {
+        break;
+      }
+      match encoding {
+        CFileEncoding::Utf16Be => {
+          if line.len() % 2 == 0 && line[line.len() - 2] == 0x00 {
+            break;
+          }
+        },
+        CFileEncoding::Utf16Le => {
+          if line.len() % 2 == 1 {
+            let mut high = [0u8; 1];
+            let read = std::io::Read::read(&mut self.reader, &mut high)?;
+            line.extend_from_slice(&high[..read]);
+            if read == 0 || high[0] == 0x00 {
+              break;
+            }
+          }
+        },
+        _ => break,
+      }
+    }
+    if line.is_empty() {
+      return Ok(None);
+    }
+    Ok(Some(line))
+  }
+
+  /// Reads up to len bytes at the offset (fewer at the end of the file)
+  /// leaving the reader positioned after them.
+  pub fn read_range(
+    &mut self,
+    offset: u64,
+    len: usize,
+  ) -> Result<CFileContents, std::io::Error> {
+    std::io::Seek::seek(self, std::io::SeekFrom::Start(offset))?;
+    Ok(self.read_chunk(len)?.unwrap_or(CFileContents::Bytes(Vec::new())))
+  }
+}
+impl std::io::Read for CFileReader {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    self.reader.read(buf)
+  }
+}
+impl std::io::BufRead for CFileReader {
+  fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+    self.reader.fill_buf()
+  }
+
+  fn consume(&mut self, amount: usize) {
+    self.reader.consume(amount)
+  }
+}
+impl std::io::Seek for CFileReader {
+  fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+    self.reader.seek(pos)
+  }
+}
+
+/// The result of a [disk_open_writer] call providing buffered writes of a
+/// file in chunks. Call [CFileWriter::finish] to flush and sync the file to
+/// disk. A [CDiskWriteMode::Atomic] writer only replaces the file when
+/// finished, discarding what was written if dropped before then.
+pub struct CFileWriter {
+  writer: std::io::BufWriter<std::fs::File>,
+  atomic: Option<(std::path::PathBuf, std::path::PathBuf)>,
+}
+impl CFileWriter {
+  /// Flushes the buffered data to the file, syncs it to disk, and for a
+  /// [CDiskWriteMode::Atomic] writer renames it over the file.
+  pub fn finish(mut self) -> Result<(), std::io::Error> {
+    std::io::Write::flush(&mut self.writer)?;
+    self.writer.get_ref().sync_all()?;
+    if let Some((temp, filename)) = self.atomic.take() {
+      std::fs::rename(&temp, &filename).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+      })?;
+      if let Some(dir) = filename.parent()
+          .and_then(|v| std::fs::File::open(v).ok()) {
+        let _ = dir.sync_all();
+      }
+    }
+    Ok(())
+  }
+
+  /// Writes the data to the file.
+  pub fn write(&mut self, data: CFileContents) -> Result<(), std::io::Error> {
+    match data {
+      CFileContents::Bytes(v) => std::io::Write::write_all(self, &v),
+      CFileContents::String(v) => {
+        std::io::Write::write_all(self, v.as_bytes())
+      },
+    }
+  }
+
+  /// Writes the string followed by a newline to the file.
+  pub fn write_line(&mut self, line: &str) -> Result<(), std::io::Error> {
+    std::io::Write::write_all(self, line.as_bytes())?;
+    std::io::Write::write_all(self, b"\n")
+  }
+}
+impl std::io::Write for CFileWriter {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.writer.write(buf)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    self.writer.flush()
+  }
+}
+impl Drop for CFileWriter {
+  fn drop(&mut self) {
+    if let Some((temp, _)) = self.atomic.take() {
+      let _ = std::fs::remove_file(temp);
+    }
+  }
+}
+
+/// Writes the entries of a [disk_archive] call to a tape archive returning
+/// the writer once finished.
+fn disk_archive_tar<W: std::io::Write>(
+  writer: W,
+  entries: &[(std::path::PathBuf, String, std::fs::Metadata)],
+  progress: &mut CDiskProgress,
+  cb: Option<CDiskProgressCB>,
+) -> Result<W, std::io::Error> {
+  let mut builder = tar::Builder::new(writer);
+  builder.follow_symlinks(false);
+  for (path, name, metadata) in entries {
+    builder.append_path_with_name(path, name)?;
+    if !metadata.is_dir() {
+      progress.report(name, metadata.len(), cb);
+    }
+  }
+  builder.into_inner()
+}
+
+/// Builds the zip entry options carrying over the permissions and modified
+/// time of the file.
+fn disk_archive_zip_options(
+  metadata: &std::fs::Metadata,
+) -> zip::write::SimpleFileOptions {
+  use chrono::{Datelike, Timelike};
+  let mut options = zip::write::SimpleFileOptions::default()
+    .compression_method(zip::CompressionMethod::Deflated);
+  if let Ok(modified) = metadata.modified() {
+    let t = chrono::DateTime::<chrono::Local>::from(modified);
+    // Zip times can't be before 1980.
+    if let Ok(v) = zip::DateTime::from_date_and_time(
+      t.year().clamp(1980, 2107) as u16, t.month() as u8, t.day() as u8,
+      t.hour() as u8, t.minute() as u8, t.second() as u8,
+    ) {
+      options = options.last_modified_time(v);
+    }
+  }
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    options = options.unix_permissions(metadata.permissions().mode());
+  }
+  options
+}
+
+/// Validates the dest of a [disk_cp_with] / [disk_mv_with] call is not the
+/// src itself or, for a directory, within its tree which would have the
+/// copy endlessly copy itself.
+fn disk_copy_validate(
+  src: &std::path::Path,
+  dest: &std::path::Path,
+) -> Result<(), std::io::Error> {
+  // Resolves the path via its closest existing ancestor without following
+  // a symbolic link at the path itself.
+  let resolve = |path: &std::path::Path| {
+    let Some(name) = path.file_name() else {
+      return std::fs::canonicalize(path).ok();
+    };
+    let mut names = vec![name];
+    let mut existing = path.parent()?;
+    loop {
+      let dir = match existing.as_os_str().is_empty() {
+        true => std::path::Path::new("."),
+        false => existing,
+      };
+      if let Ok(v) = std::fs::canonicalize(dir) {
+        return Some(names.iter().rev().fold(v, |v, name| v.join(name)));
+      }
+      names.push(existing.file_name()?);
+      existing = existing.parent()?;
+    }
+  };
+  let is_dir = std::fs::symlink_metadata(src)?.is_dir();
+  let (Some(src_path), Some(dest_path)) = (resolve(src), resolve(dest)) else {
+    return Ok(());
+  };
+  if dest_path == src_path || (is_dir && dest_path.starts_with(&src_path)) {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::InvalidInput,
+      format!("{} is within {}", dest.display(), src.display()),
+    ));
+  }
+  Ok(())
+}
+
+/// Validates the link target of the extracted link at the path within the
+/// archive stays within the extract directory (zip-slip protection). The
+/// target is relative to the link's directory or the archive root.
+fn disk_extract_enclosed(
+  name: &str,
+  target: &std::path::Path,
+  from_root: bool,
+) -> Result<(), std::io::Error> {
+  let mut depth = match from_root {
+    true => 0,
+    false => std::path::Path::new(name).components().count().saturating_sub(1),
+  };
+  for component in target.components() {
+    match component {
+      std::path::Component::Normal(_) => depth += 1,
+      std::path::Component::CurDir => {},
+      std::path::Component::ParentDir if depth > 0 => depth -= 1,
+      _ => return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{} links outside the extract directory", name),
+      )),
+    }
+  }
+  Ok(())
+}
+
+/// Validates the entry at the path within the extract directory does not
+/// pass through a symbolic link already extracted which could lead it
+/// outside of the extract directory (zip-slip protection).
+fn disk_extract_parents(
+  dest: &std::path::Path,
+  path: &std::path::Path,
+) -> Result<(), std::io::Error> {
+  let mut target = dest.to_path_buf();
+  let mut components = path.components().peekable();
+  while let Some(component) = components.next() {
+    if components.peek().is_none() {
+      break;
+    }
+    target.push(component);
+    let metadata = std::fs::symlink_metadata(&target);
+    if metadata.is_ok_and(|v| v.file_type().is_symlink()) {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{} passes through a symbolic link", path.display()),
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Resolves the extracted symbolic link against the tree extracted so far
+/// (following the links it passes through) removing it when it leads
+/// outside of the canonical dest directory (zip-slip protection).
+fn disk_extract_resolve(
+  dest: &std::path::Path,
+  link: &std::path::Path,
+) -> Result<(), std::io::Error> {
+  let outside = || {
+    let _ = std::fs::remove_file(link);
+    std::io::Error::new(
+      std::io::ErrorKind::InvalidData,
+      format!("{} links outside the extract directory", link.display()),
+    )
+  };
+  let parts = |target: &std::path::Path| {
+    target.components()
+      .map(|v| match v {
+        std::path::Component::Normal(v) => Some(v.to_os_string()),
+        std::path::Component::CurDir => Some(".".into()),
+        std::path::Component::ParentDir => Some("..".into()),
+        _ => None,
+      })
+      .collect::<Option<std::collections::VecDeque<std::ffi::OsString>>>()
+  };
+  let Some(parent) = link.parent() else {
+    return Err(outside());
+  };
+  let mut current = std::fs::canonicalize(parent)?;
+  let mut pending = parts(&std::fs::read_link(link)?).ok_or_else(outside)?;
+  let mut hops = 0;
+  while let Some(part) = pending.pop_front() {
+    if part == ".." {
+      current.pop();
+    } else if part != "." {
+      current.push(&part);
+      let metadata = std::fs::symlink_metadata(&current);
+      if metadata.is_ok_and(|v| v.file_type().is_symlink()) {
+        hops += 1;
+        let target = std::fs::read_link(&current)?;
+        current.pop();
+        match parts(&target) {
+          Some(v) if hops <= 40 => {
+            v.into_iter().rev().for_each(|v| pending.push_front(v));
+          },
+          _ => return Err(outside()),
+        }
+      }
+    }
+    if !current.starts_with(dest) {
+      return Err(outside());
+    }
+  }
+  Ok(())
+}
+
+/// Extracts the entries of a tape archive for the [disk_extract] call
+/// returning the number of files extracted. When counting the entries are
+/// totaled into the progress rather than extracted.
+fn disk_extract_tar(
+  archive: &str,
+  dest: &std::path::Path,
+  format: CDiskArchiveFormat,
+  options: &CDiskArchiveOptions,
+  progress: &mut CDiskProgress,
+  counting: bool,
+) -> Result<usize, std::io::Error> {
+  let file = std::fs::File::open(archive)?;
+  let reader: Box<dyn std::io::Read> = match format {
+    CDiskArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+    CDiskArchiveFormat::TarZst => Box::new(zstd::Decoder::new(file)?),
+    _ => Box::new(file),
+  };
+  let includes = CDiskWalker::glob_set(&options.includes);
+  let mut tar = tar::Archive::new(reader);
+  tar.set_preserve_permissions(true);
+  tar.set_overwrite(true);
+  let mut count = 0;
+  let mut links = Vec::new();
+  for entry in tar.entries()? {
+    let mut entry = entry?;
+    let name = entry.path()?.to_string_lossy().to_string();
+    if !options.is_included(&includes, &name) {
+      continue;
+    }
+    let kind = entry.header().entry_type();
+    let size = entry.header().size()?;
+    if counting {
+      if !kind.is_dir() {
+        progress.files_total += 1;
+        progress.bytes_total += size;
+      }
+      continue;
+    }
+    if (kind.is_symlink() || kind.is_hard_link())
+        && let Some(target) = entry.link_name()? {
+      disk_extract_enclosed(&name, &target, kind.is_hard_link())?;
+    }
+    if !entry.unpack_in(dest)? {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{} is outside the extract directory", name),
+      ));
+    }
+    if kind.is_symlink() {
+      let link = dest.join(&name);
+      disk_extract_resolve(dest, &link)?;
+      links.push(link);
+    }
+    if !kind.is_dir() {
+      count += 1;
+      progress.report(&name, size, options.progress);
+    }
+  }
+
+  // Links extracted later may have changed where the earlier ones lead.
+  for link in links {
+    disk_extract_resolve(dest, &link)?;
+  }
+  Ok(count)
+}
+
+/// Extracts the entries of a zip archive for the [disk_extract] call
+/// returning the number of files extracted.
+fn disk_extract_zip(
+  archive: &str,
+  dest: &std::path::Path,
+  options: &CDiskArchiveOptions,
+  progress: &mut CDiskProgress,
+) -> Result<usize, std::io::Error> {
+  let mut zip = zip::ZipArchive::new(std::fs::File::open(archive)?)?;
+  let includes = CDiskWalker::glob_set(&options.includes);
+  let unsafe_path = |name: &str| std::io::Error::new(
+    std::io::ErrorKind::InvalidData,
+    format!("{} is outside the extract directory", name),
+  );
+  if options.progress.is_some() {
+    for index in 0..zip.len() {
+      let file = zip.by_index_raw(index)?;
+      if !file.is_dir() && options.is_included(&includes, file.name()) {
+        progress.files_total += 1;
+        progress.bytes_total += file.size();
+      }
+    }
+  }
+  let mut count = 0;
+  let mut links = Vec::new();
+  for index in 0..zip.len() {
+    let mut file = zip.by_index(index)?;
+    let Some(path) = file.enclosed_name() else {
+      return Err(unsafe_path(file.name()));
+    };
+    let name = path.to_string_lossy().replace('\\', "/");
+    if !options.is_included(&includes, &name) {
+      continue;
+    }
+    disk_extract_parents(dest, &path)?;
+    let target = dest.join(&path);
+    if file.is_dir() {
+      std::fs::create_dir_all(&target)?;
+      continue;
+    }
+    if let Some(parent) = target.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::symlink_metadata(&target).is_ok() {
+      std::fs::remove_file(&target)?;
+    }
+    if file.is_symlink() {
+      let mut link = String::new();
+      std::io::Read::read_to_string(&mut file, &mut link)?;
+      disk_extract_enclosed(&name, std::path::Path::new(&link), false)?;
+      disk_make_link(std::path::Path::new(&link), &target)?;
+      disk_extract_resolve(dest, &target)?;
+      links.push(target);
+    } else {
+      let mut writer = std::fs::File::create(&target)?;
+      std::io::copy(&mut file, &mut writer)?;
+      let modified = file.last_modified().and_then(|v| {
+        chrono::NaiveDate::from_ymd_opt(
+          v.year() as i32, v.month() as u32, v.day() as u32
+        )?.and_hms_opt(v.hour() as u32, v.minute() as u32, v.second() as u32)?
+          .and_local_timezone(chrono::Local)
+          .single()
+      });
+      if let Some(modified) = modified {
+        writer.set_modified(modified.into())?;
+      }
+      #[cfg(unix)]
+      if let Some(mode) = file.unix_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        writer.set_permissions(std::fs::Permissions::from_mode(mode))?;
+      }
+    }
+    count += 1;
+    progress.report(&name, file.size(), options.progress);
+  }
+
+  // Links extracted later may have changed where the earlier ones lead.
+  for link in links {
+    disk_extract_resolve(dest, &link)?;
+  }
+  Ok(count)
+}
+
+/// Lists the files (not following symbolic links) of the src directory tree
+/// of the [CDiskFileSystem] adding them with their size to the files.
+fn disk_fs_files<F: CDiskFileSystem + ?Sized>(
+  fs: &F,
+  src: &str,
+  files: &mut Vec<(String, u64)>,
+) -> Result<(), std::io::Error> {
+  for name in fs.read_dir(src)? {
+    let path = std::path::Path::new(src).join(&name);
+    let path = path.to_string_lossy().to_string();
+    let info = fs.info(&path, false)?;
+    match info.kind() {
+      CFileKind::Directory => disk_fs_files(fs, &path, files)?,
+      CFileKind::File => files.push((path, info.size())),
+      _ => {},
+    }
+  }
+  Ok(())
+}
+
+/// Resolves the dest of a [CDiskFileSystem::disk_cp_with] /
+/// [CDiskFileSystem::disk_mv_with] call placing a src file within the dest
+/// when it is an existing directory.
+fn disk_fs_dest<F: CDiskFileSystem + ?Sized>(
+  fs: &F,
+  src: &str,
+  dest: &str,
+) -> String {
+  let name = std::path::Path::new(src).file_name();
+  match name {
+    Some(name) if !fs.exists(src, CDiskType::Directory)
+        && fs.exists(dest, CDiskType::Directory) => {
+      std::path::Path::new(dest).join(name).to_string_lossy().to_string()
+    },
+    _ => dest.to_string(),
+  }
+}
+
+/// Normalizes the path of a [CDiskFileSystem] into an absolute path from
+/// its root resolving `.` / `..` lexically (`..` stops at the root).
+fn disk_fs_normalize(path: &str) -> std::path::PathBuf {
+  let mut answer = std::path::PathBuf::from("/");
+  for component in std::path::Path::new(path).components() {
+    match component {
+      std::path::Component::Normal(v) => answer.push(v),
+      std::path::Component::ParentDir => {
+        answer.pop();
+      },
+      _ => {},
+    }
+  }
+  answer
+}
+
+/// Creates the error of a `disk_*` method of a [CDiskFileSystem] only
+/// supported by the file systems backed by the host.
+fn disk_fs_unsupported(operation: &str) -> std::io::Error {
+  std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    format!("{} is only supported by host file systems", operation),
+  )
+}
+
+/// Validates the dest of a [CDiskFileSystem::disk_cp_with] /
+/// [CDiskFileSystem::disk_mv_with] call is not the src itself or, for a
+/// directory, within its tree comparing the normalized paths.
+fn disk_fs_validate<F: CDiskFileSystem + ?Sized>(
+  fs: &F,
+  src: &str,
+  dest: &str,
+) -> Result<(), std::io::Error> {
+  let is_dir = fs.info(src, false)?.kind() == CFileKind::Directory;
+  let (src_path, dest_path) = (disk_fs_normalize(src), disk_fs_normalize(dest));
+  if dest_path == src_path || (is_dir && dest_path.starts_with(&src_path)) {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::InvalidInput,
+      format!("{} is within {}", dest, src),
+    ));
+  }
+  Ok(())
+}
+
+/// Locks the file per the [CDiskLockKind] waiting per the [CDiskLockWait]
+/// when held by another process.
+fn disk_lock_file(
+  file: &std::fs::File,
+  kind: CDiskLockKind,
+  wait: CDiskLockWait,
+) -> Result<(), std::io::Error> {
+  let try_lock = || match kind {
+    CDiskLockKind::Exclusive => file.try_lock(),
+    CDiskLockKind::Shared => file.try_lock_shared(),
+  };
+  let timeout = match wait {
+    CDiskLockWait::Block => return match kind {
+      CDiskLockKind::Exclusive => file.lock(),
+      CDiskLockKind::Shared => file.lock_shared(),
+    },
+    CDiskLockWait::Timeout(v) => std::time::Duration::from_millis(v),
+    CDiskLockWait::Try => return try_lock().map_err(std::io::Error::from),
+  };
+
+  // Polls for the lock backing off up to 50ms between attempts.
+  let deadline = std::time::Instant::now() + timeout;
+  let mut delay = std::time::Duration::from_millis(1);
+  loop {
+    match try_lock() {
+      Ok(_) => return Ok(()),
+      Err(std::fs::TryLockError::WouldBlock) => {},
+      Err(std::fs::TryLockError::Error(why)) => return Err(why),
+    }
+    let now = std::time::Instant::now();
+    if now >= deadline {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        format!("lock not acquired within {}ms", timeout.as_millis()),
+      ));
+    }
+    std::thread::sleep(delay.min(deadline - now));
+    delay = (delay * 2).min(std::time::Duration::from_millis(50));
+  }
+}
+
+/// Recreates the symbolic link pointing to the target.
+fn disk_make_link(
+  target: &std::path::Path,
+  link: &std::path::Path,
+) -> Result<(), std::io::Error> {
+  #[cfg(unix)]
+  return std::os::unix::fs::symlink(target, link);
+  #[cfg(windows)]
+  return if link.parent().unwrap_or(link).join(target).is_dir() {
+    std::os::windows::fs::symlink_dir(target, link)
+  } else {
+    std::os::windows::fs::symlink_file(target, link)
+  };
+  #[cfg(not(any(unix, windows)))]
+  return Err(std::io::Error::from(std::io::ErrorKind::Unsupported));
+}
+
+/// True while the path still names the opened file (i.e. it wasn't removed
+/// or replaced by another process since it was opened). Compares the device
+/// and inode on unix and the volume serial number and file index on
+/// Windows.
+fn disk_same_file(file: &std::fs::File, path: &std::path::Path) -> bool {
+  let held = file.try_clone().and_then(same_file::Handle::from_file);
+  let named = same_file::Handle::from_path(path);
+  matches!((held, named), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Counter making the [disk_unique_name] results unique within the process.
+static DISK_UNIQUE_ID: std::sync::atomic::AtomicUsize =
+  std::sync::atomic::AtomicUsize::new(0);
+
+/// Creates a file name that is unique across processes and calls.
+fn disk_unique_name(prefix: &str, suffix: &str) -> String {
+  let id = DISK_UNIQUE_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+  let nanos = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|v| v.subsec_nanos())
+    .unwrap_or_default();
+  format!("{}{}_{}_{}{}", prefix, std::process::id(), nanos, id, suffix)
+}
+
+/// Creates the path of the temporary file next to the filename that a
+/// [CDiskWriteMode::Atomic] write renames over it.
+fn disk_temp_path(filename: &std::path::Path) -> std::path::PathBuf {
+  let parent = match filename.parent() {
+    Some(v) if !v.as_os_str().is_empty() => v.to_path_buf(),
+    _ => std::path::PathBuf::from("."),
+  };
+  let name = filename.file_name().unwrap_or_default().to_string_lossy();
+  parent.join(disk_unique_name(&format!(".{}.", name), ".tmp"))
+}
+
+/// Creates an entry with a unique name per the options retrying names
+/// already taken by another process.
+fn disk_temp_create<T>(
+  options: &CDiskTempOptions,
+  create: impl Fn(&std::path::Path) -> Result<T, std::io::Error>,
+) -> Result<(std::path::PathBuf, T), std::io::Error> {
+  let dir = std::path::Path::new(&options.dir);
+  let mut attempt = 0;
+  loop {
+    let path = dir.join(disk_unique_name(&options.prefix, &options.suffix));
+    match create(&path) {
+      Ok(v) => return Ok((path, v)),
+      Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists
+          && attempt < 100 => attempt += 1,
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+/// Carries out the [CDiskWriteMode::Atomic] write of the data to the file.
+fn disk_write_atomic(
+  filename: &std::path::Path,
+  data: &[u8],
+) -> Result<(), std::io::Error> {
+  let temp = disk_temp_path(filename);
+  let result = (|| {
+    let mut file = std::fs::OpenOptions::new()
+      .write(true)
+      .create_new(true)
+      .open(&temp)?;
+    std::io::Write::write_all(&mut file, data)?;
+    if let Ok(metadata) = std::fs::metadata(filename) {
+      file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()?;
+    std::fs::rename(&temp, filename)
+  })();
+  if result.is_err() {
+    let _ = std::fs::remove_file(&temp);
+    return result;
+  }
+  // Makes the rename durable. Directories can't be synced on all platforms.
+  if let Some(dir) = temp.parent().and_then(|v| std::fs::File::open(v).ok()) {
+    let _ = dir.sync_all();
+  }
+  Ok(())
+}
+
+/// Writes the data to the file of the host operating system per the
+/// [CDiskWriteMode] syncing it to disk.
+fn disk_write_os(
+  path: &std::path::Path,
+  data: &[u8],
+  mode: CDiskWriteMode,
+) -> Result<(), std::io::Error> {
+  let mut options = std::fs::OpenOptions::new();
+  match mode {
+    CDiskWriteMode::Append => options.append(true).create(true),
+    CDiskWriteMode::Atomic => return disk_write_atomic(path, data),
+    CDiskWriteMode::CreateNew => options.write(true).create_new(true),
+    CDiskWriteMode::Truncate => {
+      options.write(true).create(true).truncate(true)
+    },
+  };
+  let mut file = options.open(path)?;
+  std::io::Write::write_all(&mut file, data)?;
+  file.sync_all()
+}
+
+/// Archives the src file / directory (its contents placed at the root of
+/// the archive) into the archive file of the [CDiskArchiveFormat] returning
+/// the number of files archived. Symbolic links are archived as links.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskArchiveFormat, CDiskArchiveOptions, CFileContents};
+///
+/// let root = format!("{}/test_disk_archive", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&root);
+/// for file in ["logs/a.log", "logs/b.txt", "logs/sub/c.log"] {
+///   let filename = format!("{}/{}", root, file);
+///   let parent = std::path::Path::new(&filename).parent().unwrap();
+///   codemelted::disk_mkdir(&parent.to_string_lossy()).unwrap();
+///   codemelted::disk_write_file(
+///     &filename,
+///     CFileContents::String(file.to_string()),
+///     false
+///   ).unwrap();
+/// }
+///
+/// let src = format!("{}/logs", root);
+/// for name in ["logs.zip", "logs.tar", "logs.tar.gz", "logs.tar.zst"] {
+///   let archive = format!("{}/{}", root, name);
+///   let format = CDiskArchiveFormat::from_filename(&archive).unwrap();
+///   let options = CDiskArchiveOptions::new()
+///     .progress(|progress| assert!(progress.files_total() == 3));
+///   let count = codemelted::disk_archive(&src, &archive, format, &options)
+///     .unwrap();
+///   assert_eq!(count, 3);
+///
+///   let dest = format!("{}/out_{}", root, name);
+///   let options = CDiskArchiveOptions::new().include("*.log");
+///   let count = codemelted::disk_extract(&archive, &dest, format, &options)
+///     .unwrap();
+///   assert_eq!(count, 2);
+///   let data = codemelted::disk_read_file(&format!("{}/sub/c.log", dest), true)
+///     .unwrap();
+///   assert_eq!(data.as_string().unwrap(), "logs/sub/c.log");
+///   assert!(!codemelted::disk_exists(
+///     &format!("{}/b.txt", dest),
+///     codemelted::CDiskType::File
+///   ));
+/// }
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_archive(
+  src: &str,
+  archive: &str,
+  format: CDiskArchiveFormat,
+  options: &CDiskArchiveOptions,
+) -> Result<usize, std::io::Error> {
+  CDiskOsFileSystem.disk_archive(src, archive, format, options)
+}
+
+/// Sets the Unix permission bits (i.e. 0o644) of the src. Other host
+/// operating systems only support the read only attribute set when no write
+/// bits are given.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let filename = format!("{}/test_disk_chmod.sh", codemelted::runtime_temp_path());
+/// codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::String(String::from("echo hello")),
+///   false
+/// ).unwrap();
+///
+/// codemelted::disk_chmod(&filename, 0o444).unwrap();
+/// assert!(codemelted::disk_info(&filename, true).unwrap().readonly());
+/// codemelted::disk_chmod(&filename, 0o755).unwrap();
+/// let info = codemelted::disk_info(&filename, true).unwrap();
+/// assert!(!info.readonly());
+/// if cfg!(unix) {
+///   assert_eq!(info.mode(), Some(0o755));
+/// }
+/// let _ = codemelted::disk_rm(&filename);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_chmod(src: &str, mode: u32) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_chmod(src, mode)
+}
+
+/// Changes the owning user and / or group ids of the src (None leaves it
+/// unchanged). Only supported on Unix hosts and typically requires elevated
+/// privileges to give away a file.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let filename = format!("{}/test_disk_chown.txt", codemelted::runtime_temp_path());
+/// codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::String(String::from("data")),
+///   false
+/// ).unwrap();
+///
+/// // Setting the current owner is always allowed.
+/// let info = codemelted::disk_info(&filename, true).unwrap();
+/// let result = codemelted::disk_chown(&filename, info.uid(), info.gid());
+/// assert_eq!(result.is_ok(), cfg!(unix));
+/// let _ = codemelted::disk_rm(&filename);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_chown(
+  src: &str,
+  uid: Option<u32>,
+  gid: Option<u32>,
+) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_chown(src, uid, gid)
+}
+
+/// Will copy a file / directory tree from one location on the host
+/// operating system disk to the other with the default
+/// [CDiskCopyOptions] (see [disk_cp_with]).
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let temp_filename = format!(
+///   "{}/test.txt",
+///   codemelted::runtime_temp_path()
+/// );
+/// let home_filename = format!(
+///   "{}/test.txt",
+///   codemelted::runtime_home_path()
+/// );
+///
+/// let _ = codemelted::disk_write_file(
+///   &home_filename,
+///   CFileContents::String("Hello".to_string()),
+///   false
+/// );
+/// let result = codemelted::disk_cp(&home_filename, &temp_filename);
+/// assert!(result.is_ok());
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_cp(src: &str, dest: &str) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_cp(src, dest)
+}
+
+/// Will copy a file / directory tree from one location on the host
+/// operating system disk to the other. A src directory has its contents
+/// merged into the dest directory with each file already there handled via
+/// the [CDiskConflict] of the [CDiskCopyOptions] while a src file is placed
+/// within a dest directory. Symbolic links are copied as links.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskConflict, CDiskCopyOptions, CFileContents};
+///
+/// let root = format!("{}/test_disk_cp_with", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&root);
+/// codemelted::disk_mkdir(&format!("{}/src/sub", root)).unwrap();
+/// for (file, data) in [("src/a.txt", "new"), ("src/sub/b.txt", "new"),
+///     ("dest/a.txt", "old")] {
+///   let filename = format!("{}/{}", root, file);
+///   let parent = std::path::Path::new(&filename).parent().unwrap();
+///   codemelted::disk_mkdir(&parent.to_string_lossy()).unwrap();
+///   codemelted::disk_write_file(
+///     &filename,
+///     CFileContents::String(data.to_string()),
+///     false
+///   ).unwrap();
+/// }
+/// let src = format!("{}/src", root);
+/// let dest = format!("{}/dest", root);
+/// let read = |file: &str| codemelted::disk_read_file(
+///   &format!("{}/{}", dest, file), true
+/// ).unwrap().as_string().unwrap();
+///
+/// let options = CDiskCopyOptions::new().conflict(CDiskConflict::Fail);
+/// assert!(codemelted::disk_cp_with(&src, &dest, &options).is_err());
+///
+/// let options = CDiskCopyOptions::new()
+///   .conflict(CDiskConflict::Skip)
+///   .progress(|progress| {
+///     assert!(progress.files_done() <= progress.files_total());
+///     assert_eq!(progress.files_total(), 2);
+///   });
+/// codemelted::disk_cp_with(&src, &dest, &options).unwrap();
+/// assert_eq!(read("a.txt"), "old");
+/// assert_eq!(read("sub/b.txt"), "new");
+///
+/// let options = CDiskCopyOptions::new().conflict(CDiskConflict::Rename);
+/// codemelted::disk_cp_with(&src, &dest, &options).unwrap();
+/// assert_eq!(read("a.txt"), "old");
+/// assert_eq!(read("a (1).txt"), "new");
+/// assert_eq!(read("sub/b (1).txt"), "new");
+///
+/// codemelted::disk_cp(&src, &dest).unwrap();
+/// assert_eq!(read("a.txt"), "new");
+/// assert_eq!(
+///   codemelted::disk_metadata(&format!("{}/a.txt", src)).unwrap()
+///     .modified().unwrap(),
+///   codemelted::disk_metadata(&format!("{}/a.txt", dest)).unwrap()
+///     .modified().unwrap(),
+/// );
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_cp_with(
+  src: &str,
+  dest: &str,
+  options: &CDiskCopyOptions,
+) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_cp_with(src, dest, options)
+}
+
+/// Determines if a directory or file exists on the host operating system
+/// and will further determine if it is of the expected [CDiskType].
+///
+/// **Example:**
+/// ```
+/// use codemelted::CDiskType;
+///
+/// let path = codemelted::runtime_home_path();
+/// assert_eq!(codemelted::disk_exists(&path, CDiskType::Either), true);
+/// assert_eq!(codemelted::disk_exists(&path, CDiskType::Directory), true);
+/// assert_eq!(codemelted::disk_exists(&path, CDiskType::File), false);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_exists(src: &str, disk_type: CDiskType) -> bool {
+  CDiskOsFileSystem.exists(src, disk_type)
+}
+
+/// Extracts the archive file of the [CDiskArchiveFormat] into the dest
+/// directory (created if needed) returning the number of files extracted.
+/// Entries with paths or link targets outside the dest (zip-slip), even by
+/// way of other extracted links, fail the extract with an
+/// [std::io::ErrorKind::InvalidData].
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskArchiveFormat, CDiskArchiveOptions};
+///
+/// let root = format!("{}/test_disk_extract", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&root);
+/// codemelted::disk_mkdir(&root).unwrap();
+///
+/// // An archive trying to write outside of the extract directory.
+/// let archive = format!("{}/evil.zip", root);
+/// let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+/// zip.start_file("../evil.txt", zip::write::SimpleFileOptions::default())
+///   .unwrap();
+/// std::io::Write::write_all(&mut zip, b"evil").unwrap();
+/// zip.finish().unwrap();
+///
+/// let result = codemelted::disk_extract(
+///   &archive,
+///   &format!("{}/out", root),
+///   CDiskArchiveFormat::Zip,
+///   &CDiskArchiveOptions::new(),
+/// );
+/// assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+/// assert!(!codemelted::disk_exists(
+///   &format!("{}/evil.txt", root),
+///   codemelted::CDiskType::File
+/// ));
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_extract(
+  archive: &str,
+  dest: &str,
+  format: CDiskArchiveFormat,
+  options: &CDiskArchiveOptions,
+) -> Result<usize, std::io::Error> {
+  CDiskOsFileSystem.disk_extract(archive, dest, format, options)
+}
+
+/// Finds the files with identical contents under the src directory tree
+/// comparing the hashes of files of the same size. Returns each group of
+/// duplicate paths. Empty files and symbolic links are not considered.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskHashAlgorithm, CFileContents};
+///
+/// let root = format!(
+///   "{}/test_disk_find_duplicates",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&root);
+/// for (file, data) in [("a.bin", "same"), ("b.bin", "diff"),
+///     ("sub/c.bin", "same"), ("sub/d.bin", "other")] {
+///   let filename = format!("{}/{}", root, file);
+///   let parent = std::path::Path::new(&filename).parent().unwrap();
+///   codemelted::disk_mkdir(&parent.to_string_lossy()).unwrap();
+///   codemelted::disk_write_file(
+///     &filename,
+///     CFileContents::String(data.to_string()),
+///     false
+///   ).unwrap();
+/// }
+///
+/// let duplicates = codemelted::disk_find_duplicates(
+///   &root,
+///   CDiskHashAlgorithm::Blake3
+/// ).unwrap();
+/// assert_eq!(duplicates.len(), 1);
+/// assert!(duplicates[0][0].ends_with("a.bin"));
+/// assert!(duplicates[0][1].ends_with("c.bin"));
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_find_duplicates(
+  src: &str,
+  algorithm: CDiskHashAlgorithm,
+) -> Result<Vec<Vec<String>>, std::io::Error> {
+  CDiskOsFileSystem.disk_find_duplicates(src, algorithm)
+}
+
+/// Creates a hard link at the link path to the existing src file so both
+/// paths share the same contents.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let src = format!("{}/test_disk_hard_link.txt", codemelted::runtime_temp_path());
+/// let link = format!("{}.link", src);
+/// let _ = codemelted::disk_rm(&link);
+/// codemelted::disk_write_file(
+///   &src,
+///   CFileContents::String(String::from("shared")),
+///   false
+/// ).unwrap();
+///
+/// codemelted::disk_hard_link(&src, &link).unwrap();
+/// codemelted::disk_rm(&src).unwrap();
+/// let data = codemelted::disk_read_file(&link, true).unwrap();
+/// assert_eq!(data.as_string().unwrap(), "shared");
+/// let _ = codemelted::disk_rm(&link);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_hard_link(src: &str, link: &str) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_hard_link(src, link)
+}
+
+/// Hashes the file via the [CDiskHashAlgorithm] reading it in chunks
+/// rather than into memory. Returns the lowercase hex hash.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskHashAlgorithm, CFileContents};
+///
+/// let filename = format!(
+///   "{}/test_disk_hash_file.txt",
+///   codemelted::runtime_temp_path()
+/// );
+/// let data = CFileContents::String(String::from("hello"));
+/// assert_eq!(
+///   data.hash(CDiskHashAlgorithm::Sha256),
+///   "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+/// );
+/// codemelted::disk_write_file(&filename, data, false).unwrap();
+///
+/// let hash = |algorithm| codemelted::disk_hash_file(&filename, algorithm)
+///   .unwrap();
+/// assert_eq!(
+///   hash(CDiskHashAlgorithm::Sha256),
+///   "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+/// );
+/// assert_eq!(
+///   hash(CDiskHashAlgorithm::Sha1),
+///   "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+/// );
+/// assert_eq!(hash(CDiskHashAlgorithm::Md5), "5d41402abc4b2a76b9719d911017c592");
+/// assert_eq!(hash(CDiskHashAlgorithm::Crc32), "3610a686");
+/// assert_eq!(
+///   hash(CDiskHashAlgorithm::Blake3),
+///   "ea8f163db38682925e4491c5e58d4bb3506ef8c14eb78a86e908c5624a67200f"
+/// );
+/// let _ = codemelted::disk_rm(&filename);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_hash_file(
+  filename: &str,
+  algorithm: CDiskHashAlgorithm,
+) -> Result<String, std::io::Error> {
+  CDiskOsFileSystem.disk_hash_file(filename, algorithm)
+}
+
+/// The conventional names of the [disk_hash_manifest] files left out of
+/// the manifest of a directory.
+const DISK_MANIFEST_NAMES: [&str; 5] = [
+  "BLAKE3SUMS", "CRC32SUMS", "MD5SUMS", "SHA1SUMS", "SHA256SUMS",
+];
+
+/// Creates a checksum manifest in the `sha256sum` format ("{hash}  {path}"
+/// lines) of the src file or the files of the src directory tree with paths
+/// relative to it. Save it at the top of the src directory for
+/// [disk_hash_verify] under a conventional name (BLAKE3SUMS, CRC32SUMS,
+/// MD5SUMS, SHA1SUMS, or SHA256SUMS) as files with those names there are
+/// left out of the manifest so it never lists itself.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskHashAlgorithm, CDiskHashStatus, CFileContents};
+///
+/// let root = format!(
+///   "{}/test_disk_hash_manifest",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&root);
+/// for file in ["firmware.bin", "sub/boot.bin"] {
+///   let filename = format!("{}/{}", root, file);
+///   let parent = std::path::Path::new(&filename).parent().unwrap();
+///   codemelted::disk_mkdir(&parent.to_string_lossy()).unwrap();
+///   codemelted::disk_write_file(
+///     &filename,
+///     CFileContents::String(file.to_string()),
+///     false
+///   ).unwrap();
+/// }
+///
+/// let manifest = codemelted::disk_hash_manifest(
+///   &root,
+///   CDiskHashAlgorithm::Sha256
+/// ).unwrap();
+/// assert!(manifest.lines().nth(1).unwrap().ends_with("  sub/boot.bin"));
+/// let manifest_file = format!("{}/SHA256SUMS", root);
+/// codemelted::disk_write_file(
+///   &manifest_file,
+///   CFileContents::String(manifest.clone()),
+///   false
+/// ).unwrap();
+///
+/// // Regenerating it leaves the saved manifest out.
+/// let regenerated = codemelted::disk_hash_manifest(
+///   &root,
+///   CDiskHashAlgorithm::Sha256
+/// ).unwrap();
+/// assert_eq!(regenerated, manifest);
+///
+/// codemelted::disk_write_file(
+///   &format!("{}/firmware.bin", root),
+///   CFileContents::String(String::from("tampered")),
+///   false
+/// ).unwrap();
+/// let results = codemelted::disk_hash_verify(
+///   &manifest_file,
+///   CDiskHashAlgorithm::Sha256
+/// ).unwrap();
+/// assert_eq!(results, [
+///   (String::from("firmware.bin"), CDiskHashStatus::Failed),
+///   (String::from("sub/boot.bin"), CDiskHashStatus::Ok),
+/// ]);
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_hash_manifest(
+  src: &str,
+  algorithm: CDiskHashAlgorithm,
+) -> Result<String, std::io::Error> {
+  CDiskOsFileSystem.disk_hash_manifest(src, algorithm)
+}
+
+/// Verifies the files listed in the `sha256sum` format manifest file
+/// (created via [disk_hash_manifest]) against their hashes. Paths are
+/// relative to the directory holding the manifest. Returns each path with
+/// its [CDiskHashStatus] or an [std::io::ErrorKind::InvalidData] for a
+/// malformed manifest.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskHashAlgorithm, CDiskHashStatus, CFileContents};
+///
+/// let root = format!(
+///   "{}/test_disk_hash_verify",
+///   codemelted::runtime_temp_path()
+/// );
+/// let _ = codemelted::disk_rm(&root);
+/// codemelted::disk_mkdir(&root).unwrap();
+/// let filename = format!("{}/image.bin", root);
+/// codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::String(String::from("hello")),
+///   false
+/// ).unwrap();
+/// let manifest = format!("{}/MD5SUMS", root);
+/// codemelted::disk_write_file(
+///   &manifest,
+///   CFileContents::String(String::from(
+///     "5d41402abc4b2a76b9719d911017c592 *image.bin\n\
+///      5d41402abc4b2a76b9719d911017c592  gone.bin\n"
+///   )),
+///   false
+/// ).unwrap();
+///
+/// let results = codemelted::disk_hash_verify(
+///   &manifest,
+///   CDiskHashAlgorithm::Md5
+/// ).unwrap();
+/// assert_eq!(results, [
+///   (String::from("image.bin"), CDiskHashStatus::Ok),
+///   (String::from("gone.bin"), CDiskHashStatus::Missing),
+/// ]);
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_hash_verify(
+  manifest: &str,
+  algorithm: CDiskHashAlgorithm,
+) -> Result<Vec<(String, CDiskHashStatus)>, std::io::Error> {
+  CDiskOsFileSystem.disk_hash_verify(manifest, algorithm)
+}
+
+/// Retrieves the portable [CFileInfo] of the src describing a symbolic
+/// link itself unless follow_links is true.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CFileContents, CFileKind};
+///
+/// let filename = format!("{}/test_disk_info.txt", codemelted::runtime_temp_path());
+/// codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::String(String::from("hello")),
+///   false
+/// ).unwrap();
+///
+/// let info = codemelted::disk_info(&filename, false).unwrap();
+/// assert_eq!(info.kind(), CFileKind::File);
+/// assert_eq!(info.size(), 5);
+/// assert!(info.modified().is_some());
+/// let obj = info.as_object();
+/// assert_eq!(obj["kind"], "file");
+/// assert_eq!(obj["size"], 5);
+/// assert!(obj["modified"].is_string());
+/// let _ = codemelted::disk_rm(&filename);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_info(
+  src: &str,
+  follow_links: bool,
+) -> Result<CFileInfo, std::io::Error> {
+  CDiskOsFileSystem.info(src, follow_links)
+}
+
+/// Takes an advisory lock of the file (created if missing) to coordinate
+/// access to it between processes. The lock is held until the returned
+/// [CDiskLock] is unlocked or dropped. Lock a separate file (i.e.
+/// `{filename}.lock`) to guard files that are replaced via rename or that
+/// carry their own locking like SQLite databases.
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskLockKind, CDiskLockWait};
+///
+/// let filename = format!("{}/test_disk_lock.lock",
+///   codemelted::runtime_temp_path());
+/// let reader1 = codemelted::disk_lock(
+///   &filename, CDiskLockKind::Shared, CDiskLockWait::Block
+/// ).unwrap();
+/// let reader2 = codemelted::disk_lock(
+///   &filename, CDiskLockKind::Shared, CDiskLockWait::Try
+/// ).unwrap();
+/// assert_eq!(reader2.kind(), CDiskLockKind::Shared);
+///
+/// let writer = codemelted::disk_lock(
+///   &filename, CDiskLockKind::Exclusive, CDiskLockWait::Try
+/// );
+/// assert_eq!(writer.unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+/// let writer = codemelted::disk_lock(
+///   &filename, CDiskLockKind::Exclusive, CDiskLockWait::Timeout(20)
+/// );
+/// assert_eq!(writer.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+///
+/// reader1.unlock().unwrap();
+/// drop(reader2);
+/// let writer = codemelted::disk_lock(
+///   &filename, CDiskLockKind::Exclusive, CDiskLockWait::Timeout(20)
+/// ).unwrap();
+/// assert_eq!(writer.path(), filename);
+/// drop(writer);
+/// let _ = codemelted::disk_rm(&filename);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_lock(
+  filename: &str,
+  kind: CDiskLockKind,
+  wait: CDiskLockWait,
+) -> Result<CDiskLock, std::io::Error> {
+  CDiskOsFileSystem.disk_lock(filename, kind, wait)
+}
+
+/// Will list the files / directories in a given location on the host
+/// operating system.
+///
+/// **Example:**
+/// ```
+/// let temp_path = codemelted::runtime_temp_path();
+/// let result = codemelted::disk_ls(&temp_path);
+/// assert!(result.is_ok());
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_ls(src: &str) -> Result<std::fs::ReadDir, std::io::Error> {
+  CDiskOsFileSystem.disk_ls(src)
+}
+
+/// Memory maps the file for read access without reading it into memory.
+/// The file must not be changed while mapped.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let filename = format!(
+///   "{}/test_disk_map_file.bin",
+///   codemelted::runtime_temp_path()
+/// );
+/// codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::Bytes(Vec::from([0x01, 0x02, 0x03, 0x04])),
+///   false
+/// ).unwrap();
+///
+/// let map = codemelted::disk_map_file(&filename).unwrap();
+/// assert_eq!(map.len(), 4);
+/// assert_eq!(map[0], 0x01);
+/// assert_eq!(map.range(2, 10).as_bytes().unwrap(), [0x03, 0x04]);
+/// let _ = codemelted::disk_rm(&filename);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_map_file(filename: &str) -> Result<CFileMap, std::io::Error> {
+  CDiskOsFileSystem.disk_map_file(filename)
+}
+
+/// Retrieves metadata about the specified directory or stored on the
+/// host operating system.
+///
+/// **Example:**
+/// ```
+/// let path = codemelted::runtime_home_path();
+/// let answer = codemelted::disk_metadata(&path);
+/// assert!(answer.is_ok());
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_metadata(
+  src: &str
+) -> Result<std::fs::Metadata, std::io::Error> {
+  CDiskOsFileSystem.disk_metadata(src)
+}
+
+/// Will create a directory and sub-directories in a given location on the
+/// host operating system.
+///
+/// **Example:**
+/// ```
+/// let temp_path = codemelted::runtime_temp_path();
+/// let new_path = format!("{}/1/2/ready/go", temp_path);
+/// let result = codemelted::disk_mkdir(&new_path);
+/// assert!(result.is_ok());
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_mkdir(src: &str) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.create_dir_all(src)
+}
+
+/// Will move a file / directory tree from one location on the host
+/// operating system disk to the other with the default
+/// [CDiskCopyOptions] (see [disk_mv_with]).
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let temp_filename = format!(
+///   "{}/test.txt",
+///   codemelted::runtime_temp_path()
+/// );
+/// let new_filename = format!(
+///   "{}/test_new.txt",
+///   codemelted::runtime_temp_path()
 /// );
-/// assert!(rows == 1);
 ///
-/// // Now lets query the data back
-/// let sql = "SELECT id, name, data FROM person";
-/// let row_data = codemelted::db_query(
-///   &db_file,
-///   sql,
-///   [],
-///   |row| {
-///     Ok(Person {
-///       id: row.get(0)?,
-///       name: row.get(1)?,
-///       data: row.get(2)?,
-///     })
-///   }
+/// let _ = codemelted::disk_write_file(
+///   &temp_filename,
+///   CFileContents::String("Hello".to_string()),
+///   false
 /// );
-/// assert!(row_data.len() == 1);
-/// let _ = codemelted::disk_rm(&db_file);
+/// let result = codemelted::disk_mv(&temp_filename, &new_filename);
+/// assert!(result.is_ok());
 /// ```
-#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
-pub fn db_query<
-    P: rusqlite::Params,
-    T,
-    F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>>(
-      db_path: &str,
-      sql: &str,
-      params: P,
-      f: F
-) -> Vec<T> {
-  // Ensure database exists and we can connect to it.
-  db_exists(db_path, true);
-  match rusqlite::Connection::open(db_path) {
-    Ok(conn) => {
-      // Prepare the SQL statement and binding parameters.
-      match conn.prepare(sql) {
-        Ok(mut stmt) => {
-          // Execute the query based on the params and function binding.
-          match stmt.query_map(params, f) {
-            Ok(rows) => {
-              // Capture the results into from the query to return
-              // to the caller.
-              let mut results = Vec::<T>::new();
-              for row in rows {
-                match row {
-                  Ok(v) => results.push(v),
-                  // Something happened with the bindings
-                  Err(why) => panic!(
-                    "SyntaxError: db_query {}",
-                    why
-                  ),
-                }
-              }
-              results
-            },
-            // Unable to do the query.
-            Err(why) => panic!("SyntaxError: db_query {}", why),
-          }
-        },
-        // Unable to prepare the SQL
-        Err(why) => panic!("SyntaxError: db_query {}", why),
-      }
-    },
-    // Failed to make the database connection.
-    Err(why) => panic!("SyntaxError: db_query {}", why),
-  }
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_mv(src: &str, dest: &str) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_mv(src, dest)
 }
 
-/// Provides the ability Data Manipulation Language (DML) statements
-/// (i.e. INSERT, DELETE, or UPDATE) with the number of rows updated
-/// based on the transaction.
+/// Will move a file / directory tree from one location on the host
+/// operating system disk to the other. A rename is attempted first falling
+/// back to a [disk_cp_with] that removes each src file once copied when the
+/// rename fails (i.e. across filesystems) or the dest must be merged into.
+/// Skipped files are left in the src.
 ///
 /// **Example:**
-/// ```no_run
-/// // Create the database file and table.
-/// let db_file = format!("{}/test.db", codemelted::runtime_temp_path());
-/// assert!(!codemelted::db_exists(&db_file, false));
-/// let sql = "CREATE TABLE person (
-///   id    INTEGER PRIMARY KEY,
-///   name  TEXT NOT NULL,
-///   data  BLOB
-/// )";
-/// codemelted::db_manage(&db_file, true, Some(sql));
-/// assert!(codemelted::db_exists(&db_file, false));
-///
-/// // Go add a person to the database.
-/// #[derive(Debug)]
-/// struct Person {
-///   id: i32,
-///   name: String,
-///   data: Option<Vec<u8>>,
+/// ```
+/// use codemelted::{CDiskConflict, CDiskCopyOptions, CFileContents};
+///
+/// let root = format!("{}/test_disk_mv_with", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&root);
+/// for file in ["src/a.txt", "src/b.txt", "dest/a.txt"] {
+///   let filename = format!("{}/{}", root, file);
+///   let parent = std::path::Path::new(&filename).parent().unwrap();
+///   codemelted::disk_mkdir(&parent.to_string_lossy()).unwrap();
+///   codemelted::disk_write_file(
+///     &filename,
+///     CFileContents::String(file.to_string()),
+///     false
+///   ).unwrap();
 /// }
+/// let src = format!("{}/src", root);
+/// let dest = format!("{}/dest", root);
+///
+/// let options = CDiskCopyOptions::new().conflict(CDiskConflict::Skip);
+/// codemelted::disk_mv_with(&src, &dest, &options).unwrap();
+/// assert!(codemelted::disk_exists(
+///   &format!("{}/a.txt", src), codemelted::CDiskType::File
+/// ));
+/// assert!(!codemelted::disk_exists(
+///   &format!("{}/b.txt", src), codemelted::CDiskType::File
+/// ));
+/// assert!(codemelted::disk_exists(
+///   &format!("{}/b.txt", dest), codemelted::CDiskType::File
+/// ));
+///
+/// let moved = format!("{}/moved", root);
+/// codemelted::disk_mv(&dest, &moved).unwrap();
+/// assert!(!codemelted::disk_exists(&dest, codemelted::CDiskType::Either));
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_mv_with(
+  src: &str,
+  dest: &str,
+  options: &CDiskCopyOptions,
+) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_mv_with(src, dest, options)
+}
+
+/// Opens the file for buffered chunk, line, and range reads via the
+/// returned [CFileReader] supporting files too large to read into memory
+/// with [disk_read_file].
 ///
-/// let sql = "INSERT INTO person (name, data) VALUES (?1, ?2)";
-/// let me = Person {
-///    id: 0,
-///    name: "Steven".to_string(),
-///    data: None,
-/// };
-/// let rows = codemelted::db_update(
-///   &db_file,
-///   sql,
-///   (&me.name, &me.data)
+/// **Example:**
+/// ```
+/// use codemelted::{CFileContents, CFileEncoding};
+///
+/// let filename = format!(
+///   "{}/test_disk_open_reader.txt",
+///   codemelted::runtime_temp_path()
 /// );
-/// assert!(rows == 1);
+/// codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::String(String::from("one\r\ntwo\nthree")),
+///   false
+/// ).unwrap();
+///
+/// let lines = codemelted::disk_open_reader(&filename).unwrap()
+///   .lines(CFileEncoding::Utf8)
+///   .collect::<Result<Vec<String>, std::io::Error>>()
+///   .unwrap();
+/// assert_eq!(lines, ["one", "two", "three"]);
+///
+/// let chunks = codemelted::disk_open_reader(&filename).unwrap()
+///   .chunks(6)
+///   .map(|v| v.unwrap().as_bytes().unwrap().len())
+///   .collect::<Vec<usize>>();
+/// assert_eq!(chunks, [6, 6, 2]);
+/// let mut chunks = codemelted::disk_open_reader(&filename).unwrap().chunks(0);
+/// assert!(chunks.next().unwrap().is_err());
+/// assert!(chunks.next().is_none());
+///
+/// let mut reader = codemelted::disk_open_reader(&filename).unwrap();
+/// assert_eq!(reader.len().unwrap(), 14);
+/// let data = reader.read_range(5, 3).unwrap();
+/// assert_eq!(data.as_bytes().unwrap(), b"two");
+///
+/// // UTF-16 little endian with a byte order mark.
+/// let mut data = vec![0xFF, 0xFE];
+/// for unit in "hé\u{0A41}\nthere".encode_utf16() {
+///   data.extend_from_slice(&unit.to_le_bytes());
+/// }
+/// codemelted::disk_write_file(&filename, CFileContents::Bytes(data), false)
+///   .unwrap();
+/// let lines = codemelted::disk_open_reader(&filename).unwrap()
+///   .lines(CFileEncoding::Auto)
+///   .collect::<Result<Vec<String>, std::io::Error>>()
+///   .unwrap();
+/// assert_eq!(lines, ["hé\u{0A41}", "there"]);
+/// let _ = codemelted::disk_rm(&filename);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_open_reader(filename: &str) -> Result<CFileReader, std::io::Error> {
+  CDiskOsFileSystem.disk_open_reader(filename)
+}
+
+/// Opens the file for buffered writes via the returned [CFileWriter] with
+/// the [CDiskWriteMode].
 ///
-/// // Now lets query the data back
-/// let sql = "SELECT id, name, data FROM person";
-/// let row_data = codemelted::db_query(
-///   &db_file,
-///   sql,
-///   [],
-///   |row| {
-///     Ok(Person {
-///       id: row.get(0)?,
-///       name: row.get(1)?,
-///       data: row.get(2)?,
-///     })
-///   }
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskWriteMode, CFileContents};
+///
+/// let filename = format!(
+///   "{}/test_disk_open_writer.txt",
+///   codemelted::runtime_temp_path()
 /// );
-/// assert!(row_data.len() == 1);
-/// let _ = codemelted::disk_rm(&db_file);
+/// let _ = codemelted::disk_rm(&filename);
+/// let read = || codemelted::disk_read_file(&filename, true)
+///   .unwrap().as_string().unwrap();
+///
+/// let mut writer = codemelted::disk_open_writer(
+///   &filename,
+///   CDiskWriteMode::Truncate
+/// ).unwrap();
+/// for n in 0..3 {
+///   writer.write_line(&format!("line {}", n)).unwrap();
+/// }
+/// writer.finish().unwrap();
+/// assert_eq!(read(), "line 0\nline 1\nline 2\n");
+///
+/// let mut writer = codemelted::disk_open_writer(
+///   &filename,
+///   CDiskWriteMode::Atomic
+/// ).unwrap();
+/// writer.write(CFileContents::String(String::from("discarded"))).unwrap();
+/// drop(writer);
+/// assert_eq!(read(), "line 0\nline 1\nline 2\n");
+///
+/// let mut writer = codemelted::disk_open_writer(
+///   &filename,
+///   CDiskWriteMode::Atomic
+/// ).unwrap();
+/// writer.write(CFileContents::Bytes(b"replaced".to_vec())).unwrap();
+/// writer.finish().unwrap();
+/// assert_eq!(read(), "replaced");
+/// let _ = codemelted::disk_rm(&filename);
 /// ```
-#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
-pub fn db_update<P: rusqlite::Params>(
-  db_path: &str,
-  sql: &str,
-  params: P
-) -> usize {
-  // Ensure database exists and connect to it.
-  db_exists(db_path, true);
-  match rusqlite::Connection::open(db_path) {
-    Ok(conn) => {
-      // Prepare the SQL statement for execution.
-      match conn.prepare(sql) {
-        Ok(mut stmt) => {
-          // Execute the prepared SQL statement and return the #
-          // of rows updated
-          match stmt.execute(params) {
-            Ok(v) => v,
-            // Failed binding of parameters to prepared statement
-            Err(why) => panic!("SyntaxError: db_update {}", why),
-          }
-        },
-        // Failed to prepare the SQL statement
-        Err(why) => panic!("SyntaxError: db_update {}", why),
-      }
-    },
-    // Failed to Connect
-    Err(why) => panic!("SyntaxError: db_update {}", why),
-  }
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_open_writer(
+  filename: &str,
+  mode: CDiskWriteMode,
+) -> Result<CFileWriter, std::io::Error> {
+  CDiskOsFileSystem.disk_open_writer(filename, mode)
 }
 
-/// Determines the setup version of sqlite supported by the module
+/// Takes ownership of the lock / PID file recording the id of this process
+/// in it. Fails per the [CDiskLockWait] while another running process owns
+/// it. A file left behind by a process that exited without releasing it is
+/// detected as stale (its lock went with the process) and taken over (see
+/// [CDiskPidFile::stale_pid]).
 ///
 /// **Example:**
 /// ```
-/// assert!(codemelted::db_version().len() > 0);
+/// use codemelted::CDiskLockWait;
+///
+/// let filename = format!("{}/test_disk_pid_file.pid",
+///   codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&filename);
+/// codemelted::disk_write_file(
+///   &filename,
+///   codemelted::CFileContents::String(String::from("4194305\n")),
+///   false
+/// ).unwrap();
+///
+/// let pid_file = codemelted::disk_pid_file(&filename, CDiskLockWait::Try)
+///   .unwrap();
+/// assert_eq!(pid_file.stale_pid(), Some(4194305));
+/// let data = codemelted::disk_read_file(&filename, true).unwrap();
+/// assert_eq!(data.as_string().unwrap(), format!("{}\n", pid_file.pid()));
+///
+/// let taken = codemelted::disk_pid_file(&filename, CDiskLockWait::Try);
+/// assert_eq!(taken.unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+/// pid_file.release().unwrap();
+/// assert!(!codemelted::disk_exists(&filename, codemelted::CDiskType::File));
 /// ```
-#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
-pub fn db_version() -> String {
-  rusqlite::version().to_string()
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_pid_file(
+  filename: &str,
+  wait: CDiskLockWait,
+) -> Result<CDiskPidFile, std::io::Error> {
+  CDiskOsFileSystem.disk_pid_file(filename, wait)
 }
 
-// ============================================================================
-// [DISK UC IMPLEMENTATION] ===================================================
-// ============================================================================
+/// Reads a binary file from the host operating system.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let data = Vec::<u8>::from([0x01, 0x02]);
+/// let filename = format!("{}/test.bin", codemelted::runtime_temp_path());
+/// let result = codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::Bytes(data.to_owned()),
+///   false
+/// );
+/// assert!(result.is_ok());
+/// let data = codemelted::disk_read_file(
+///   &filename,
+///   false,
+/// );
+/// assert!(data.is_ok());
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_read_file(
+  filename: &str,
+  is_string: bool,
+) -> Result<CFileContents, std::io::Error> {
+  CDiskOsFileSystem.disk_read_file(filename, is_string)
+}
 
-/// Identifies the type of src on the disk when attempting to see if it
-/// [disk_exists] or not.
-pub enum CDiskType {
-  /// Does not matter the type, just does it exist or not.
-  Either,
-  /// Only true if it is a directory.
-  Directory,
-  /// Only true if it is a file.
-  File,
+/// Reads the target path of the symbolic link.
+///
+/// **Example:**
+/// ```
+/// let link = format!("{}/test_disk_read_link", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&link);
+/// codemelted::disk_symlink("target.txt", &link).unwrap();
+/// assert_eq!(codemelted::disk_read_link(&link).unwrap(), "target.txt");
+/// let _ = codemelted::disk_rm(&link);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_read_link(link: &str) -> Result<String, std::io::Error> {
+  CDiskOsFileSystem.disk_read_link(link)
 }
 
-/// Supports the [disk_read_file] and [disk_write_file] functions for
-/// reading / writing file contents from the host operating system disk.
-pub enum CFileContents {
-  /// Read / write files as Bytes.
-  Bytes(Vec<u8>),
-  /// Read / write files as String.
-  String(String),
+/// Will remove a file / directory from one location on the host operating
+/// system disk to the other.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let temp_filename = format!(
+///   "{}/test.txt",
+///   codemelted::runtime_temp_path()
+/// );
+///
+/// let _ = codemelted::disk_write_file(
+///   &temp_filename,
+///   CFileContents::String("Hello".to_string()),
+///   false
+/// );
+/// let result = codemelted::disk_rm(&temp_filename);
+/// assert!(result.is_ok());
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
+pub fn disk_rm(src: &str) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.remove_all(src)
 }
-impl CFileContents {
-  /// Utility method to retrieve the [CFileContents::Bytes] data.
-  pub fn as_bytes(&self) -> Option<Vec<u8>> {
-    match self {
-      CFileContents::Bytes(items) => Some(items.to_owned()),
-      CFileContents::String(_) => None,
-    }
-  }
-  /// Utility method to retrieve the [CFileContents::String] data.
-  pub fn as_string(&self) -> Option<String> {
-    match self {
-      CFileContents::Bytes(_) => None,
-      CFileContents::String(v) => Some(v.to_owned()),
-    }
-  }
+
+/// Sets the last access and / or modified times of the src (None leaves it
+/// unchanged).
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let filename = format!(
+///   "{}/test_disk_set_times.txt",
+///   codemelted::runtime_temp_path()
+/// );
+/// codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::String(String::from("data")),
+///   false
+/// ).unwrap();
+///
+/// let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(86400);
+/// codemelted::disk_set_times(&filename, Some(time), Some(time)).unwrap();
+/// let info = codemelted::disk_info(&filename, true).unwrap();
+/// assert_eq!(info.modified(), Some(time));
+/// assert_eq!(info.as_object()["modified"], "1970-01-02T00:00:00+00:00");
+/// let _ = codemelted::disk_rm(&filename);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_set_times(
+  src: &str,
+  accessed: Option<std::time::SystemTime>,
+  modified: Option<std::time::SystemTime>,
+) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_set_times(src, accessed, modified)
 }
 
-/// Will copy a file / directory from one location on the host operating
-/// system disk to the other.
+/// Creates a symbolic link at the link path pointing to the target (which
+/// need not exist). A relative target is relative to the link's directory.
 ///
 /// **Example:**
 /// ```
-/// use codemelted::CFileContents;
+/// use codemelted::{CFileContents, CFileKind};
 ///
-/// let temp_filename = format!(
-///   "{}/test.txt",
-///   codemelted::runtime_temp_path()
-/// );
-/// let home_filename = format!(
-///   "{}/test.txt",
-///   codemelted::runtime_home_path()
-/// );
+/// let root = format!("{}/test_disk_symlink", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&root);
+/// codemelted::disk_mkdir(&root).unwrap();
+/// codemelted::disk_write_file(
+///   &format!("{}/target.txt", root),
+///   CFileContents::String(String::from("linked")),
+///   false
+/// ).unwrap();
 ///
-/// let _ = codemelted::disk_write_file(
-///   &home_filename,
-///   CFileContents::String("Hello".to_string()),
+/// let link = format!("{}/link.txt", root);
+/// codemelted::disk_symlink("target.txt", &link).unwrap();
+/// let info = codemelted::disk_info(&link, false).unwrap();
+/// assert_eq!(info.kind(), CFileKind::Symlink);
+/// let data = codemelted::disk_read_file(&link, true).unwrap();
+/// assert_eq!(data.as_string().unwrap(), "linked");
+/// let _ = codemelted::disk_rm(&root);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_symlink(target: &str, link: &str) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_symlink(target, link)
+}
+
+/// Creates a uniquely named directory within the [runtime_temp_path] that
+/// is removed along with its contents once the handle is dropped.
+///
+/// **Example:**
+/// ```
+/// use codemelted::CFileContents;
+///
+/// let temp = codemelted::disk_temp_dir().unwrap();
+/// let filename = temp.join("data.txt");
+/// codemelted::disk_write_file(
+///   &filename,
+///   CFileContents::String(String::from("staged")),
 ///   false
-/// );
-/// let result = codemelted::disk_cp(&home_filename, &temp_filename);
-/// assert!(result.is_ok());
+/// ).unwrap();
+/// assert!(codemelted::disk_exists(&filename, codemelted::CDiskType::File));
+///
+/// let path = temp.path();
+/// drop(temp);
+/// assert!(!codemelted::disk_exists(&path, codemelted::CDiskType::Either));
 /// ```
 #[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
-pub fn disk_cp(src: &str, dest: &str) -> Result<(), std::io::Error> {
-  let result = std::fs::copy(src, dest);
-  match result {
-    Ok(_) => Ok(()),
-    Err(why) => Err(why),
-  }
+pub fn disk_temp_dir() -> Result<CDiskTempDir, std::io::Error> {
+  CDiskOsFileSystem.disk_temp_dir()
 }
 
-/// Determines if a directory or file exists on the host operating system
-/// and will further determine if it is of the expected [CDiskType].
+/// Creates a uniquely named directory per the options that is removed along
+/// with its contents once the handle is dropped unless
+/// [CDiskTempDir::persist] is called.
 ///
 /// **Example:**
 /// ```
-/// use codemelted::CDiskType;
+/// use codemelted::CDiskTempOptions;
 ///
-/// let path = codemelted::runtime_home_path();
-/// assert_eq!(codemelted::disk_exists(&path, CDiskType::Either), true);
-/// assert_eq!(codemelted::disk_exists(&path, CDiskType::Directory), true);
-/// assert_eq!(codemelted::disk_exists(&path, CDiskType::File), false);
+/// let options = CDiskTempOptions::new().prefix("stage_").suffix(".d");
+/// let temp = codemelted::disk_temp_dir_with(&options).unwrap();
+/// let name = std::path::Path::new(&temp.path()).file_name().unwrap()
+///   .to_string_lossy().to_string();
+/// assert!(name.starts_with("stage_") && name.ends_with(".d"));
+///
+/// let path = temp.persist();
+/// assert!(codemelted::disk_exists(&path, codemelted::CDiskType::Directory));
+/// let _ = codemelted::disk_rm(&path);
 /// ```
 #[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
-pub fn disk_exists(src: &str, disk_type: CDiskType) -> bool {
-  match disk_type {
-    CDiskType::Either => std::path::Path::new(src).is_dir()
-      || std::path::Path::new(src).is_file(),
-    CDiskType::Directory => std::path::Path::new(src).is_dir(),
-    CDiskType::File => std::path::Path::new(src).is_file(),
-  }
+pub fn disk_temp_dir_with(
+  options: &CDiskTempOptions,
+) -> Result<CDiskTempDir, std::io::Error> {
+  CDiskOsFileSystem.disk_temp_dir_with(options)
 }
 
-/// Will list the files / directories in a given location on the host
-/// operating system.
+/// Creates a uniquely named file within the [runtime_temp_path] opened for
+/// reading and writing that is removed once the handle is dropped.
 ///
 /// **Example:**
 /// ```
-/// let temp_path = codemelted::runtime_temp_path();
-/// let result = codemelted::disk_ls(&temp_path);
-/// assert!(result.is_ok());
+/// use std::io::{Read, Seek, Write};
+///
+/// let mut temp = codemelted::disk_temp_file().unwrap();
+/// temp.write_all(b"scratch").unwrap();
+/// temp.rewind().unwrap();
+/// let mut data = String::new();
+/// temp.read_to_string(&mut data).unwrap();
+/// assert_eq!(data, "scratch");
+///
+/// let path = temp.path();
+/// temp.close().unwrap();
+/// assert!(!codemelted::disk_exists(&path, codemelted::CDiskType::File));
 /// ```
 #[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
-pub fn disk_ls(src: &str) -> Result<std::fs::ReadDir, std::io::Error> {
-  std::fs::read_dir(src)
+pub fn disk_temp_file() -> Result<CDiskTempFile, std::io::Error> {
+  CDiskOsFileSystem.disk_temp_file()
 }
 
-/// Retrieves metadata about the specified directory or stored on the
-/// host operating system.
+/// Creates a uniquely named file per the options opened for reading and
+/// writing that is removed once the handle is dropped unless
+/// [CDiskTempFile::persist] / [CDiskTempFile::persist_as] is called.
 ///
 /// **Example:**
 /// ```
-/// let path = codemelted::runtime_home_path();
-/// let answer = codemelted::disk_metadata(&path);
-/// assert!(answer.is_ok());
+/// use codemelted::CDiskTempOptions;
+/// use std::io::Write;
+///
+/// let dir = codemelted::disk_temp_dir().unwrap();
+/// let options = CDiskTempOptions::new().dir(&dir.path()).suffix(".csv");
+/// let mut temp = codemelted::disk_temp_file_with(&options).unwrap();
+/// assert!(temp.path().ends_with(".csv"));
+/// writeln!(temp, "id,name").unwrap();
+///
+/// // A failed move hands back the file.
+/// let (temp, why) = temp.persist_as(&dir.join("missing/report.csv"))
+///   .unwrap_err();
+/// assert_eq!(why.kind(), std::io::ErrorKind::NotFound);
+/// assert!(codemelted::disk_exists(&temp.path(), codemelted::CDiskType::File));
+/// let filename = temp.persist_as(&dir.join("report.csv")).unwrap();
+/// let data = codemelted::disk_read_file(&filename, true).unwrap();
+/// assert_eq!(data.as_string().unwrap(), "id,name\n");
 /// ```
 #[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
-pub fn disk_metadata(
-  src: &str
-) -> Result<std::fs::Metadata, std::io::Error> {
-  std::path::Path::new(src).metadata()
+pub fn disk_temp_file_with(
+  options: &CDiskTempOptions,
+) -> Result<CDiskTempFile, std::io::Error> {
+  CDiskOsFileSystem.disk_temp_file_with(options)
 }
 
-/// Will create a directory and sub-directories in a given location on the
-/// host operating system.
+/// Calculates the disk usage of the src directory tree (like du) with
+/// the totals of each of its directories and its largest files (0 to skip
+/// them). When parallel is true the directories are read by a worker thread
+/// per CPU which speeds up large trees and network filesystems. Symbolic
+/// links are not followed.
 ///
 /// **Example:**
 /// ```
-/// let temp_path = codemelted::runtime_temp_path();
-/// let new_path = format!("{}/1/2/ready/go", temp_path);
-/// let result = codemelted::disk_mkdir(&new_path);
-/// assert!(result.is_ok());
+/// use codemelted::{CCsvFormat, CFileContents};
+///
+/// let root = format!("{}/test_disk_usage", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&root);
+/// for (file, size) in [("a.bin", 100), ("sub/b.bin", 300),
+///     ("sub/deep/c.bin", 200)] {
+///   let filename = format!("{}/{}", root, file);
+///   let parent = std::path::Path::new(&filename).parent().unwrap();
+///   codemelted::disk_mkdir(&parent.to_string_lossy()).unwrap();
+///   codemelted::disk_write_file(
+///     &filename,
+///     CFileContents::Bytes(vec![0; size]),
+///     false
+///   ).unwrap();
+/// }
+///
+/// for parallel in [false, true] {
+///   let usage = codemelted::disk_usage(&root, 2, parallel).unwrap();
+///   assert_eq!(usage.apparent_size(), 600);
+///   assert!(usage.allocated_size() > 0);
+///   assert_eq!(usage.files(), 3);
+///   assert_eq!(usage.directories(), 2);
+///   assert_eq!(usage.largest().len(), 2);
+///   assert!(usage.largest()[0].0.ends_with("b.bin"));
+///   assert_eq!(usage.breakdown()[1].apparent_size(), 500);
+///   assert_eq!(usage.as_object()["breakdown"][2]["files"], 1);
+///   assert_eq!(usage.csv_header(), "path,apparent_size,allocated_size,files");
+///   assert_eq!(usage.as_csv().lines().count(), 3);
+/// }
+/// let _ = codemelted::disk_rm(&root);
 /// ```
 #[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
-pub fn disk_mkdir(src: &str) -> Result<(), std::io::Error> {
-  std::fs::create_dir_all(src)
+pub fn disk_usage(
+  src: &str,
+  largest: usize,
+  parallel: bool,
+) -> Result<CDiskUsage, std::io::Error> {
+  CDiskOsFileSystem.disk_usage(src, largest, parallel)
 }
 
-/// Will move a file / directory from one location on the host operating
-/// system disk to the other.
+/// Creates a [CDiskWalker] to recursively walk the src directory with glob
+/// include / exclude patterns, max depth, symbolic link policy, hidden file
+/// handling, and gitignore-style ignore files.
 ///
 /// **Example:**
 /// ```
 /// use codemelted::CFileContents;
 ///
-/// let temp_filename = format!(
-///   "{}/test.txt",
-///   codemelted::runtime_temp_path()
+/// let root = format!("{}/test_disk_walk", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&root);
+/// for file in ["a.rs", "b.txt", ".hidden", "sub/c.rs", "sub/deep/d.rs",
+///     "target/e.rs"] {
+///   let filename = format!("{}/{}", root, file);
+///   let parent = std::path::Path::new(&filename).parent().unwrap();
+///   codemelted::disk_mkdir(&parent.to_string_lossy()).unwrap();
+///   codemelted::disk_write_file(
+///     &filename,
+///     CFileContents::String(String::from("data")),
+///     false
+///   ).unwrap();
+/// }
+/// codemelted::disk_write_file(
+///   &format!("{}/.walkignore", root),
+///   CFileContents::String(String::from("target/\n")),
+///   false
+/// ).unwrap();
+///
+/// let names = |walker: codemelted::CDiskWalker| walker.entries()
+///   .map(|v| v.unwrap())
+///   .filter(|v| v.is_file())
+///   .map(|v| v.file_name())
+///   .collect::<Vec<String>>();
+/// assert_eq!(
+///   names(codemelted::disk_walk(&root)),
+///   [".hidden", ".walkignore", "a.rs", "b.txt", "c.rs", "d.rs", "e.rs"]
 /// );
-/// let new_filename = format!(
-///   "{}/test_new.txt",
-///   codemelted::runtime_temp_path()
+/// assert_eq!(
+///   names(codemelted::disk_walk(&root).include("*.rs").exclude("deep")),
+///   ["a.rs", "c.rs", "e.rs"]
 /// );
-///
-/// let _ = codemelted::disk_write_file(
-///   &temp_filename,
-///   CFileContents::String("Hello".to_string()),
-///   false
+/// assert_eq!(
+///   names(codemelted::disk_walk(&root).hidden(false).max_depth(1)),
+///   ["a.rs", "b.txt"]
 /// );
-/// let result = codemelted::disk_mv(&temp_filename, &new_filename);
-/// assert!(result.is_ok());
+/// assert_eq!(
+///   names(codemelted::disk_walk(&root).include("*.rs")
+///     .ignore_file(".walkignore")),
+///   ["a.rs", "c.rs", "d.rs"]
+/// );
+///
+/// let entry = codemelted::disk_walk(&root).include("b.txt").entries()
+///   .next().unwrap().unwrap();
+/// assert_eq!(entry.depth(), 1);
+/// assert_eq!(entry.metadata().len(), 4);
+/// let _ = codemelted::disk_rm(&root);
 /// ```
 #[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
-pub fn disk_mv(src: &str, dest: &str) -> Result<(), std::io::Error> {
-  let result = std::fs::rename(src, dest);
-  match result {
-    Ok(_) => Ok(()),
-    Err(why) => Err(why),
-  }
+pub fn disk_walk(src: &str) -> CDiskWalker {
+  CDiskOsFileSystem.disk_walk(src).expect("the host walks any path")
 }
 
-/// Reads a binary file from the host operating system.
+/// Watches the path for changes with the default [CDiskWatchOptions] (see
+/// [disk_watch_with]).
 ///
 /// **Example:**
 /// ```
-/// use codemelted::CFileContents;
+/// use codemelted::{CDiskWatchKind, CFileContents, CProtocolHandler};
 ///
-/// let data = Vec::<u8>::from([0x01, 0x02]);
-/// let filename = format!("{}/test.bin", codemelted::runtime_temp_path());
-/// let result = codemelted::disk_write_file(
+/// let path = format!("{}/test_disk_watch", codemelted::runtime_temp_path());
+/// let _ = codemelted::disk_rm(&path);
+/// codemelted::disk_mkdir(&path).unwrap();
+/// let mut watcher = codemelted::disk_watch(&path, true).unwrap();
+/// assert!(watcher.is_running());
+///
+/// let filename = format!("{}/config.json", path);
+/// codemelted::disk_write_file(
 ///   &filename,
-///   CFileContents::Bytes(data.to_owned()),
+///   CFileContents::String(String::from("{}")),
 ///   false
-/// );
-/// assert!(result.is_ok());
-/// let data = codemelted::disk_read_file(
-///   &filename,
-///   false,
-/// );
-/// assert!(data.is_ok());
+/// ).unwrap();
+/// let mut event = None;
+/// for _ in 0..100 {
+///   event = watcher.get_message(None).unwrap();
+///   if event.is_some() {
+///     break;
+///   }
+///   codemelted::async_sleep(50);
+/// }
+/// let event = event.unwrap();
+/// assert_eq!(event.kind(), CDiskWatchKind::Create);
+/// assert!(event.path().ends_with("config.json"));
+///
+/// watcher.terminate();
+/// assert!(!watcher.is_running());
+/// let _ = codemelted::disk_rm(&path);
 /// ```
 #[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
-pub fn disk_read_file(
-  filename: &str,
-  is_string: bool,
-) -> Result<CFileContents, std::io::Error> {
-  let file = std::fs::File::open(filename);
-  match file {
-    Ok(_) => {
-      let mut data = Vec::new();
-      let result = std::io::Read::read_to_end(&mut file?, &mut data);
-      match result {
-        Ok(_) => {
-          if !is_string {
-            Ok(CFileContents::Bytes(data.to_owned()))
-          } else {
-            match String::from_utf8(data) {
-              Ok(v) => Ok(CFileContents::String(v)),
-              Err(why) => Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                why.to_string()
-              )),
-            }
-          }
-        },
-        Err(why) => Err(why),
-      }
-    },
-    Err(why) => Err(why),
-  }
+pub fn disk_watch(
+  path: &str,
+  recursive: bool,
+) -> Result<CDiskWatcher, std::io::Error> {
+  CDiskOsFileSystem.disk_watch(path, recursive)
 }
 
-/// Will remove a file / directory from one location on the host operating
-/// system disk to the other.
+/// Watches the path (and its sub-directories when recursive) for changes
+/// received as [CDiskWatchEvent] items from the returned [CDiskWatcher]. The
+/// [CDiskWatchOptions] configure the debouncing and polling fallback.
 ///
 /// **Example:**
 /// ```
-/// use codemelted::CFileContents;
+/// use codemelted::{
+///   CDiskWatchEvent, CDiskWatchKind, CDiskWatchOptions, CDiskWatcher,
+///   CFileContents, CProtocolHandler,
+/// };
 ///
-/// let temp_filename = format!(
-///   "{}/test.txt",
+/// let path = format!(
+///   "{}/test_disk_watch_with",
 ///   codemelted::runtime_temp_path()
 /// );
-///
-/// let _ = codemelted::disk_write_file(
-///   &temp_filename,
-///   CFileContents::String("Hello".to_string()),
+/// let _ = codemelted::disk_rm(&path);
+/// codemelted::disk_mkdir(&path).unwrap();
+/// let old_name = format!("{}/a.txt", path);
+/// let new_name = format!("{}/b.txt", path);
+/// let write = |filename: &str| codemelted::disk_write_file(
+///   filename,
+///   CFileContents::String(String::from("data")),
 ///   false
-/// );
-/// let result = codemelted::disk_rm(&temp_filename);
-/// assert!(result.is_ok());
+/// ).unwrap();
+/// let wait = |watcher: &mut CDiskWatcher, kind: CDiskWatchKind| {
+///   for _ in 0..100 {
+///     while let Some(event) = watcher.get_message(None).unwrap() {
+///       if event.kind() == kind {
+///         return event;
+///       }
+///     }
+///     codemelted::async_sleep(50);
+///   }
+///   panic!("no {:?} event", kind);
+/// };
+///
+/// // Native watcher reporting each change.
+/// let options = CDiskWatchOptions::new().debounce(0);
+/// let mut watcher = codemelted::disk_watch_with(&path, false, &options)
+///   .unwrap();
+/// write(&old_name);
+/// wait(&mut watcher, CDiskWatchKind::Create);
+/// codemelted::disk_mv(&old_name, &new_name).unwrap();
+/// let event = wait(&mut watcher, CDiskWatchKind::Rename);
+/// assert!(event.path().ends_with("a.txt"));
+/// assert!(event.to().unwrap().ends_with("b.txt"));
+/// codemelted::disk_rm(&new_name).unwrap();
+/// wait(&mut watcher, CDiskWatchKind::Delete);
+/// watcher.terminate();
+///
+/// // Polling fallback.
+/// let options = CDiskWatchOptions::new().poll(50);
+/// let mut watcher = codemelted::disk_watch_with(&path, true, &options)
+///   .unwrap();
+/// write(&old_name);
+/// let event = wait(&mut watcher, CDiskWatchKind::Create);
+/// assert!(event.path().ends_with("a.txt"));
+/// watcher.terminate();
+/// let _ = codemelted::disk_rm(&path);
 /// ```
-#[doc = simple_mermaid::mermaid!("models/codemelted_db.mmd")]
-pub fn disk_rm(src: &str) -> Result<(), std::io::Error> {
-  let is_file = disk_exists(src, CDiskType::File);
-  if is_file {
-    let result = std::fs::remove_file(src);
-    match result {
-      Ok(_) => return Ok(()),
-      Err(why) => return Err(why),
-    }
-  } else {
-    let result = std::fs::remove_dir_all(src,);
-    match result {
-      Ok(_) => return Ok(()),
-      Err(why) => return Err(why),
-    }
-  }
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_watch_with(
+  path: &str,
+  recursive: bool,
+  options: &CDiskWatchOptions,
+) -> Result<CDiskWatcher, std::io::Error> {
+  CDiskOsFileSystem.disk_watch_with(path, recursive, options)
 }
 
-/// Writes a binary file to the host operating system.
+/// Writes a binary file to the host operating system replacing its
+/// contents or appending to them (see [disk_write_file_with] for the other
+/// [CDiskWriteMode] options).
 ///
 /// **Example:**
 /// ```
@@ -1101,26 +10503,55 @@ pub fn disk_write_file(
   data: CFileContents,
   append: bool,
 ) -> Result<(), std::io::Error> {
-  let mut file = if append {
-    std::fs::OpenOptions::new()
-      .append(true)
-      .create(true)
-      .open(filename)?
-  } else {
-    std::fs::OpenOptions::new()
-      .write(true)
-      .create(true)
-      .open(filename)?
-  };
-  match data {
-    CFileContents::Bytes(data) => {
-      let _ = std::io::Write::write_all(&mut file, &data)?;
-    },
-    CFileContents::String(data) => {
-      let _ = std::io::Write::write_all(&mut file, data.as_bytes())?;
-    },
-  }
-  Ok(())
+  CDiskOsFileSystem.disk_write_file(filename, data, append)
+}
+
+/// Writes a file to the host operating system via the [CDiskWriteMode]
+/// syncing it to disk before returning. When backup is true an existing
+/// file is first copied to "{filename}.bak".
+///
+/// **Example:**
+/// ```
+/// use codemelted::{CDiskWriteMode, CFileContents};
+///
+/// let filename = format!(
+///   "{}/test_disk_write_file_with.txt",
+///   codemelted::runtime_temp_path()
+/// );
+/// let backup = format!("{}.bak", filename);
+/// let _ = codemelted::disk_rm(&filename);
+/// let _ = codemelted::disk_rm(&backup);
+/// let write = |data: &str, mode: CDiskWriteMode, backup: bool| {
+///   codemelted::disk_write_file_with(
+///     &filename,
+///     CFileContents::String(data.to_string()),
+///     mode,
+///     backup,
+///   )
+/// };
+/// let read = |filename: &str| codemelted::disk_read_file(filename, true)
+///   .unwrap().as_string().unwrap();
+///
+/// write("Hello World", CDiskWriteMode::CreateNew, false).unwrap();
+/// assert!(write("Again", CDiskWriteMode::CreateNew, false).is_err());
+/// write("Hi", CDiskWriteMode::Atomic, true).unwrap();
+/// assert_eq!(read(&filename), "Hi");
+/// assert_eq!(read(&backup), "Hello World");
+/// write(" There", CDiskWriteMode::Append, false).unwrap();
+/// assert_eq!(read(&filename), "Hi There");
+/// write("Bye", CDiskWriteMode::Truncate, false).unwrap();
+/// assert_eq!(read(&filename), "Bye");
+/// let _ = codemelted::disk_rm(&filename);
+/// let _ = codemelted::disk_rm(&backup);
+/// ```
+#[doc = simple_mermaid::mermaid!("models/codemelted_disk.mmd")]
+pub fn disk_write_file_with(
+  filename: &str,
+  data: CFileContents,
+  mode: CDiskWriteMode,
+  backup: bool,
+) -> Result<(), std::io::Error> {
+  CDiskOsFileSystem.disk_write_file_with(filename, data, mode, backup)
 }
 
 // ============================================================================
@@ -4353,28 +13784,80 @@ static STORAGE: std::sync::Mutex<Option<CObject>> = std::sync::Mutex::new(
   None
 );
 
-/// Responsible for saving the storage to a private file on disk anytime
-/// a change is made to the storage.
+/// How long a storage change waits on another process changing the storage
+/// before giving up.
+const STORAGE_LOCK_TIMEOUT: u64 = 10000;
+
+/// Retrieves the private file on disk holding the storage.
 #[doc = simple_mermaid::mermaid!("models/codemelted_storage.mmd")]
-fn storage_save_file(data: &str) {
-  let home_path = runtime_home_path().to_string();
-  let filename = format!("{}/{}",
+fn storage_filename() -> String {
+  let home_path = runtime_home_path();
+  format!("{}/{}",
     home_path,
     ".codemelted_storage"
-  );
-  if disk_exists(&filename, CDiskType::File) {
-    let _ = disk_rm(&filename);
+  )
+}
+
+/// Reads the storage from the private file on disk. A missing file is an
+/// empty storage.
+#[doc = simple_mermaid::mermaid!("models/codemelted_storage.mmd")]
+fn storage_read_file(filename: &str) -> CObject {
+  let data = CDiskOsFileSystem.read(filename).ok()
+    .and_then(|v| String::from_utf8(v).ok());
+  match data {
+    Some(contents) => {
+      if contents.is_empty() {
+        CObject::new_object()
+      } else {
+        match json_parse(&contents) {
+          Some(v) => v,
+          None => {
+            panic!("SyntaxError: .codemelted_storage file is corrupt.")
+          },
+        }
+      }
+    },
+    None => CObject::new_object(),
   }
-  let result = disk_write_file(
+}
+
+/// Responsible for saving the storage to a private file on disk anytime
+/// a change is made to the storage.
+#[doc = simple_mermaid::mermaid!("models/codemelted_storage.mmd")]
+fn storage_save_file(data: &str) {
+  let filename = storage_filename();
+  let result = CDiskOsFileSystem.write(
     &filename,
-    CFileContents::String(data.to_owned()),
-    false
+    data.as_bytes(),
+    CDiskWriteMode::Atomic,
   );
   if result.is_err() {
     panic!("SyntaxError: storage_save_file: {}", result.err().unwrap());
   }
 }
 
+/// Carries out the change to the storage while holding the exclusive lock
+/// of its private file. The storage is first refreshed from disk so changes
+/// made by other processes sharing it are kept.
+#[doc = simple_mermaid::mermaid!("models/codemelted_storage.mmd")]
+fn storage_update(
+  storage_obj: &mut CObject,
+  change: impl FnOnce(&mut CObject),
+) {
+  let filename = storage_filename();
+  let lock = disk_lock(
+    &format!("{}.lock", filename),
+    CDiskLockKind::Exclusive,
+    CDiskLockWait::Timeout(STORAGE_LOCK_TIMEOUT),
+  );
+  if lock.is_err() {
+    panic!("SyntaxError: storage_update: {}", lock.err().unwrap());
+  }
+  *storage_obj = storage_read_file(&filename);
+  change(storage_obj);
+  storage_save_file(&storage_obj.dump());
+}
+
 /// Responsible for initializing the storage for the module.
 /// This must be called first before the module can be used or a panic will
 /// occur. It will read previous storage from disk and bring it into memory
@@ -4388,29 +13871,7 @@ fn storage_save_file(data: &str) {
 pub fn storage_init() {
   let mut storage_mutex = STORAGE.lock().unwrap();
   if storage_mutex.is_none() {
-    let home_path = runtime_home_path();
-    let filename = format!("{}/{}",
-      home_path,
-      ".codemelted_storage"
-    );
-    let data = disk_read_file(&filename, true);
-    let storage_obj = match data {
-      Ok(v) => {
-        let contents = v.as_string().unwrap();
-        if contents.len() == 0 {
-          CObject::new_object()
-        } else {
-          match json_parse(&contents) {
-            Some(v) => v,
-            None => {
-              panic!("SyntaxError: .codemelted_storage file is corrupt.")
-            },
-          }
-        }
-      },
-      Err(_) => CObject::new_object(),
-    };
-    *storage_mutex = Some(storage_obj);
+    *storage_mutex = Some(storage_read_file(&storage_filename()));
   }
 }
 
@@ -4428,8 +13889,7 @@ pub fn storage_clear() {
   if let Some(storage_obj) = std::ops::DerefMut::deref_mut(
     &mut storage_mutex
   ).as_mut() {
-    storage_obj.clear();
-    storage_save_file("");
+    storage_update(storage_obj, |v| v.clear());
   } else {
     panic!("SyntaxError: storage_init() not yet called!");
   }
@@ -4493,15 +13953,17 @@ pub fn storage_remove(key: &str) {
   if let Some(storage_obj) = std::ops::DerefMut::deref_mut(
     &mut storage_mutex
   ).as_mut() {
-    storage_obj.remove(key);
-    let data = storage_obj.dump();
-    storage_save_file(&data);
+    storage_update(storage_obj, |v| {
+      v.remove(key);
+    });
   } else {
     panic!("SyntaxError: storage_init() not yet called!");
   }
 }
 
-/// Sets a key / value in the module storage.
+/// Sets a key / value in the module storage. The change is written while
+/// holding the lock of the storage file (see [disk_lock]) so processes
+/// sharing the storage don't clobber each other's changes.
 ///
 /// **Example:**
 /// ```
@@ -4515,9 +13977,9 @@ pub fn storage_set(key: &str, value: &str) {
   if let Some(storage_obj) = std::ops::DerefMut::deref_mut(
     &mut storage_mutex
   ).as_mut() {
-    let _ = storage_obj.insert(key, value);
-    let data = storage_obj.dump();
-    storage_save_file(&data);
+    storage_update(storage_obj, |v| {
+      let _ = v.insert(key, value);
+    });
   } else {
     panic!("SyntaxError: storage_init() not yet called!");
   }
@@ -4535,12 +13997,218 @@ pub fn storage_set(key: &str, value: &str) {
 
 #[cfg(test)]
 mod tests {
-  // use super::*;
+  use super::*;
 
   #[test]
   fn test_is_nan() {
     assert_eq!(true, f64::is_nan((-1.0 as f64).sqrt()));
   }
+
+  #[test]
+  fn test_repl_parse() {
+    fn cb(_args: &CReplArgs) -> Result<String, String> {
+      Ok(String::new())
+    }
+    let tokens = CRepl::tokenize(
+      r#"send "hello world" --port=COM1 --retries 3 --verbose"#
+    ).unwrap();
+    assert_eq!(tokens[1], "hello world");
+    assert!(CRepl::tokenize("send 'oops").is_err());
+
+    let command = CReplCommand::new("send", "Sends data.", cb)
+      .arg("data", "The data.", true)
+      .option("port", "The port.")
+      .option("retries", "The retries.")
+      .flag("verbose", "Be chatty.");
+    let args = command.parse(&tokens[1..]).unwrap();
+    assert_eq!(args.get("data").unwrap(), "hello world");
+    assert_eq!(args.get("port").unwrap(), "COM1");
+    assert_eq!(args.get("retries").unwrap(), "3");
+    assert!(args.has_flag("verbose"));
+    assert!(command.parse(&[]).is_err());
+    assert!(command.parse(&["a".to_string(), "b".to_string()]).is_err());
+    assert!(command.parse(&["a".to_string(), "--bad".to_string()]).is_err());
+  }
+
+  #[test]
+  fn test_db_infer_value() {
+    use rusqlite::types::Value;
+    assert_eq!(db_infer_value(""), Value::Null);
+    assert_eq!(db_infer_value(" 42 "), Value::Integer(42));
+    assert_eq!(db_infer_value("-7"), Value::Integer(-7));
+    assert_eq!(db_infer_value("0"), Value::Integer(0));
+    assert_eq!(db_infer_value("0.5"), Value::Real(0.5));
+    assert_eq!(db_infer_value("1e3"), Value::Real(1000.0));
+    assert_eq!(db_infer_value("01234"), Value::Text("01234".to_string()));
+    assert_eq!(db_infer_value("NaN"), Value::Text("NaN".to_string()));
+    assert_eq!(db_infer_value("pump"), Value::Text("pump".to_string()));
+  }
+
+  #[test]
+  fn test_disk_copy_validate() {
+    let root = format!("{}/test_disk_copy_validate", runtime_temp_path());
+    let _ = disk_rm(&root);
+    let src = format!("{}/a", root);
+    disk_mkdir(&src).unwrap();
+    disk_write_file(
+      &format!("{}/file.txt", src),
+      CFileContents::String(String::from("data")),
+      false
+    ).unwrap();
+
+    let options = CDiskCopyOptions::new();
+    for dest in [format!("{}/b", src), format!("{}/./b/c", src), src.clone()] {
+      let result = disk_cp_with(&src, &dest, &options);
+      assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+      let result = disk_mv_with(&src, &dest, &options);
+      assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+    assert!(!disk_exists(&format!("{}/b", src), CDiskType::Either));
+    disk_cp_with(&src, &format!("{}/ab", root), &options).unwrap();
+    assert!(disk_exists(&format!("{}/ab/file.txt", root), CDiskType::File));
+    let _ = disk_rm(&root);
+  }
+
+  #[test]
+  fn test_disk_extract_enclosed() {
+    let path = std::path::Path::new;
+    assert!(disk_extract_enclosed("a/b/link", path("../c.txt"), false).is_ok());
+    assert!(
+      disk_extract_enclosed("a/link", path("./b/../../c"), false).is_ok()
+    );
+    assert!(disk_extract_enclosed("a/link", path("../../c"), false).is_err());
+    assert!(disk_extract_enclosed("a/link", path("../c"), true).is_err());
+    assert!(disk_extract_enclosed("link", path("/etc/passwd"), false).is_err());
+  }
+
+  #[test]
+  fn test_disk_fs_normalize() {
+    let path = std::path::PathBuf::from;
+    assert_eq!(disk_fs_normalize(""), path("/"));
+    assert_eq!(disk_fs_normalize("a/./b/"), path("/a/b"));
+    assert_eq!(disk_fs_normalize("/a/../../b"), path("/b"));
+    assert_eq!(disk_fs_normalize("../../etc/passwd"), path("/etc/passwd"));
+  }
+
+  #[test]
+  fn test_disk_overlay_file_system() {
+    let lower = std::sync::Arc::new(CDiskMemoryFileSystem::new());
+    lower.create_dir_all("/a/b").unwrap();
+    lower.write("/a/b/c.txt", b"lower", CDiskWriteMode::Truncate).unwrap();
+    lower.write("/a/d.txt", b"lower", CDiskWriteMode::Truncate).unwrap();
+
+    let overlay = CDiskOverlayFileSystem::new(lower.clone());
+    overlay.write("/a/b/c.txt", b"+", CDiskWriteMode::Append).unwrap();
+    assert_eq!(overlay.read("/a/b/c.txt").unwrap(), b"lower+");
+    overlay.remove_file("/a/d.txt").unwrap();
+    overlay.write("/a/e.txt", b"upper", CDiskWriteMode::CreateNew).unwrap();
+    assert_eq!(overlay.read_dir("/a").unwrap(), ["b", "e.txt"]);
+    overlay.rename("/a/b", "/f").unwrap();
+    assert_eq!(overlay.read_dir("/").unwrap(), ["a", "f"]);
+    assert_eq!(overlay.read("/f/c.txt").unwrap(), b"lower+");
+    assert!(overlay.read("/a/b/c.txt").is_err());
+    overlay.create_dir_all("/a/b").unwrap();
+    assert!(overlay.read_dir("/a/b").unwrap().is_empty());
+    assert!(overlay.create_dir_all("/a/e.txt/g").is_err());
+
+    assert_eq!(lower.read("/a/b/c.txt").unwrap(), b"lower");
+    assert_eq!(lower.read_dir("/a").unwrap(), ["b", "d.txt"]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_disk_extract_zip_chained_links() {
+    let root = format!("{}/test_disk_extract_chained", runtime_temp_path());
+    let _ = disk_rm(&root);
+    let dest = format!("{}/dest", root);
+    disk_mkdir(&dest).unwrap();
+    let archive = format!("{}/chained.zip", root);
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    zip.add_symlink("a", ".", options).unwrap();
+    zip.add_symlink("a/l", "..", options).unwrap();
+    zip.start_file("a/l/pwned.txt", options).unwrap();
+    std::io::Write::write_all(&mut zip, b"pwned").unwrap();
+    zip.finish().unwrap();
+
+    let result = disk_extract(
+      &archive,
+      &dest,
+      CDiskArchiveFormat::Zip,
+      &CDiskArchiveOptions::new(),
+    );
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert!(!disk_exists(&format!("{}/pwned.txt", root), CDiskType::File));
+    let _ = disk_rm(&root);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_disk_extract_tar_chained_links() {
+    let root = format!("{}/test_disk_extract_tar_chained", runtime_temp_path());
+    let _ = disk_rm(&root);
+    let dest = format!("{}/dest", root);
+    let archive = format!("{}/chained.tar", root);
+    disk_mkdir(&root).unwrap();
+
+    // A link through an earlier link and one made to escape by a later one.
+    for links in [
+      [("s2", "."), ("s1", "s2/..")],
+      [("s1", "s2/.."), ("s2", ".")],
+    ] {
+      let file = std::fs::File::create(&archive).unwrap();
+      let mut tar = tar::Builder::new(file);
+      for (name, target) in links {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        tar.append_link(&mut header, name, target).unwrap();
+      }
+      tar.finish().unwrap();
+      drop(tar);
+
+      let result = disk_extract(
+        &archive,
+        &dest,
+        CDiskArchiveFormat::Tar,
+        &CDiskArchiveOptions::new(),
+      );
+      assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+      let link = std::path::Path::new(&dest).join("s1");
+      assert!(std::fs::symlink_metadata(link).is_err());
+      let _ = disk_rm(&dest);
+    }
+    let _ = disk_rm(&root);
+  }
+
+  #[test]
+  fn test_file_reader_lines() {
+    let filename = format!(
+      "{}/test_file_reader_lines.txt",
+      runtime_temp_path()
+    );
+    let text = "\u{0A41}\u{410A}\r\n\nend";
+    for (encoding, mut data) in [
+      (CFileEncoding::Utf16Be, vec![0xFE, 0xFF]),
+      (CFileEncoding::Utf16Le, vec![0xFF, 0xFE]),
+    ] {
+      for unit in text.encode_utf16() {
+        if encoding == CFileEncoding::Utf16Be {
+          data.extend_from_slice(&unit.to_be_bytes());
+        } else {
+          data.extend_from_slice(&unit.to_le_bytes());
+        }
+      }
+      disk_write_file(&filename, CFileContents::Bytes(data), false).unwrap();
+      let mut reader = disk_open_reader(&filename).unwrap();
+      assert_eq!(reader.read_bom(), encoding);
+      let lines = reader.lines(encoding)
+        .collect::<Result<Vec<String>, std::io::Error>>()
+        .unwrap();
+      assert_eq!(lines, ["\u{0A41}\u{410A}", "", "end"]);
+    }
+    let _ = disk_rm(&filename);
+  }
 }
 
 // /// Used to vet logic in the `codemelted.rs` module, build complicated tests,